@@ -5,6 +5,8 @@ pub mod simulation;
 
 use winit::{event_loop::EventLoop, window::WindowBuilder};
 use state::{State, ApplicationEvent};
+use utils::args::Args;
+use simulation::{simulator::Simulator, scene::Scene, export::VtkExporter, stepper::Stepper};
 
 fn main() {
     env_logger::init_from_env(
@@ -12,10 +14,104 @@ fn main() {
             env_logger::DEFAULT_FILTER_ENV, "info, wgpu_hal::vulkan::instance=warn"
         )
     );
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    log::info!("Simulating on the {:?} backend", args.backend);
+
+    let scene = match &args.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(2);
+            }
+        },
+        None => Scene::default(),
+    };
+
+    let exporter = args.export.as_ref().map(|directory| match VtkExporter::new(directory, args.export_every) {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(2);
+        }
+    });
+
+    if args.headless {
+        run_headless(&args, &scene, exporter);
+        return;
+    }
+
     #[allow(deprecated)]
     let event_loop = EventLoop::<ApplicationEvent>::with_user_event().expect("event loop building");
     let window = WindowBuilder::new().build(&event_loop).expect("window building");
     
-    let state = futures::executor::block_on(State::new(&window));
+    let stepper = Stepper::new(args.substeps, scene.time_step.max_delta_time, args.time_scale);
+    let state = futures::executor::block_on(State::new(&window, args.backend, &scene, stepper, exporter));
     state.run(event_loop);
-}
\ No newline at end of file
+}
+
+fn run_headless(args: &Args, scene: &Scene, mut exporter: Option<VtkExporter>) {
+    let mut simulator = futures::executor::block_on(Simulator::new(args.backend, scene, args.software));
+    if let Some(path) = &args.load_snapshot {
+        if let Err(e) = simulator.load_snapshot(path) {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let start = std::time::Instant::now();
+    match exporter.as_mut() {
+        Some(exporter) => {
+            // the state before the first step, so the series starts where the scene does
+            if let Err(e) = exporter.write_frame(&simulator.water_simulation, simulator.steps, simulator.time()) {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+            for _ in 0..args.steps {
+                simulator.step();
+                if exporter.is_due(simulator.steps) {
+                    simulator.sync();
+                    if let Err(e) = exporter.write_frame(&simulator.water_simulation, simulator.steps, simulator.time()) {
+                        log::error!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        None => simulator.advance(args.steps),
+    }
+    let elapsed = start.elapsed();
+    simulator.sync();
+
+    let water_simulation = &simulator.water_simulation;
+    let n = water_simulation.num_particles as usize;
+    let mean_density = water_simulation.densities[..n].iter().map(|d| d.density.x).sum::<f32>() / n as f32;
+    let max_speed = water_simulation.velocities[..n].iter()
+        .map(|v| (v.position.x * v.position.x + v.position.y * v.position.y).sqrt())
+        .fold(0.0f32, f32::max);
+
+    let time_step = simulator.time_step();
+    log::info!("Simulated {} steps of {} particles in {:.2?}", simulator.steps, n, elapsed);
+    log::info!("simulated time {:.3} s, last delta time {:.5} s", time_step.time, time_step.delta_time);
+    log::info!("mean density {:.4}, max speed {:.4}", mean_density, max_speed);
+    log::info!("spatial hash: {}", simulator.hash_stats());
+    if water_simulation.solver.solves_to_tolerance() {
+        log::info!("last step: {}", simulator.solver_stats());
+    }
+    for (i, body) in water_simulation.bodies.bodies.iter().enumerate() {
+        log::info!("body {}: position ({:.3}, {:.3}), angle {:.3}, velocity ({:.3}, {:.3})", i, body.position[0], body.position[1], body.angle, body.velocity[0], body.velocity[1]);
+    }
+
+    if let Some(path) = &args.save_snapshot {
+        match simulator.save_snapshot(path) {
+            Ok(()) => log::info!("Saved snapshot to {}", path.display()),
+            Err(e) => log::error!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform MatrixUniform {
+    mat4 view;
+};
+layout(set = 0, binding = 1) uniform MatrixUniform {
+    mat4 proj;
+};
+
+// mirrors RigidBody in simulation/rigid_body.rs
+struct RigidBody {
+    vec2 position;
+    vec2 velocity;
+    float angle;
+    float angular_velocity;
+    float inverse_mass;
+    float inverse_inertia;
+    uint kind;
+    uint first_point;
+    uint num_points;
+    float radius;
+};
+
+layout(set = 1, binding = 0) buffer BodyBuffer {
+    RigidBody bodies[];
+};
+layout(set = 1, binding = 1) buffer BodyPointBuffer {
+    vec2 body_points[];
+};
+
+const mat4 OPENGL_TO_WGPU_MATRIX = mat4(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.5,
+    0.0, 0.0, 0.0, 1.0
+);
+
+const float PI = 3.14159265359;
+// MAX_BODY_POINTS in rigid_body.rs, line segments per body
+const uint SEGMENTS = 32;
+const uint BODY_CIRCLE = 0;
+
+vec2 rotate(vec2 v, float angle) {
+    float c = cos(angle);
+    float s = sin(angle);
+    return vec2(c * v.x - s * v.y, s * v.x + c * v.y);
+}
+
+void main() {
+    uint body_index = gl_VertexIndex / (2 * SEGMENTS);
+    uint segment = (gl_VertexIndex / 2) % SEGMENTS;
+    uint end = gl_VertexIndex % 2;
+    RigidBody body = bodies[body_index];
+
+    vec2 offset;
+    if (body.kind == BODY_CIRCLE) {
+        // the rim out of all but the last segment, that one is a spoke to show the body turn
+        if (segment == SEGMENTS - 1) {
+            offset = end == 0 ? vec2(0.0) : rotate(vec2(body.radius, 0.0), body.angle);
+        } else {
+            float angle = 2.0 * PI * float(segment + end) / float(SEGMENTS - 1) + body.angle;
+            offset = body.radius * vec2(cos(angle), sin(angle));
+        }
+    } else {
+        // segments past the last corner collapse onto the first one
+        uint corner = segment < body.num_points ? (segment + end) % body.num_points : 0;
+        offset = rotate(body_points[body.first_point + corner], body.angle);
+    }
+    gl_Position = OPENGL_TO_WGPU_MATRIX * proj * view * vec4(body.position + offset, 0.0, 1.0);
+}
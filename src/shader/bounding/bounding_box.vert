@@ -7,9 +7,16 @@ layout(set = 0, binding = 1) uniform MatrixUniform {
     mat4 proj;
 };
 
+// mirrors BoundingBoxLl in simulation/bounding_box.rs
 layout(set = 1, binding = 0) buffer readonly BoundingBoxLl{
-    vec2 box_position;
     vec2 size;
+    vec2 origin;
+    vec2 origin_velocity;
+    uvec2 modes;
+    float angle;
+    float angular_velocity;
+    vec2 box_position;
+    vec2 velocity;
 };
 
 
@@ -25,6 +32,7 @@ layout(location = 0) in vec2 position;
 
 
 void main() {
-    vec2 new_position = position * size / 2.0 + box_position;
+    vec2 corner = position * size / 2.0;
+    vec2 new_position = vec2(cos(angle) * corner.x - sin(angle) * corner.y, sin(angle) * corner.x + cos(angle) * corner.y) + box_position;
     gl_Position = OPENGL_TO_WGPU_MATRIX * proj * view *vec4(new_position, 0.0, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,68 @@
+// smoothing kernels shared by simulation.wgsl and pressure.wgsl, appended to both when the
+// shader modules are built. mirrors Kernel in simulation/kernel.rs: a shape in
+// q = dist / s_rad times the 2d normalization, so each integrates to one over its support
+
+const KERNEL_POLY6: u32 = 0u;
+const KERNEL_SPIKY: u32 = 1u;
+const KERNEL_CUBIC_SPLINE: u32 = 2u;
+const KERNEL_WENDLAND_C2: u32 = 3u;
+const KERNEL_WENDLAND_C4: u32 = 4u;
+const KERNEL_PI: f32 = 3.14159265359;
+
+fn smoothing_kernel(kernel: u32, s_rad: f32, dist: f32) -> f32 {
+    if (dist > s_rad) { return 0.0; }
+
+    return kernel_normalization(kernel, s_rad) * kernel_shape(kernel, dist / s_rad);
+}
+
+fn smoothing_kernel_derivative(kernel: u32, s_rad: f32, dist: f32) -> f32 {
+    if (dist > s_rad) { return 0.0; }
+
+    return kernel_normalization(kernel, s_rad) * kernel_shape_derivative(kernel, dist / s_rad) / s_rad;
+}
+
+// unknown kernels fall back to the spiky one
+fn kernel_normalization(kernel: u32, s_rad: f32) -> f32 {
+    let area = KERNEL_PI * s_rad * s_rad;
+    switch (kernel) {
+        case KERNEL_POLY6: { return 4.0 / area; }
+        case KERNEL_CUBIC_SPLINE: { return 40.0 / (7.0 * area); }
+        case KERNEL_WENDLAND_C2: { return 7.0 / area; }
+        case KERNEL_WENDLAND_C4: { return 9.0 / area; }
+        case KERNEL_SPIKY, default: { return 6.0 / area; }
+    }
+}
+
+fn kernel_shape(kernel: u32, q: f32) -> f32 {
+    let r = 1.0 - q;
+    switch (kernel) {
+        case KERNEL_POLY6: {
+            let v = 1.0 - q * q;
+            return v * v * v;
+        }
+        case KERNEL_CUBIC_SPLINE: {
+            if (q <= 0.5) { return 6.0 * (q * q * q - q * q) + 1.0; }
+            return 2.0 * r * r * r;
+        }
+        case KERNEL_WENDLAND_C2: { return r * r * r * r * (1.0 + 4.0 * q); }
+        case KERNEL_WENDLAND_C4: { return r * r * r * r * r * r * (1.0 + 6.0 * q + 35.0 / 3.0 * q * q); }
+        case KERNEL_SPIKY, default: { return r * r; }
+    }
+}
+
+fn kernel_shape_derivative(kernel: u32, q: f32) -> f32 {
+    let r = 1.0 - q;
+    switch (kernel) {
+        case KERNEL_POLY6: {
+            let v = 1.0 - q * q;
+            return -6.0 * q * v * v;
+        }
+        case KERNEL_CUBIC_SPLINE: {
+            if (q <= 0.5) { return 18.0 * q * q - 12.0 * q; }
+            return -6.0 * r * r;
+        }
+        case KERNEL_WENDLAND_C2: { return -20.0 * q * r * r * r; }
+        case KERNEL_WENDLAND_C4: { return -56.0 / 3.0 * q * (1.0 + 5.0 * q) * r * r * r * r * r; }
+        case KERNEL_SPIKY, default: { return -2.0 * r; }
+    }
+}
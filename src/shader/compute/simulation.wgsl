@@ -1,6 +1,6 @@
 // This is a simple 2D SPH simulation shader.
 
-@compute @workgroup_size(16, 16, 1)
+@compute @workgroup_size(16, 1, 1)
 fn predict_position(@builtin(global_invocation_id) global_id: vec3<u32>) {
     var index = global_id.x;
     if (index >= num_particles) {
@@ -16,90 +16,181 @@ fn predict_position(@builtin(global_invocation_id) global_id: vec3<u32>) {
 
 
 
-@compute @workgroup_size(16, 16, 1)
+// the spatial hash is binned with a counting sort, a fixed number of passes for any particle count:
+// clear_cell_counts -> update_spatial_hash (histogram) -> scan_cell_counts -> scatter_spatial_hash
+@compute @workgroup_size(16, 1, 1)
+fn clear_cell_counts(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    var index = global_id.x;
+    if (index >= table_size) {
+        return;
+    }
+    atomicStore(&cell_counts[index], 0u);
+}
+
+@compute @workgroup_size(16, 1, 1)
 fn update_spatial_hash(@builtin(global_invocation_id) global_id: vec3<u32>) {
     var index = global_id.x;
     if (index >= num_particles) {
         return;
     }
-    var half_boundries = calculateBoundries();
-    var pos = predicted_p_position[index].position;
-    var norm_pos = get_shifted_2D_pos(pos.xy, half_boundries);
-    var hash_key = hash_position(norm_pos);
+    atomicAdd(&cell_counts[particle_cell_key(index)], 1u);
+}
 
-    spatial_hash[index].cell_key = hash_key;
-    spatial_hash[index].particle_index = index;
+// single workgroup, every thread scans its own chunk of bins and the chunk totals are
+// combined in workgroup memory. writes start_indices on the way so no extra pass is needed
+@compute @workgroup_size(256, 1, 1)
+fn scan_cell_counts(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    let bins = table_size;
+    let chunk = (bins + SCAN_THREADS - 1u) / SCAN_THREADS;
+    let begin = min(local_id.x * chunk, bins);
+    let end = min(begin + chunk, bins);
+
+    var total = 0u;
+    for (var i = begin; i < end; i++) {
+        total += atomicLoad(&cell_counts[i]);
+    }
+    scan_totals[local_id.x] = total;
     workgroupBarrier();
-}    
 
-// black magic fr, this alternative non-recursive method of bitonic sort is a bit more complex
-//https://en.m.wikipedia.org/wiki/Bitonic_sorter
-@compute @workgroup_size(16, 16, 1)
-fn bitonic_sort_kernel(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    var index = global_id.x;
-    var l = index ^ c.j;
-
-    if (index >= c.next_pwr) {
-        return;
+    // hillis steele inclusive scan over the chunk totals
+    for (var offset = 1u; offset < SCAN_THREADS; offset *= 2u) {
+        var value = scan_totals[local_id.x];
+        if (local_id.x >= offset) {
+            value += scan_totals[local_id.x - offset];
+        }
+        workgroupBarrier();
+        scan_totals[local_id.x] = value;
+        workgroupBarrier();
     }
 
-    if (l > index) {
-        var a = spatial_hash[index];
-        var b = spatial_hash[l];
-        if ((index & c.k) == 0) {
-            if (a.cell_key > b.cell_key) {
-                spatial_hash[index] = b;
-                spatial_hash[l] = a;
-            }
+    var running = scan_totals[local_id.x] - total;
+    for (var i = begin; i < end; i++) {
+        let count = atomicLoad(&cell_counts[i]);
+        atomicStore(&cell_offsets[i], running);
+        if (count > 0u) {
+            start_indices[i] = running;
         } else {
-            if (a.cell_key < b.cell_key) {
-                spatial_hash[index] = b;
-                spatial_hash[l] = a;
-            }
+            start_indices[i] = max_particles;
         }
+        running += count;
     }
-    
-
-    workgroupBarrier(); // Ensure all threads have completed their operations before next iteration
 }
 
-@compute @workgroup_size(16, 16, 1)
-fn reset_indecies(@builtin(global_invocation_id) global_id: vec3<u32>) {
+@compute @workgroup_size(16, 1, 1)
+fn scatter_spatial_hash(@builtin(global_invocation_id) global_id: vec3<u32>) {
     var index = global_id.x;
-    if (index >= max_particles) {
+    if (index >= num_particles) {
         return;
     }
-    start_indices[index] = max_particles;
+    let hash_key = particle_cell_key(index);
+    let slot = atomicAdd(&cell_offsets[hash_key], 1u);
+    spatial_hash[slot].cell_key = hash_key;
+    spatial_hash[slot].particle_index = index;
 }
 
-@compute @workgroup_size(16, 16, 1)
-fn calculate_start_indices(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    var index = global_id.x;
+@compute @workgroup_size(16, 1, 1)
+fn calculate_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
     if (index >= num_particles) {
         return;
     }
 
-    var key = spatial_hash[index].cell_key;
-    if (index == 0) {
-        start_indices[key] = 0u;
-    } else if key != spatial_hash[index - 1].cell_key {
-        start_indices[key] = index;
-    }
-
+    var density = fast_update_density(index);
+    p_density[index].density = density;
 }
 
-@compute @workgroup_size(16, 16, 1)
-fn calculate_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+// Boundary particles, Akinci et al. 2012. the walls are sampled with fixed particles whose psi
+// stands in for their mass, and each one takes the pressure of the fluid particle it is seen
+// from. so the solvers only need the psi weighted sums, collected once before the density and
+// again in every pbf iteration since those move the predicted positions. the outlines of the
+// rigid bodies are sampled the same way and add to the same sums
+@compute @workgroup_size(16, 1, 1)
+fn calculate_boundary(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
     if (index >= num_particles) {
         return;
     }
 
-    var density = fast_update_density(index);
-    p_density[index].density = density;
+    boundary_data[index] = fast_calculate_boundary(index);
+}
+
+// sum_b psi_b W_ib and sum_b psi_b grad W_ib for both kernels, all zero without boundary particles
+fn fast_calculate_boundary(index: u32) -> BoundaryData {
+    var data = BoundaryData(vec3f(0.0, 0.0, 0.0), 0.0, vec3f(0.0, 0.0, 0.0), 0.0, 0.0);
+    // the boundary particles sit in the frame of the container, so the lookup happens there
+    // and the offsets are turned back into world space
+    var particle_position = vec3f(container_local(predicted_p_position[index].position.xy), 0.0);
+    var norm_particle_position = grid_cell(particle_position.xy);
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = boundary_start_indices[hash_key];
+
+        while (curr_index < num_boundary_particles && boundary_particles[curr_index].cell_key == hash_key) {
+            var boundary_particle = boundary_particles[curr_index];
+            var offset_to_neighbour = vec3f(nearest_local_image(boundary_particle.position - particle_position.xy), 0.0);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+            offset_to_neighbour = vec3f(rotate(offset_to_neighbour.xy, boundry_box.angle), 0.0);
+
+            var dist = sqrt(sqr_dst_to_neighbour);
+            var psi = boundary_particle.psi;
+            data.density += psi * smoothing_kernel(params.kernel, params.smoothing_radius, dist);
+            data.near_density += psi * smoothing_kernel_spikey_near(params.smoothing_radius, dist);
+            data.gradient += psi * kernel_gradient(index, offset_to_neighbour, dist);
+            data.near_gradient += psi * near_kernel_gradient(index, offset_to_neighbour, dist);
+
+            curr_index += 1u;
+        }
+    }
+
+    // the bodies are few enough to go through body by body, same as Bodies::for_each_sample
+    let position = predicted_p_position[index].position.xy;
+    for (var b = 0u; b < num_bodies; b++) {
+        let body = bodies[b];
+        let offset_to_body = nearest_image(vec3f(body.position - position, 0.0)).xy;
+        let reach = body.radius + params.smoothing_radius;
+        if (dot(offset_to_body, offset_to_body) > reach * reach) {
+            continue;
+        }
+        let range = body_sample_ranges[b];
+        for (var k = range.x; k < range.x + range.y; k++) {
+            let sample = body_samples[k];
+            let arm = rotate(sample.position, body.angle);
+            let offset = vec3f(offset_to_body + arm, 0.0);
+            let sqr_dst = dot(offset, offset);
+            if (sqr_dst > sqrRadius) {
+                continue;
+            }
+
+            let dist = sqrt(sqr_dst);
+            let gradient = sample.psi * kernel_gradient(index, offset, dist);
+            let surface_velocity = body.velocity + perpendicular(arm) * body.angular_velocity;
+            data.density += sample.psi * smoothing_kernel(params.kernel, params.smoothing_radius, dist);
+            data.near_density += sample.psi * smoothing_kernel_spikey_near(params.smoothing_radius, dist);
+            data.gradient += gradient;
+            data.near_gradient += sample.psi * near_kernel_gradient(index, offset, dist);
+            data.flux += dot(surface_velocity, gradient.xy);
+        }
+    }
+    return data;
 }
 
-@compute @workgroup_size(16, 16, 1)
+// the velocity change waits in solver_data[i].c for apply_viscosity, so every particle sees the
+// velocities from before the pass like the cpu solver does. no solver holds anything in c
+// across the viscosity pass. the xsph correction rides along, it needs the same snapshot
+@compute @workgroup_size(16, 1, 1)
 fn calculate_viscosity(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
     if (index >= num_particles) {
@@ -107,86 +198,1443 @@ fn calculate_viscosity(@builtin(global_invocation_id) global_id: vec3<u32>) {
     }
 
     var viscosity_force = fast_calculate_viscosity_force(index);
-    p_velocity[index].velocity += viscosity_force * VISCOSITY_STRENGTH * delta_time;
+    var velocity_change = viscosity_force * params.viscosity_strength * time_step.delta_time;
+    if (params.xsph != 0.0) {
+        velocity_change += fast_calculate_xsph_correction(index) * params.xsph;
+    }
+    solver_data[index].c = vec4f(velocity_change, 0.0);
 }
 
-
-@compute @workgroup_size(16, 16, 1)
-fn update_position(@builtin(global_invocation_id) global_id: vec3<u32>) {
+@compute @workgroup_size(16, 1, 1)
+fn apply_viscosity(@builtin(global_invocation_id) global_id: vec3<u32>) {
     let index = global_id.x;
     if (index >= num_particles) {
         return;
     }
 
-    var pos = p_position[index].position;
-    var vel = p_velocity[index].velocity;
-    let half_boundries = calculateBoundries();
+    p_velocity[index].velocity += solver_data[index].c.xyz;
+}
+
+// Surface tension, Akinci et al. 2013. a cohesion force pulls neighbours together and a
+// curvature force from the difference of the surface normals straightens the surface.
+// solver_data[i].c holds the normal between the two passes, both run after the viscosity
+@compute @workgroup_size(16, 1, 1)
+fn calculate_surface_normal(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || params.surface_tension == 0.0) {
+        return;
+    }
+
+    solver_data[index].c = vec4f(fast_calculate_surface_normal(index), 0.0);
+}
+
+// only writes the own velocity and reads positions, densities and normals, so it can apply
+// the force in place
+@compute @workgroup_size(16, 1, 1)
+fn apply_surface_tension(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || params.surface_tension == 0.0) {
+        return;
+    }
+
+    p_velocity[index].velocity += fast_calculate_surface_tension_force(index) * time_step.delta_time;
+}
+
+// n_i = h * sum_j m_j / rho_j * grad W_ij, long at the surface and close to zero inside
+fn fast_calculate_surface_normal(index: u32) -> vec3f {
+    var normal = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dist = sqrt(sqr_dst_to_neighbour);
+            // the density floor of calculate_density, pbf and dfsph store theirs without one
+            var neighbour_density = max(p_density[neighbour_index].density.x, 0.1);
+            normal += MASS / neighbour_density * kernel_gradient(index, offset_to_neighbour, dist);
+
+            curr_index += 1u;
+        }
+    }
+    return normal * params.smoothing_radius;
+}
+
+// sum_j K_ij (cohesion + curvature), K_ij = 2 rho_0 / (rho_i + rho_j) makes the force stronger
+// where a particle has too few neighbours. the mass is 1, so force and acceleration agree
+fn fast_calculate_surface_tension_force(index: u32) -> vec3f {
+    var force = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+    let density = max(p_density[index].density.x, 0.1);
+    let normal = solver_data[index].c.xyz;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dist = sqrt(sqr_dst_to_neighbour);
+            var cohesion = vec3f(0.0, 0.0, 0.0);
+            if (dist > 1e-6) {
+                cohesion = MASS * MASS * cohesion_kernel(params.smoothing_radius, dist) * offset_to_neighbour / dist;
+            }
+            var curvature = CURVATURE_WEIGHT * MASS * (solver_data[neighbour_index].c.xyz - normal);
+            var correction = 2.0 * params.target_density / (density + max(p_density[neighbour_index].density.x, 0.1));
+            force += correction * (cohesion + curvature);
+
+            curr_index += 1u;
+        }
+    }
+    return force * params.surface_tension;
+}
+
+// Vorticity confinement, Fedkiw et al. 2001 as used by Macklin & Müller 2013. puts back the
+// swirls the viscosity and the smoothing damp out. solver_data[i].c holds the vorticity between
+// the two passes, they run after the surface tension is done with it
+@compute @workgroup_size(16, 1, 1)
+fn calculate_vorticity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || params.vorticity_confinement == 0.0) {
+        return;
+    }
+
+    solver_data[index].c = vec4f(fast_calculate_vorticity(index), 0.0);
+}
+
+// only writes the own velocity and reads the vorticities, so it can apply the force in place
+@compute @workgroup_size(16, 1, 1)
+fn apply_vorticity_confinement(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || params.vorticity_confinement == 0.0) {
+        return;
+    }
+
+    p_velocity[index].velocity += fast_calculate_vorticity_confinement_force(index) * time_step.delta_time;
+}
+
+// w_i = sum_j m_j / rho_j * grad W_ij x (v_j - v_i), only the z component is non-zero in 2d
+fn fast_calculate_vorticity(index: u32) -> vec3f {
+    var vorticity = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dist = sqrt(sqr_dst_to_neighbour);
+            var neighbour_density = max(p_density[neighbour_index].density.x, 0.1);
+            var vel_diff = p_velocity[neighbour_index].velocity - p_velocity[index].velocity;
+            vorticity += MASS / neighbour_density * cross(kernel_gradient(index, offset_to_neighbour, dist), vel_diff);
+
+            curr_index += 1u;
+        }
+    }
+    return vorticity;
+}
+
+// N x w with N the direction towards more vorticity, eta = grad |w|. spins a vortex up
+// around its own centre instead of adding any net motion
+fn fast_calculate_vorticity_confinement_force(index: u32) -> vec3f {
+    var eta = vec3f(0.0, 0.0, 0.0);
+    let vorticity = solver_data[index].c.xyz;
+    let magnitude = length(vorticity);
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dist = sqrt(sqr_dst_to_neighbour);
+            var neighbour_density = max(p_density[neighbour_index].density.x, 0.1);
+            var magnitude_diff = length(solver_data[neighbour_index].c.xyz) - magnitude;
+            eta += MASS / neighbour_density * magnitude_diff * kernel_gradient(index, offset_to_neighbour, dist);
+
+            curr_index += 1u;
+        }
+    }
+
+    if (length(eta) < 1e-6) {
+        return vec3f(0.0, 0.0, 0.0);
+    }
+    return cross(normalize(eta), vorticity) * params.vorticity_confinement;
+}
+
+
+// no early return here, every invocation has to reach the barriers of the max reduction
+@compute @workgroup_size(16, 1, 1)
+fn update_position(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var speed = 0.0;
+    var acceleration = 0.0;
+
+    if (index < num_particles) {
+        let maxima = integrate_position(index, fast_calculate_pressure_force(index));
+        speed = maxima.x;
+        acceleration = maxima.y;
+
+        // the boundary part of the pressure force, the near pressure on its own near gradient
+        let density = p_density[index].density;
+        let pressure = max(convert_density_to_pressure(density.x), 0.0) / (density.x * density.x);
+        let near_pressure = convert_near_density_to_pressure(density.y) / (density.y * density.x);
+        push_bodies(index, vec2f(pressure, near_pressure) * time_step.delta_time);
+    }
+
+    reduce_time_step_maxima(local_id.x, speed, acceleration);
+}
+
+// applies the pressure acceleration and moves the particle, returns its speed and acceleration
+fn integrate_position(index: u32, pressure_force: vec3f) -> vec2f {
+    var pos = p_position[index].position;
+    var vel = p_velocity[index].velocity;
+    let half_boundries = calculateBoundries();
+    let delta_time = time_step.delta_time;
+
+    vel += pressure_force * delta_time;
+    pos += vel * delta_time;
+
+    checkBoundaries(&pos, &vel, half_boundries);
+
+    p_position[index].position = pos;
+    p_velocity[index].velocity = vel;
+
+    if (has_escaped(pos.xy)) {
+        return vec2f(0.0, 0.0);
+    }
+    return vec2f(length(vel), length(pressure_force + vec3<f32>(0.0, -params.gravity, 0.0)));
+}
+
+// workgroup max of speed and acceleration folded into time_step for the next step's delta time.
+// has to be reached by every invocation of the workgroup
+fn reduce_time_step_maxima(local_index: u32, speed: f32, acceleration: f32) {
+    max_speeds[local_index] = speed;
+    max_accelerations[local_index] = acceleration;
+    workgroupBarrier();
+    for (var stride = 8u; stride > 0u; stride /= 2u) {
+        if (local_index < stride) {
+            max_speeds[local_index] = max(max_speeds[local_index], max_speeds[local_index + stride]);
+            max_accelerations[local_index] = max(max_accelerations[local_index], max_accelerations[local_index + stride]);
+        }
+        workgroupBarrier();
+    }
+    if (local_index == 0u) {
+        atomicMax(&time_step.max_speed, bitcast<u32>(max_speeds[0]));
+        atomicMax(&time_step.max_acceleration, bitcast<u32>(max_accelerations[0]));
+    }
+}
+
+// single invocation at the start of a step, CFL and force condition on last step's maxima.
+// same as TimeStepParams::delta_time on the cpu
+@compute @workgroup_size(1, 1, 1)
+fn update_time_step() {
+    let max_speed = bitcast<f32>(atomicExchange(&time_step.max_speed, 0u));
+    let max_acceleration = bitcast<f32>(atomicExchange(&time_step.max_acceleration, 0u));
+    let h = params.smoothing_radius;
+
+    var delta_time = time_step_params.max_delta_time;
+    if (max_speed > 0.0) {
+        delta_time = min(delta_time, time_step_params.cfl_factor * h / max_speed);
+    }
+    if (max_acceleration > 0.0) {
+        delta_time = min(delta_time, time_step_params.force_factor * sqrt(h / max_acceleration));
+    }
+    delta_time = clamp(delta_time, time_step_params.min_delta_time, max(time_step_params.max_delta_time, time_step_params.min_delta_time));
+
+    time_step.delta_time = delta_time;
+    time_step.time += delta_time;
+    update_container(time_step.time);
+}
+
+// where the scripted motion has taken the container by `time`, and how fast its walls move.
+// same as ContainerMotion::apply on the cpu
+fn update_container(time: f32) {
+    let omega = 2.0 * PI * container_motion.frequency;
+    let swing = sin(omega * time);
+    let swing_rate = omega * cos(omega * time);
+    boundry_box.boundry_box_center = boundry_box.origin + container_motion.velocity * time + container_motion.amplitude * swing;
+    boundry_box.velocity = boundry_box.origin_velocity + container_motion.velocity + container_motion.amplitude * swing_rate;
+    boundry_box.angle = radians(container_motion.angular_velocity * time + container_motion.angle_amplitude * swing);
+    boundry_box.angular_velocity = radians(container_motion.angular_velocity + container_motion.angle_amplitude * swing_rate);
+}
+
+
+// Position based fluids, Macklin & Müller 2013. solver_data[i].a.x holds lambda and
+// solver_data[i].b.xy the position correction of the current iteration
+
+@compute @workgroup_size(16, 1, 1)
+fn pbf_predict_position(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+    var pos = p_position[index].position;
+    var vel = p_velocity[index].velocity;
+    external_forces(&pos, &vel);
+
+    var predicted = pos + vel * time_step.delta_time;
+    clamp_to_boundaries(&predicted);
+    predicted_p_position[index].position = predicted;
+    p_velocity[index].velocity = vel;
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn pbf_calculate_lambda(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+
+    var density = 0.0;
+    var grad_i = vec3f(0.0, 0.0, 0.0);
+    var sum_grad_sqr = 0.0;
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            density += smoothing_kernel(params.kernel, params.smoothing_radius, dst);
+
+            // gradient of the constraint with respect to the neighbour
+            var grad_j = kernel_gradient(index, offset_to_neighbour, dst) / params.target_density;
+            grad_i += grad_j;
+            sum_grad_sqr += dot(grad_j, grad_j);
+
+            curr_index += 1u;
+        }
+    }
+    // the boundary particles don't move, they only add to the density and the own gradient
+    density += self_density() + boundary_data[index].density;
+    grad_i += boundary_data[index].gradient / params.target_density;
+    sum_grad_sqr += dot(grad_i, grad_i);
+
+    // only ever push apart, a free surface would otherwise clump to reach the rest density
+    var constraint = max(density / params.target_density - 1.0, 0.0);
+    solver_data[index].a.x = -constraint / (sum_grad_sqr + solver_params.relaxation);
+    p_density[index].density = vec2f(density, 0.0);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn pbf_calculate_delta(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+
+    var delta = vec3f(0.0, 0.0, 0.0);
+    var lambda = solver_data[index].a.x;
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+    var tensile_reference = smoothing_kernel(params.kernel, params.smoothing_radius, solver_params.tensile_dq * params.smoothing_radius);
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var tensile_correction = -solver_params.tensile_k * pow(smoothing_kernel(params.kernel, params.smoothing_radius, dst) / tensile_reference, solver_params.tensile_n);
+            var neighbour_lambda = solver_data[neighbour_index].a.x;
+            delta += (lambda + neighbour_lambda + tensile_correction) * kernel_gradient(index, offset_to_neighbour, dst);
+
+            curr_index += 1u;
+        }
+    }
+    delta += lambda * boundary_data[index].gradient;
+    // the boundary part of the correction, as the momentum it gives the particle
+    push_bodies(index, vec2f(-lambda / (params.target_density * time_step.delta_time), 0.0));
+    solver_data[index].b = vec4f(delta / params.target_density, 0.0);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn pbf_apply_delta(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+    var predicted = predicted_p_position[index].position + solver_data[index].b.xyz;
+    clamp_to_boundaries(&predicted);
+    predicted_p_position[index].position = predicted;
+}
+
+// no early return, every invocation has to reach the barriers of the max reduction
+@compute @workgroup_size(16, 1, 1)
+fn pbf_update_velocity(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var speed = 0.0;
+    var acceleration = 0.0;
+
+    if (index < num_particles) {
+        let delta_time = time_step.delta_time;
+        let predicted = predicted_p_position[index].position;
+        let vel = (predicted - p_position[index].position) / delta_time;
+
+        // the velocity before the projection already had gravity in it
+        acceleration = length((vel - p_velocity[index].velocity) / delta_time + vec3<f32>(0.0, -params.gravity, 0.0));
+        speed = length(vel);
+
+        p_position[index].position = vec3f(wrap_periodic(predicted.xy), predicted.z);
+        p_velocity[index].velocity = vel;
+        if (has_escaped(predicted.xy)) {
+            speed = 0.0;
+            acceleration = 0.0;
+        }
+    }
+
+    reduce_time_step_maxima(local_id.x, speed, acceleration);
+}
+
+
+// Divergence-free SPH, Bender & Koschier 2015. solver_data[i].a.x holds the factor
+// 1 / (|sum grad W|^2 + sum |grad W|^2), a.y kappa / density of the current iteration and
+// b.xyz the velocity at the start of the step. the neighbourhoods stay fixed for the whole step
+
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_begin_step(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index == 0u) {
+        reset_solver_stats();
+    }
+    if (index >= num_particles) {
+        return;
+    }
+    predicted_p_position[index].position = p_position[index].position;
+    solver_data[index].b = vec4f(p_velocity[index].velocity, 0.0);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_compute_factors(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle_index = global_id.x;
+    if (particle_index >= num_particles) {
+        return;
+    }
+
+    var density = 0.0;
+    var grad_i = vec3f(0.0, 0.0, 0.0);
+    var sum_grad_sqr = 0.0;
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            density += MASS * smoothing_kernel(params.kernel, params.smoothing_radius, dst);
+            var grad_j = MASS * kernel_gradient(particle_index, offset_to_neighbour, dst);
+            grad_i += grad_j;
+            sum_grad_sqr += dot(grad_j, grad_j);
+
+            curr_index += 1u;
+        }
+    }
+
+    // the own term has no gradient, it only adds to the density
+    density += self_density() + boundary_data[particle_index].density;
+    grad_i += boundary_data[particle_index].gradient;
+
+    // a particle without neighbours has nothing to push against
+    var denominator = dot(grad_i, grad_i) + sum_grad_sqr;
+    var factor = 0.0;
+    if (denominator > 1e-6) {
+        factor = 1.0 / denominator;
+    }
+    solver_data[particle_index].a = vec4f(factor, 0.0, 0.0, 0.0);
+    p_density[particle_index].density = vec2f(density, 0.0);
+}
+
+// no early return in the error kernels, every invocation has to reach the barriers of the sum
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_divergence_error(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var error = 0.0;
+
+    if (index < num_particles && solver_stats.converged[DIVERGENCE_SOLVE] == 0u) {
+        // only compression is corrected, a free surface may spread out
+        let divergence = max(dfsph_density_change(index), 0.0);
+        solver_data[index].a.y = divergence / time_step.delta_time * solver_data[index].a.x;
+        error = divergence / params.target_density;
+    }
+
+    reduce_solver_error(local_id.x, error);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_density_error(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var error = 0.0;
+
+    if (index < num_particles && solver_stats.converged[DENSITY_SOLVE] == 0u) {
+        let delta_time = time_step.delta_time;
+        let predicted_density = p_density[index].density.x + delta_time * dfsph_density_change(index);
+        let compression = max(predicted_density - params.target_density, 0.0);
+        solver_data[index].a.y = compression / (delta_time * delta_time) * solver_data[index].a.x;
+        error = compression / params.target_density;
+    }
+
+    reduce_solver_error(local_id.x, error);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_apply_divergence(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || solver_stats.converged[DIVERGENCE_SOLVE] != 0u) {
+        return;
+    }
+    p_velocity[index].velocity -= time_step.delta_time * dfsph_pressure_acceleration(index);
+    push_bodies(index, vec2f(solver_data[index].a.y * time_step.delta_time, 0.0));
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_apply_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || solver_stats.converged[DENSITY_SOLVE] != 0u) {
+        return;
+    }
+    p_velocity[index].velocity -= time_step.delta_time * dfsph_pressure_acceleration(index);
+    push_bodies(index, vec2f(solver_data[index].a.y * time_step.delta_time, 0.0));
+}
+
+@compute @workgroup_size(1, 1, 1)
+fn dfsph_check_divergence() {
+    check_solver_error(DIVERGENCE_SOLVE, solver_params.divergence_tolerance, 0u);
+}
+
+@compute @workgroup_size(1, 1, 1)
+fn dfsph_check_density() {
+    check_solver_error(DENSITY_SOLVE, solver_params.tolerance, 0u);
+}
+
+// gravity and the mouse between the two solves
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_external_forces(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+    var pos = p_position[index].position;
+    var vel = p_velocity[index].velocity;
+    external_forces(&pos, &vel);
+    p_velocity[index].velocity = vel;
+}
+
+// no early return, every invocation has to reach the barriers of the max reduction
+@compute @workgroup_size(16, 1, 1)
+fn dfsph_update_position(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var speed = 0.0;
+    var acceleration = 0.0;
+
+    if (index < num_particles) {
+        var pos = p_position[index].position;
+        var vel = p_velocity[index].velocity;
+        let delta_time = time_step.delta_time;
+
+        acceleration = length(vel - solver_data[index].b.xyz) / delta_time;
+        pos += vel * delta_time;
+        checkBoundaries(&pos, &vel, calculateBoundries());
+
+        p_position[index].position = pos;
+        p_velocity[index].velocity = vel;
+        speed = length(vel);
+        if (has_escaped(pos.xy)) {
+            speed = 0.0;
+            acceleration = 0.0;
+        }
+    }
+
+    reduce_time_step_maxima(local_id.x, speed, acceleration);
+}
+
+// sum_j m (v_i - v_j) . grad W_ij, the rate the density of the particle changes at
+fn dfsph_density_change(particle_index: u32) -> f32 {
+    var density_change = 0.0;
+    var velocity = p_velocity[particle_index].velocity;
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var relative_velocity = velocity - p_velocity[neighbour_index].velocity;
+            density_change += MASS * dot(relative_velocity, kernel_gradient(particle_index, offset_to_neighbour, dst));
+
+            curr_index += 1u;
+        }
+    }
+
+    // the walls stand still, the bodies move their boundary particles along
+    density_change += dot(velocity, boundary_data[particle_index].gradient) - boundary_data[particle_index].flux;
+    return density_change;
+}
+
+// sum_j m (kappa_i / rho_i + kappa_j / rho_j) grad W_ij
+fn dfsph_pressure_acceleration(particle_index: u32) -> vec3f {
+    var acceleration = vec3f(0.0, 0.0, 0.0);
+    var kappa = solver_data[particle_index].a.y;
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var shared_kappa = kappa + solver_data[neighbour_index].a.y;
+            acceleration += MASS * shared_kappa * kernel_gradient(particle_index, offset_to_neighbour, dst);
+
+            curr_index += 1u;
+        }
+    }
+
+    acceleration += kappa * boundary_data[particle_index].gradient;
+    return acceleration;
+}
+
+// workgroup sum of the error folded into solver_stats.error_sum as fixed point.
+// has to be reached by every invocation of the workgroup
+fn reduce_solver_error(local_index: u32, error: f32) {
+    solver_errors[local_index] = error;
+    workgroupBarrier();
+    for (var stride = 8u; stride > 0u; stride /= 2u) {
+        if (local_index < stride) {
+            solver_errors[local_index] += solver_errors[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+    if (local_index == 0u) {
+        atomicAdd(&solver_stats.error_sum, u32(round(solver_errors[0] * SOLVER_ERROR_SCALE)));
+    }
+}
+
+// single invocation between the error and the apply pass of an iterative solve. the average
+// error decides whether the remaining iterations of the solve are skipped, once at least
+// `min_iterations` corrections went in
+fn check_solver_error(solve: u32, tolerance: f32, min_iterations: u32) {
+    let error_sum = f32(atomicExchange(&solver_stats.error_sum, 0u)) / SOLVER_ERROR_SCALE;
+    if (solver_stats.converged[solve] != 0u) {
+        return;
+    }
+    let error = error_sum / f32(max(num_particles, 1u));
+    solver_stats.error[solve] = error;
+    let iterations = solver_stats.iterations[solve];
+    if ((iterations >= min_iterations && error <= tolerance) || iterations >= solver_params.max_iterations) {
+        solver_stats.converged[solve] = 1u;
+    } else {
+        solver_stats.iterations[solve] += 1u;
+    }
+}
+
+fn reset_solver_stats() {
+    atomicStore(&solver_stats.error_sum, 0u);
+    for (var solve = 0u; solve < 2u; solve++) {
+        solver_stats.converged[solve] = 0u;
+        solver_stats.iterations[solve] = 0u;
+        solver_stats.error[solve] = 0.0;
+    }
+}
+
+
+// Implicit incompressible SPH, Ihmsen et al. 2014. runs on the density and viscosity of the
+// explicit scheme and replaces its pressure force. solver_data[i].a holds a_ii, the advected
+// density, the pressure and the next pressure, b.xyz d_ii and c.xyz sum_j d_ij p_j
+
+// external forces on the velocity only, the density is taken where the particles are since
+// the pressure solve itself predicts where they end up
+@compute @workgroup_size(16, 1, 1)
+fn iisph_predict_velocity(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+    var pos = p_position[index].position;
+    var vel = p_velocity[index].velocity;
+
+    external_forces(&pos, &vel);
+    predicted_p_position[index].position = pos;
+    p_velocity[index].velocity = vel;
+}
+
+// calculate_density with the own W(0), the explicit scheme was tuned without it but the solve
+// has to see the full density to reach the rest density
+@compute @workgroup_size(16, 1, 1)
+fn iisph_calculate_density(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+
+    p_density[index].density = fast_update_density(index) + vec2f(self_density(), 0.0);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn iisph_prepare(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle_index = global_id.x;
+    if (particle_index == 0u) {
+        reset_solver_stats();
+    }
+    if (particle_index >= num_particles) {
+        return;
+    }
+
+    let delta_time = time_step.delta_time;
+    let density = p_density[particle_index].density.x;
+    let velocity = p_velocity[particle_index].velocity;
+    var density_change = 0.0;
+    var sum_grad = vec3f(0.0, 0.0, 0.0);
+    var sum_grad_sqr = 0.0;
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var grad = MASS * kernel_gradient(particle_index, offset_to_neighbour, dst);
+            density_change += dot(velocity - p_velocity[neighbour_index].velocity, grad);
+            sum_grad += grad;
+            sum_grad_sqr += dot(grad, grad);
+
+            curr_index += 1u;
+        }
+    }
+
+    // the boundary particles count into d_ii, but have no d_ji of their own
+    density_change += dot(velocity, boundary_data[particle_index].gradient) - boundary_data[particle_index].flux;
+    sum_grad += boundary_data[particle_index].gradient;
+
+    // d_ii = -dt^2 sum_j m / rho_i^2 grad W_ij, and a_ii = sum_j m (d_ii - d_ji) . grad W_ij
+    // with d_ji = dt^2 m / rho_i^2 grad W_ij
+    let scale = delta_time * delta_time / (density * density);
+    let d_ii = -scale * sum_grad;
+    let a_ii = dot(d_ii, sum_grad) - scale * sum_grad_sqr;
+
+    // half of the last step's pressure as the first guess
+    let pressure = 0.5 * solver_data[particle_index].a.z;
+    solver_data[particle_index].a = vec4f(a_ii, density + delta_time * density_change, pressure, pressure);
+    solver_data[particle_index].b = vec4f(d_ii, 0.0);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn iisph_sum_dij(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let particle_index = global_id.x;
+    if (particle_index >= num_particles || solver_stats.converged[DENSITY_SOLVE] != 0u) {
+        return;
+    }
+
+    let delta_time = time_step.delta_time;
+    var sum_dij = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var neighbour_density = p_density[neighbour_index].density.x;
+            var neighbour_pressure = solver_data[neighbour_index].a.z;
+            var grad = MASS * kernel_gradient(particle_index, offset_to_neighbour, dst);
+            sum_dij -= delta_time * delta_time / (neighbour_density * neighbour_density) * neighbour_pressure * grad;
+
+            curr_index += 1u;
+        }
+    }
+
+    solver_data[particle_index].c = vec4f(sum_dij, 0.0);
+}
+
+// the density the current pressures lead to gives the error, the relaxed jacobi update of the
+// pressure waits in a.w for iisph_apply_pressure. no early return, every invocation has to
+// reach the barriers of the sum
+@compute @workgroup_size(16, 1, 1)
+fn iisph_pressure_error(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var error = 0.0;
+
+    if (index < num_particles && solver_stats.converged[DENSITY_SOLVE] == 0u) {
+        let a_ii = solver_data[index].a.x;
+        let advected_density = solver_data[index].a.y;
+        let pressure = solver_data[index].a.z;
+        let pressure_term = iisph_pressure_term(index);
+
+        // only compression is corrected, pressures stay non-negative
+        let density = advected_density + a_ii * pressure + pressure_term;
+        error = max(density - params.target_density, 0.0) / params.target_density;
+
+        var next_pressure = 0.0;
+        if (abs(a_ii) > 1e-9) {
+            let omega = solver_params.omega;
+            next_pressure = max((1.0 - omega) * pressure + omega / a_ii * (params.target_density - advected_density - pressure_term), 0.0);
+        }
+        solver_data[index].a.w = next_pressure;
+    }
+
+    reduce_solver_error(local_id.x, error);
+}
+
+@compute @workgroup_size(1, 1, 1)
+fn iisph_check_pressure() {
+    check_solver_error(DENSITY_SOLVE, solver_params.tolerance, IISPH_MIN_ITERATIONS);
+}
+
+@compute @workgroup_size(16, 1, 1)
+fn iisph_apply_pressure(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || solver_stats.converged[DENSITY_SOLVE] != 0u) {
+        return;
+    }
+    solver_data[index].a.z = solver_data[index].a.w;
+}
+
+// no early return, every invocation has to reach the barriers of the max reduction
+@compute @workgroup_size(16, 1, 1)
+fn iisph_update_position(@builtin(global_invocation_id) global_id: vec3<u32>, @builtin(local_invocation_id) local_id: vec3<u32>) {
+    let index = global_id.x;
+    var speed = 0.0;
+    var acceleration = 0.0;
+
+    if (index < num_particles) {
+        let maxima = integrate_position(index, iisph_pressure_acceleration(index));
+        speed = maxima.x;
+        acceleration = maxima.y;
+
+        let density = p_density[index].density.x;
+        push_bodies(index, vec2f(solver_data[index].a.z / (density * density) * time_step.delta_time, 0.0));
+    }
+
+    reduce_time_step_maxima(local_id.x, speed, acceleration);
+}
+
+// sum_j m (sum_k d_ik p_k - d_jj p_j - sum_{k != i} d_jk p_k) . grad W_ij, where the last sum
+// is the neighbour's sum_dij without the d_ji p_i of this particle
+fn iisph_pressure_term(particle_index: u32) -> f32 {
+    let delta_time = time_step.delta_time;
+    let density = p_density[particle_index].density.x;
+    let pressure = solver_data[particle_index].a.z;
+    let sum_dij = solver_data[particle_index].c.xyz;
+    var pressure_term = 0.0;
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var grad = MASS * kernel_gradient(particle_index, offset_to_neighbour, dst);
+            var neighbour = solver_data[neighbour_index];
+            var d_ji = delta_time * delta_time / (density * density) * grad;
+            var neighbour_sum = neighbour.c.xyz - d_ji * pressure;
+            pressure_term += dot(sum_dij - neighbour.b.xyz * neighbour.a.z - neighbour_sum, grad);
+
+            curr_index += 1u;
+        }
+    }
+
+    pressure_term += dot(sum_dij, boundary_data[particle_index].gradient);
+    return pressure_term;
+}
+
+// -sum_j m (p_i / rho_i^2 + p_j / rho_j^2) grad W_ij
+fn iisph_pressure_acceleration(particle_index: u32) -> vec3f {
+    let density = p_density[particle_index].density.x;
+    let pressure_over_density = solver_data[particle_index].a.z / (density * density);
+    var acceleration = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[particle_index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == particle_index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dst = sqrt(sqr_dst_to_neighbour);
+            var neighbour_density = p_density[neighbour_index].density.x;
+            var shared_pressure = pressure_over_density + solver_data[neighbour_index].a.z / (neighbour_density * neighbour_density);
+            acceleration -= MASS * shared_pressure * kernel_gradient(particle_index, offset_to_neighbour, dst);
+
+            curr_index += 1u;
+        }
+    }
+
+    acceleration -= pressure_over_density * boundary_data[particle_index].gradient;
+    return acceleration;
+}
+
+
+struct Particle_position {
+    position: vec3f,
+};
+
+struct Particle_velocity {
+    velocity: vec3f,
+};
+
+struct Particle_density {
+    density: vec2f,
+};
+
+// mirrors BoundingBoxLl in simulation/bounding_box.rs. the cpu writes the size, the edges and the
+// origin the container rests at, update_container moves it from there every step
+struct BoundryBox {
+    boundry_box_size: vec2<f32>,
+    origin: vec2f,
+    origin_velocity: vec2f,
+    modes: vec2<u32>,
+    angle: f32,
+    angular_velocity: f32,
+    boundry_box_center: vec2<f32>,
+    velocity: vec2f,
+};
+
+// mirrors ContainerMotion in simulation/container.rs, angles in degrees
+struct ContainerMotion {
+    velocity: vec2f,
+    amplitude: vec2f,
+    angular_velocity: f32,
+    angle_amplitude: f32,
+    frequency: f32,
+};
+
+struct KeyValuePair {
+    particle_index: u32,
+    cell_key: u32,
+};
+
+struct matrix {
+    m: mat4x4<f32>,
+};
+
+// mirrors BoundaryParticle in simulation/boundary.rs
+struct BoundaryParticle {
+    position: vec2<f32>,
+    psi: f32,
+    cell_key: u32,
+};
+
+// mirrors BoundaryData in simulation/boundary.rs
+struct BoundaryData {
+    gradient: vec3<f32>,
+    density: f32,
+    near_gradient: vec3<f32>,
+    near_density: f32,
+    flux: f32,
+};
 
-    var pressure_force = fast_calculate_pressure_force(index);
-    var clamp_force = clamp_force(pressure_force, 100.0);
-    vel += clamp_force * delta_time;
-    pos += vel * delta_time;
+// mirrors ObstacleShape in simulation/obstacle.rs
+struct ObstacleShape {
+    kind: u32,
+    group: u32,
+    first_point: u32,
+    num_points: u32,
+    a: vec4f,
+    b: vec4f,
+};
 
-    checkBoundaries(&pos, &vel, half_boundries);
+// mirrors ObstacleInfo in simulation/obstacle.rs
+struct ObstacleInfo {
+    num_shapes: u32,
+    baked: u32,
+    field_size: vec2<u32>,
+    field_min: vec2f,
+    cell_size: f32,
+};
 
-    p_position[index].position = pos;
-    p_velocity[index].velocity = vel;
-    workgroupBarrier();
-}
+// mirrors RigidBody in simulation/rigid_body.rs
+struct RigidBody {
+    position: vec2f,
+    velocity: vec2f,
+    angle: f32,
+    angular_velocity: f32,
+    inverse_mass: f32,
+    inverse_inertia: f32,
+    kind: u32,
+    first_point: u32,
+    num_points: u32,
+    radius: f32,
+};
 
+// mirrors BodySample in simulation/rigid_body.rs
+struct BodySample {
+    position: vec2f,
+    psi: f32,
+    _padding: f32,
+};
 
-struct Particle_position {
-    position: vec3f,
+// what the fluid pushed into one body this step, fixed point by IMPULSE_SCALE
+struct BodyImpulse {
+    x: atomic<i32>,
+    y: atomic<i32>,
+    torque: atomic<i32>,
+    _padding: i32,
 };
 
-struct Particle_velocity {
-    velocity: vec3f,
+// mirrors SimParams in simulation/params.rs
+struct SimParams {
+    gravity: f32,
+    smoothing_radius: f32,
+    pressure_multiplier: f32,
+    near_pressure_multiplier: f32,
+    target_density: f32,
+    viscosity_strength: f32,
+    interaction_radius: f32,
+    interaction_strength: f32,
+    boundary_restitution: f32,
+    equation_of_state: u32,
+    tait_stiffness: f32,
+    tait_gamma: f32,
+    kernel: u32,
+    surface_tension: f32,
+    vorticity_confinement: f32,
+    xsph: f32,
 };
 
-struct Particle_density {
-    density: vec2f,
+// mirrors SolverParams in simulation/solver.rs
+struct SolverParams {
+    iterations: u32,
+    relaxation: f32,
+    tensile_k: f32,
+    tensile_n: f32,
+    tensile_dq: f32,
+    max_iterations: u32,
+    tolerance: f32,
+    divergence_tolerance: f32,
+    omega: f32,
 };
 
-struct BoundryBox {
-    boundry_box_center: vec2<f32>,
-    boundry_box_size: vec2<f32>,
+// mirrors SolverStats in simulation/solver.rs, one slot per solve of a step
+struct SolverStats {
+    error_sum: atomic<u32>,
+    converged: array<u32, 2>,
+    iterations: array<u32, 2>,
+    error: array<f32, 2>,
 };
 
-struct KeyValuePair {
-    particle_index: u32,
-    cell_key: i32,
+// mirrors SolverData in simulation/solver.rs
+struct SolverData {
+    a: vec4<f32>,
+    b: vec4<f32>,
+    c: vec4<f32>,
 };
 
-struct matrix {
-    m: mat4x4<f32>,
+// mirrors TimeStepParams in simulation/time_step.rs
+struct TimeStepParams {
+    cfl_factor: f32,
+    force_factor: f32,
+    min_delta_time: f32,
+    max_delta_time: f32,
+};
+
+// mirrors TimeStep in simulation/time_step.rs, the maxima hold f32 bits so atomicMax works on them
+struct TimeStep {
+    delta_time: f32,
+    time: f32,
+    max_speed: atomic<u32>,
+    max_acceleration: atomic<u32>,
 };
 
 @group(0) @binding(0) var<storage, read_write> p_position: array<Particle_position>;
 @group(0) @binding(1) var<storage, read_write> p_velocity: array<Particle_velocity>;
 @group(0) @binding(2) var<storage, read_write> p_density: array<Particle_density>;
 @group(0) @binding(3) var<storage, read_write> predicted_p_position: array<Particle_position>;
+@group(0) @binding(4) var<storage, read_write> solver_data: array<SolverData>;
 
 @group(1) @binding(0) var<uniform> radius: f32;
 @group(1) @binding(1) var<uniform> num_particles: u32;
-@group(1) @binding(2) var<storage, read> boundry_box: BoundryBox;
-// @group(1) @binding(3) var<uniform> delta_time: f32;
+@group(1) @binding(2) var<storage, read_write> boundry_box: BoundryBox;
+@group(1) @binding(3) var<storage, read_write> time_step: TimeStep;
 @group(1) @binding(4) var<uniform> max_particles: u32;
 @group(1) @binding(5) var<storage, read> pressed: u32;
 @group(1) @binding(6) var<storage, read> mouse_delta: MouseDelta;
 @group(1) @binding(7) var<storage, read> cheat_depth: f32;
+@group(1) @binding(8) var<uniform> params: SimParams;
+@group(1) @binding(9) var<uniform> time_step_params: TimeStepParams;
+@group(1) @binding(10) var<uniform> solver_params: SolverParams;
+@group(1) @binding(11) var<storage, read_write> solver_stats: SolverStats;
+@group(1) @binding(12) var<storage, read> obstacle_shapes: array<ObstacleShape>;
+@group(1) @binding(13) var<storage, read> obstacle_points: array<vec2f>;
+@group(1) @binding(14) var<uniform> obstacles: ObstacleInfo;
+@group(1) @binding(15) var<storage, read> obstacle_field: array<f32>;
+@group(1) @binding(16) var<storage, read_write> bodies: array<RigidBody>;
+@group(1) @binding(17) var<storage, read> body_points: array<vec2f>;
+@group(1) @binding(18) var<storage, read_write> body_impulses: array<BodyImpulse>;
+@group(1) @binding(19) var<uniform> num_bodies: u32;
+@group(1) @binding(20) var<uniform> container_motion: ContainerMotion;
+@group(1) @binding(21) var<storage, read> body_samples: array<BodySample>;
+@group(1) @binding(22) var<storage, read> body_sample_ranges: array<vec2<u32>>;
 
 
 
 @group(2) @binding(0) var<storage, read_write> spatial_hash: array<KeyValuePair>;
 @group(2) @binding(1) var<storage, read_write> start_indices: array<u32>;
-@group(2) @binding(1) var<storage, read_write> entries: array<u32>;
+@group(2) @binding(2) var<storage, read_write> cell_counts: array<atomic<u32>>;
+@group(2) @binding(3) var<storage, read_write> cell_offsets: array<atomic<u32>>;
+@group(2) @binding(4) var<uniform> table_size: u32;
+@group(2) @binding(5) var<storage, read> boundary_particles: array<BoundaryParticle>;
+@group(2) @binding(6) var<storage, read> boundary_start_indices: array<u32>;
+@group(2) @binding(7) var<uniform> num_boundary_particles: u32;
+@group(2) @binding(8) var<storage, read_write> boundary_data: array<BoundaryData>;
 
 @group(3) @binding(0) var<uniform> proj_view_inv: matrix;
 
 
-struct PushConstants { k: u32, j: u32, next_pwr: u32 }
-var<push_constant> c: PushConstants;
-
 struct MouseDelta {
     previous_position: vec2<f32>,
     current_position: vec2<f32>,
@@ -201,23 +1649,50 @@ const OPENGL_TO_WGPU_MATRIX = mat4x4<f32>(
 
 
 const PI: f32 = 3.14159265359;
-const GRAVITY: f32 = 9.81;
-const BOUNDARY_RESTITUTION: f32 = 0.9; 
-const SMOOTHING_RADIUS: f32 = 1.0;
-const PRESSURE_MULTIPLIER: f32 = 50.0;
-const NEAR_PRESSURE_MULTIPLIER: f32 = 10.0;
-const TARGET_DENSITY: f32 = 5.0;
-const TIME_STEP: f32 = 1 / 60.0;
 const MASS: f32 = 1.0;
-const VISCOSITY_STRENGTH: f32 = 0.1;
-const delta_time: f32 = 1.0 / 60.0; // the game loop is so bad from winit that we have to hardcode this
-const interaction_radius: f32 = 3.0;
-const interaction_strength: f32 = 1.0;
+// akinci's gamma multiplies m_i m_j for the cohesion but only m_i for the curvature, which
+// balances for masses in kg. with unit masses the curvature wins and tears the surface layer
+// off in the solvers without negative pressures, so it is weighted down
+const CURVATURE_WEIGHT: f32 = 0.01;
+const SCAN_THREADS: u32 = 256u;
+const DIVERGENCE_SOLVE: u32 = 0u;
+const DENSITY_SOLVE: u32 = 1u;
+// errors are summed as fixed point since there is no float atomicAdd
+const SOLVER_ERROR_SCALE: f32 = 4096.0;
+// sweeps of the jacobi pressure solve before its average error is trusted, as in the paper
+const IISPH_MIN_ITERATIONS: u32 = 2u;
+const EOS_LINEAR: u32 = 0u;
+const EOS_CLAMPED_LINEAR: u32 = 1u;
+const EOS_TAIT: u32 = 2u;
+const EOS_CLAMPED_TAIT: u32 = 3u;
+// same numbering as in obstacle.rs
+const SHAPE_CIRCLE: u32 = 0u;
+const SHAPE_BOX: u32 = 1u;
+const SHAPE_CAPSULE: u32 = 2u;
+const SHAPE_POLYGON: u32 = 3u;
+// distance reported when there is nothing to collide with
+const FAR_AWAY: f32 = 1e9;
+// step of the central differences the obstacle normal is taken from
+const NORMAL_EPSILON: f32 = 0.01;
+// same numbering as in rigid_body.rs
+const BODY_CIRCLE: u32 = 0u;
+const BODY_POLYGON: u32 = 1u;
+// same numbering as BoundaryMode in container.rs
+const BOUNDARY_REFLECTIVE: u32 = 0u;
+const BOUNDARY_PERIODIC: u32 = 1u;
+const BOUNDARY_OPEN: u32 = 2u;
+// body impulses are summed as fixed point as well
+const IMPULSE_SCALE: f32 = 4096.0;
+
+var<workgroup> scan_totals: array<u32, 256>;
+var<workgroup> max_speeds: array<f32, 16>;
+var<workgroup> max_accelerations: array<f32, 16>;
+var<workgroup> solver_errors: array<f32, 16>;
 
 
 fn external_forces(pos: ptr<function, vec3<f32>>, vel: ptr<function, vec3<f32>>) -> vec3<f32> {
     // Apply gravity
-    (*vel).y -= GRAVITY * delta_time;
+    (*vel).y -= params.gravity * time_step.delta_time;
 
     // Add mouse interaction force
     if pressed == 1 {
@@ -230,22 +1705,404 @@ fn external_forces(pos: ptr<function, vec3<f32>>, vel: ptr<function, vec3<f32>>)
         let diff = (mouse_pos_world.xy / mouse_pos_world.w) - (*pos).xy; // Divide by w to get correct world coordinates
         let sqrDst = dot(diff, diff);
 
-        if (sqrDst < interaction_radius * interaction_radius) {
+        if (sqrDst < params.interaction_radius * params.interaction_radius) {
             let dist = sqrt(sqrDst);
-            let edgeT = dist / interaction_radius;
+            let edgeT = dist / params.interaction_radius;
             let centreT = 1.0 - edgeT;
             let direction = normalize(diff);
-            let force = direction * (interaction_radius - dist) * interaction_strength;
-            (*vel).x += force.x;
-            (*vel).y += force.y;
-            return (*pos) + (*vel) * TIME_STEP;
+            let force = direction * (params.interaction_radius - dist) * params.interaction_strength;
+            (*vel).x += force.x * time_step.delta_time;
+            (*vel).y += force.y * time_step.delta_time;
+            return (*pos) + (*vel) * time_step.delta_time;
+        }
+    }
+
+    return (*pos) + (*vel) * time_step.delta_time;
+}
+
+
+
+// Obstacles, static signed distance fields inside the container. after the solver has moved
+// the particles, any that ended up closer to an obstacle than their radius are pushed back out
+// along the normal and lose the part of their velocity going in, like at the container walls
+@compute @workgroup_size(16, 1, 1)
+fn resolve_obstacles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles || obstacles.num_shapes == 0u) {
+        return;
+    }
+    var pos = p_position[index].position;
+    var vel = p_velocity[index].velocity;
+    collide_with_obstacles(&pos, &vel);
+    p_position[index].position = pos;
+    p_velocity[index].velocity = vel;
+}
+
+fn collide_with_obstacles(pos: ptr<function, vec3f>, vel: ptr<function, vec3f>) {
+    let penetration = radius - obstacle_distance((*pos).xy);
+    if (penetration <= 0.0) {
+        return;
+    }
+    let normal = vec3f(obstacle_normal((*pos).xy), 0.0);
+    *pos += normal * penetration;
+    let normal_speed = dot(*vel, normal);
+    if (normal_speed < 0.0) {
+        *vel -= normal * (1.0 + params.boundary_restitution) * normal_speed;
+    }
+}
+
+// signed distance to the closest obstacle surface, negative inside
+fn obstacle_distance(p: vec2f) -> f32 {
+    if (obstacles.baked != 0u) {
+        return field_distance(p);
+    }
+    return analytic_distance(p);
+}
+
+// the shapes of one obstacle are smooth-min'd together, the obstacles a plain min of those
+fn analytic_distance(p: vec2f) -> f32 {
+    if (obstacles.num_shapes == 0u) {
+        return FAR_AWAY;
+    }
+    var nearest = FAR_AWAY;
+    var group = obstacle_shapes[0].group;
+    var group_distance = FAR_AWAY;
+    for (var i = 0u; i < obstacles.num_shapes; i++) {
+        let shape = obstacle_shapes[i];
+        let d = shape_distance(shape, p);
+        if (shape.group != group) {
+            nearest = min(nearest, group_distance);
+            group = shape.group;
+            group_distance = d;
+        } else {
+            group_distance = smooth_min(group_distance, d, shape.b.w);
+        }
+    }
+    return min(nearest, group_distance);
+}
+
+fn shape_distance(shape: ObstacleShape, p: vec2f) -> f32 {
+    let a = shape.a;
+    switch (shape.kind) {
+        case SHAPE_CIRCLE: {
+            return length(p - a.xy) - a.z;
+        }
+        case SHAPE_BOX: {
+            // into the frame of the box, then the usual axis aligned distance
+            let offset = p - a.xy;
+            let local = vec2f(shape.b.x * offset.x + shape.b.y * offset.y, -shape.b.y * offset.x + shape.b.x * offset.y);
+            let d = abs(local) - a.zw;
+            return length(max(d, vec2f(0.0, 0.0))) + min(max(d.x, d.y), 0.0);
+        }
+        case SHAPE_CAPSULE: {
+            let along = a.zw - a.xy;
+            let offset = p - a.xy;
+            let h = clamp(dot(offset, along) / max(dot(along, along), 1e-12), 0.0, 1.0);
+            return length(offset - along * h) - shape.b.x;
+        }
+        default: {
+            return polygon_distance(shape.first_point, shape.num_points, p);
+        }
+    }
+}
+
+// exact distance to a closed polygon, negative inside by the crossing number
+fn polygon_distance(first: u32, count: u32, p: vec2f) -> f32 {
+    let v0 = p - obstacle_points[first];
+    var nearest = dot(v0, v0);
+    var side = 1.0;
+    var j = count - 1u;
+    for (var i = 0u; i < count; i++) {
+        let vi = obstacle_points[first + i];
+        let vj = obstacle_points[first + j];
+        let edge = vj - vi;
+        let w = p - vi;
+        let closest = w - edge * clamp(dot(w, edge) / max(dot(edge, edge), 1e-12), 0.0, 1.0);
+        nearest = min(nearest, dot(closest, closest));
+
+        let crossing = vec3<bool>(p.y >= vi.y, p.y < vj.y, edge.x * w.y > edge.y * w.x);
+        if (all(crossing) || !any(crossing)) {
+            side = -side;
+        }
+        j = i;
+    }
+    return side * sqrt(nearest);
+}
+
+// polynomial smooth minimum, a plain min for k = 0
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if (k <= 0.0) {
+        return min(a, b);
+    }
+    let h = max(k - abs(a - b), 0.0) / k;
+    return min(a, b) - h * h * k * 0.25;
+}
+
+// bilinear in the baked grid, clamped to its edges
+fn field_distance(p: vec2f) -> f32 {
+    let size = obstacles.field_size;
+    let cell = (p - obstacles.field_min) / obstacles.cell_size;
+    let x = clamp(cell.x, 0.0, f32(size.x - 1u));
+    let y = clamp(cell.y, 0.0, f32(size.y - 1u));
+    let x0 = min(u32(floor(x)), size.x - 2u);
+    let y0 = min(u32(floor(y)), size.y - 2u);
+    let tx = x - f32(x0);
+    let ty = y - f32(y0);
+
+    let row = y0 * size.x;
+    let bottom = mix(obstacle_field[row + x0], obstacle_field[row + x0 + 1u], tx);
+    let top = mix(obstacle_field[row + size.x + x0], obstacle_field[row + size.x + x0 + 1u], tx);
+    return mix(bottom, top, ty);
+}
+
+// points away from the obstacles, central differences of the distance
+fn obstacle_normal(p: vec2f) -> vec2f {
+    let dx = vec2f(NORMAL_EPSILON, 0.0);
+    let dy = vec2f(0.0, NORMAL_EPSILON);
+    let gradient = vec2f(
+        obstacle_distance(p + dx) - obstacle_distance(p - dx),
+        obstacle_distance(p + dy) - obstacle_distance(p - dy),
+    );
+    if (dot(gradient, gradient) < 1e-12) {
+        return vec2f(0.0, 1.0);
+    }
+    return normalize(gradient);
+}
+
+// Rigid bodies, two way coupled. their boundary particles keep the fluid off like those of the
+// walls, and push_bodies hands the body back what its particles pushed the fluid with. a particle
+// that still got into a body is pushed out of it and loses the part of its velocity going into
+// the surface. the bodies sum all of it up with atomics and integrate_bodies moves them with
+// it, one thread each
+@compute @workgroup_size(16, 1, 1)
+fn collide_with_bodies(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_particles) {
+        return;
+    }
+    var pos = p_position[index].position;
+    var vel = p_velocity[index].velocity;
+    for (var b = 0u; b < num_bodies; b++) {
+        body_contact(b, &pos, &vel);
+    }
+    p_position[index].position = pos;
+    p_velocity[index].velocity = vel;
+}
+
+// inelastic, the fluid doesn't bounce off floating things. same as Bodies::contact
+fn body_contact(b: u32, pos: ptr<function, vec3f>, vel: ptr<function, vec3f>) {
+    let body = bodies[b];
+    let offset = nearest_image(vec3f((*pos).xy - body.position, 0.0)).xy;
+    let reach = body.radius + radius;
+    if (dot(offset, offset) > reach * reach) {
+        return;
+    }
+    let local = rotate(offset, -body.angle);
+    let penetration = radius - body_distance(body, local);
+    if (penetration <= 0.0) {
+        return;
+    }
+    let normal = rotate(body_normal(body, local), body.angle);
+    *pos += vec3f(normal * penetration, 0.0);
+
+    let arm = offset + normal * penetration;
+    let surface_velocity = body.velocity + perpendicular(arm) * body.angular_velocity;
+    let normal_speed = dot((*vel).xy - surface_velocity, normal);
+    if (normal_speed >= 0.0) {
+        return;
+    }
+    let arm_cross_normal = cross_2d(arm, normal);
+    let effective_mass = 1.0 / MASS + body.inverse_mass + body.inverse_inertia * arm_cross_normal * arm_cross_normal;
+    let j = -normal_speed / effective_mass;
+    *vel += vec3f(normal * (j / MASS), 0.0);
+
+    let impulse = -normal * j;
+    atomicAdd(&body_impulses[b].x, i32(round(impulse.x * IMPULSE_SCALE)));
+    atomicAdd(&body_impulses[b].y, i32(round(impulse.y * IMPULSE_SCALE)));
+    atomicAdd(&body_impulses[b].torque, i32(round(-j * arm_cross_normal * IMPULSE_SCALE)));
+}
+
+// every solver moves a particle by -push.x psi_b grad W_ib - push.y psi_b grad_near W_ib for a
+// boundary particle b, one way or another. on a body that is pushed back with the opposite
+// impulse, which adds up to the buoyancy and whatever else the fluid does to it. summed per body
+// first, so there is one atomic add per body the particle is near. same as
+// CpuSolver::push_bodies
+fn push_bodies(index: u32, push: vec2f) {
+    if (push.x == 0.0 && push.y == 0.0) {
+        return;
+    }
+    let position = predicted_p_position[index].position.xy;
+    let sqr_radius = params.smoothing_radius * params.smoothing_radius;
+    for (var b = 0u; b < num_bodies; b++) {
+        let body = bodies[b];
+        let offset_to_body = nearest_image(vec3f(body.position - position, 0.0)).xy;
+        let reach = body.radius + params.smoothing_radius;
+        if (dot(offset_to_body, offset_to_body) > reach * reach) {
+            continue;
+        }
+        var impulse = vec2f(0.0, 0.0);
+        var torque = 0.0;
+        let range = body_sample_ranges[b];
+        for (var k = range.x; k < range.x + range.y; k++) {
+            let sample = body_samples[k];
+            let arm = rotate(sample.position, body.angle);
+            let offset = vec3f(offset_to_body + arm, 0.0);
+            let sqr_dst = dot(offset, offset);
+            if (sqr_dst > sqr_radius) {
+                continue;
+            }
+
+            let dist = sqrt(sqr_dst);
+            let gradient = push.x * kernel_gradient(index, offset, dist) + push.y * near_kernel_gradient(index, offset, dist);
+            let received = MASS * sample.psi * gradient.xy;
+            impulse += received;
+            torque += cross_2d(arm, received);
+        }
+        if (impulse.x == 0.0 && impulse.y == 0.0 && torque == 0.0) {
+            continue;
+        }
+        atomicAdd(&body_impulses[b].x, i32(round(impulse.x * IMPULSE_SCALE)));
+        atomicAdd(&body_impulses[b].y, i32(round(impulse.y * IMPULSE_SCALE)));
+        atomicAdd(&body_impulses[b].torque, i32(round(torque * IMPULSE_SCALE)));
+    }
+}
+
+// gravity and what the fluid pushed into the body, then the container walls. same as
+// Bodies::integrate
+@compute @workgroup_size(16, 1, 1)
+fn integrate_bodies(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= num_bodies) {
+        return;
+    }
+    let impulse = vec2f(f32(atomicLoad(&body_impulses[index].x)), f32(atomicLoad(&body_impulses[index].y))) / IMPULSE_SCALE;
+    let torque = f32(atomicLoad(&body_impulses[index].torque)) / IMPULSE_SCALE;
+    atomicStore(&body_impulses[index].x, 0);
+    atomicStore(&body_impulses[index].y, 0);
+    atomicStore(&body_impulses[index].torque, 0);
+
+    var body = bodies[index];
+    let delta_time = time_step.delta_time;
+    if (body.inverse_mass > 0.0) {
+        body.velocity += impulse * body.inverse_mass;
+        body.velocity.y -= params.gravity * delta_time;
+    }
+    body.angular_velocity += torque * body.inverse_inertia;
+    body.position += body.velocity * delta_time;
+    body.angle += body.angular_velocity * delta_time;
+
+    var num_corners = body.num_points;
+    if (body.kind == BODY_CIRCLE) {
+        num_corners = 4u;
+    }
+    for (var corner = 0u; corner < num_corners; corner++) {
+        body_collide_with_walls(&body, body_corner(body, corner));
+    }
+    body.position = wrap_periodic(body.position);
+    bodies[index] = body;
+}
+
+// offset of a corner from the body's position, the four extremes of a circle
+fn body_corner(body: RigidBody, corner: u32) -> vec2f {
+    if (body.kind == BODY_CIRCLE) {
+        switch (corner) {
+            case 0u: { return vec2f(body.radius, 0.0); }
+            case 1u: { return vec2f(-body.radius, 0.0); }
+            case 2u: { return vec2f(0.0, body.radius); }
+            default: { return vec2f(0.0, -body.radius); }
+        }
+    }
+    return rotate(body_points[body.first_point + corner], body.angle);
+}
+
+// in the frame of the container and relative to the velocity of its wall, only reflective axes
+// have walls
+fn body_collide_with_walls(body: ptr<function, RigidBody>, arm: vec2f) {
+    let half_size = boundry_box.boundry_box_size / 2.0;
+    let point = (*body).position + arm;
+    let local = container_local(point);
+    var penetrations = array<f32, 4>(-half_size.x - local.x, local.x - half_size.x, -half_size.y - local.y, local.y - half_size.y);
+    var normals = array<vec2f, 4>(vec2f(1.0, 0.0), vec2f(-1.0, 0.0), vec2f(0.0, 1.0), vec2f(0.0, -1.0));
+    let wall_velocity = container_velocity_at(point);
+    for (var wall = 0u; wall < 4u; wall++) {
+        let penetration = penetrations[wall];
+        let normal = rotate(normals[wall], boundry_box.angle);
+        if (penetration <= 0.0 || boundry_box.modes[wall / 2u] != BOUNDARY_REFLECTIVE) {
+            continue;
+        }
+        if ((*body).inverse_mass > 0.0) {
+            (*body).position += normal * penetration;
         }
+        let point_velocity = (*body).velocity + perpendicular(arm) * (*body).angular_velocity - wall_velocity;
+        let normal_speed = dot(point_velocity, normal);
+        if (normal_speed >= 0.0) {
+            continue;
+        }
+        let arm_cross_normal = cross_2d(arm, normal);
+        let effective_mass = (*body).inverse_mass + (*body).inverse_inertia * arm_cross_normal * arm_cross_normal;
+        let j = -(1.0 + params.boundary_restitution) * normal_speed / max(effective_mass, 1e-12);
+        (*body).velocity += normal * (j * (*body).inverse_mass);
+        (*body).angular_velocity += j * arm_cross_normal * (*body).inverse_inertia;
+    }
+}
+
+// signed distance to the surface of a body from a point in its own frame
+fn body_distance(body: RigidBody, p: vec2f) -> f32 {
+    if (body.kind == BODY_CIRCLE) {
+        return length(p) - body.radius;
+    }
+    return body_polygon_distance(body.first_point, body.num_points, p);
+}
+
+// polygon_distance over the body corners
+fn body_polygon_distance(first: u32, count: u32, p: vec2f) -> f32 {
+    let v0 = p - body_points[first];
+    var nearest = dot(v0, v0);
+    var side = 1.0;
+    var j = count - 1u;
+    for (var i = 0u; i < count; i++) {
+        let vi = body_points[first + i];
+        let vj = body_points[first + j];
+        let edge = vj - vi;
+        let w = p - vi;
+        let closest = w - edge * clamp(dot(w, edge) / max(dot(edge, edge), 1e-12), 0.0, 1.0);
+        nearest = min(nearest, dot(closest, closest));
+
+        let crossing = vec3<bool>(p.y >= vi.y, p.y < vj.y, edge.x * w.y > edge.y * w.x);
+        if (all(crossing) || !any(crossing)) {
+            side = -side;
+        }
+        j = i;
+    }
+    return side * sqrt(nearest);
+}
+
+fn body_normal(body: RigidBody, p: vec2f) -> vec2f {
+    let dx = vec2f(NORMAL_EPSILON, 0.0);
+    let dy = vec2f(0.0, NORMAL_EPSILON);
+    let gradient = vec2f(
+        body_distance(body, p + dx) - body_distance(body, p - dx),
+        body_distance(body, p + dy) - body_distance(body, p - dy),
+    );
+    if (dot(gradient, gradient) < 1e-12) {
+        return vec2f(0.0, 1.0);
     }
+    return normalize(gradient);
+}
 
-    return (*pos) + (*vel) * TIME_STEP;
+fn rotate(v: vec2f, angle: f32) -> vec2f {
+    let c = cos(angle);
+    let s = sin(angle);
+    return vec2f(c * v.x - s * v.y, s * v.x + c * v.y);
 }
 
+fn perpendicular(v: vec2f) -> vec2f {
+    return vec2f(-v.y, v.x);
+}
 
+fn cross_2d(a: vec2f, b: vec2f) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
 
 fn calculateBoundries() -> vec2<f32> {
     return vec2(
@@ -254,40 +2111,85 @@ fn calculateBoundries() -> vec2<f32> {
     );
 }
 
-fn clamp_force(force: vec3<f32>, max_magnitude: f32) -> vec3<f32> {
-    let magnitude = length(force);
-    if (magnitude > max_magnitude) {
-        return normalize(force) * max_magnitude;
+// the position part of checkBoundaries, for solvers that derive the velocity afterwards. only
+// the walls, a position past a periodic edge is wrapped once the step is done
+fn clamp_to_boundaries(pos: ptr<function, vec3f>) {
+    let half_boundaries = calculateBoundries();
+    let local = container_local((*pos).xy);
+    if (all(abs(local) <= half_boundaries)) {
+        return;
     }
-    return force;
+    let reflective = boundry_box.modes == vec2<u32>(BOUNDARY_REFLECTIVE, BOUNDARY_REFLECTIVE);
+    let clamped = select(local, clamp(local, -half_boundaries, half_boundaries), reflective);
+    *pos = vec3f(container_world(clamped), (*pos).z);
 }
 
-// Simple boundary function
+// Simple boundary function, in the frame of the container and relative to the velocity of its
+// walls so a moving wall pushes the fluid along. across a periodic axis the particle comes back
+// in at the far edge instead, across an open one it just leaves
 fn checkBoundaries(pos: ptr<function, vec3f>, vel: ptr<function, vec3f>, half_boundaries: vec2<f32>) {
-    let min_bound = boundry_box.boundry_box_center.xy - half_boundaries;
-    let max_bound = boundry_box.boundry_box_center.xy + half_boundaries;
-
-    // Check X boundaries
-    if ((*pos).x < min_bound.x) {
-        let penetration = min_bound.x - (*pos).x;
-        (*pos).x = min_bound.x + penetration; // Push the particle out of the boundary
-        (*vel).x = -(*vel).x * BOUNDARY_RESTITUTION; // Invert and dampen velocity
-    } else if ((*pos).x > max_bound.x) {
-        let penetration = (*pos).x - max_bound.x;
-        (*pos).x = max_bound.x - penetration; // Push the particle out of the boundary
-        (*vel).x = -(*vel).x * BOUNDARY_RESTITUTION; // Invert and dampen velocity
+    var local = container_local((*pos).xy);
+    if (all(abs(local) <= half_boundaries)) {
+        return;
+    }
+    let wall_velocity = container_velocity_at((*pos).xy);
+    var local_vel = rotate((*vel).xy - wall_velocity, -boundry_box.angle);
+
+    for (var axis = 0u; axis < 2u; axis++) {
+        let half = half_boundaries[axis];
+        let mode = boundry_box.modes[axis];
+        if (mode == BOUNDARY_REFLECTIVE) {
+            if (local[axis] < -half) {
+                let penetration = -half - local[axis];
+                local[axis] = -half + penetration; // Push the particle out of the boundary
+                local_vel[axis] = -local_vel[axis] * params.boundary_restitution; // Invert and dampen velocity
+            } else if (local[axis] > half) {
+                let penetration = local[axis] - half;
+                local[axis] = half - penetration; // Push the particle out of the boundary
+                local_vel[axis] = -local_vel[axis] * params.boundary_restitution; // Invert and dampen velocity
+            }
+        } else if (mode == BOUNDARY_PERIODIC) {
+            let size = boundry_box.boundry_box_size[axis];
+            if (local[axis] < -size / 2.0) {
+                local[axis] += size;
+            } else if (local[axis] > size / 2.0) {
+                local[axis] -= size;
+            }
+        }
     }
 
-    // Check Y boundaries
-    if ((*pos).y < min_bound.y) {
-        let penetration = min_bound.y - (*pos).y;
-        (*pos).y = min_bound.y + penetration; // Push the particle out of the boundary
-        (*vel).y = -(*vel).y * BOUNDARY_RESTITUTION; // Invert and dampen velocity
-    } else if ((*pos).y > max_bound.y) {
-        let penetration = (*pos).y - max_bound.y;
-        (*pos).y = max_bound.y - penetration; // Push the particle out of the boundary
-        (*vel).y = -(*vel).y * BOUNDARY_RESTITUTION; // Invert and dampen velocity
+    *pos = vec3f(container_world(local), (*pos).z);
+    *vel = vec3f(rotate(local_vel, boundry_box.angle) + wall_velocity, (*vel).z);
+}
+
+// brings a point that left through a periodic edge back in at the other one, same as Bounds::wrap
+fn wrap_periodic(p: vec2f) -> vec2f {
+    if (!any(is_periodic())) {
+        return p;
     }
+    let size = boundry_box.boundry_box_size;
+    let local = container_local(p);
+    return container_world(select(local, local - size * floor(local / size + 0.5), is_periodic()));
+}
+
+// out past an open edge, such a particle no longer limits the time step
+fn has_escaped(p: vec2f) -> bool {
+    let open = boundry_box.modes == vec2<u32>(BOUNDARY_OPEN, BOUNDARY_OPEN);
+    return any(open & (abs(container_local(p)) > boundry_box.boundry_box_size / 2.0));
+}
+
+// from world space into the frame of the container, centred on it and upright
+fn container_local(p: vec2f) -> vec2f {
+    return rotate(p - boundry_box.boundry_box_center, -boundry_box.angle);
+}
+
+fn container_world(local: vec2f) -> vec2f {
+    return boundry_box.boundry_box_center + rotate(local, boundry_box.angle);
+}
+
+// how fast the container moves at `p`, both from its centre moving and from it turning
+fn container_velocity_at(p: vec2f) -> vec2f {
+    return boundry_box.velocity + perpendicular(p - boundry_box.boundry_box_center) * boundry_box.angular_velocity;
 }
 
 
@@ -296,26 +2198,46 @@ fn move_particle(pos: ptr<function, vec3<f32>>, vel: ptr<function, vec3<f32>>) {
     // *vel *= 0.0;
 }
 
-// Smoothing kernel functions
+// Smoothing kernel functions, the selectable ones are in common/kernel.wgsl
 
-// spikey kernel (s-d)^2
-fn smoothing_kernel_spikey(s_rad: f32, dist: f32) -> f32 {
-    if (dist > s_rad) { return 0.0; }
+// gradient of the selected kernel with respect to the particle itself, offset is neighbour minus particle
+fn kernel_gradient(particle_index: u32, offset_to_neighbour: vec3f, dst: f32) -> vec3f {
+    var direction = -offset_to_neighbour / max(dst, 1e-6);
+    if (dst < 0.01) {
+        direction = get_random_direction(particle_index);
+    }
+    return direction * smoothing_kernel_derivative(params.kernel, params.smoothing_radius, dst);
+}
 
-    var volume: f32 = 6.0 / (PI * pow(s_rad, 4.0));
-    var v: f32 = s_rad - dist + 1e-5;
-    return v * v * volume;
+// m W(0), what a particle adds to its own density. the neighbour loops skip the particle itself
+fn self_density() -> f32 {
+    return MASS * smoothing_kernel(params.kernel, params.smoothing_radius, 0.0);
+}
+
+// the same for the near density kernel
+fn near_kernel_gradient(particle_index: u32, offset_to_neighbour: vec3f, dst: f32) -> vec3f {
+    var direction = -offset_to_neighbour / max(dst, 1e-6);
+    if (dst < 0.01) {
+        direction = get_random_direction(particle_index);
+    }
+    return direction * smoothing_kernel_spikey_near_derivative(params.smoothing_radius, dst);
 }
 
-fn smoothing_kernel_spike_derivative(s_rad: f32, dist: f32) -> f32 {
+// the cohesion spline of Akinci et al. 2013, attracts beyond s_rad / 2 and repels a little
+// closer than that. not a smoothing kernel and not part of the kernel selection
+fn cohesion_kernel(s_rad: f32, dist: f32) -> f32 {
     if (dist > s_rad) { return 0.0; }
 
-    var volume: f32 = 12.0 / (PI * pow(s_rad, 4.0));
-    var v: f32 = s_rad - dist + 1e-5;
-    return - v * volume;
+    var scale: f32 = 32.0 / (PI * pow(s_rad, 9.0));
+    var v: f32 = (s_rad - dist) * (s_rad - dist) * (s_rad - dist) * dist * dist * dist;
+    if (2.0 * dist > s_rad) {
+        return scale * v;
+    }
+    return scale * (2.0 * v - pow(s_rad, 6.0) / 64.0);
 }
 
-// spikey kernel near (s-d)^3
+// the near density of the double density relaxation keeps its own sharper kernel, it only
+// pushes close particles apart and is not part of the kernel selection. (s-d)^3
 fn smoothing_kernel_spikey_near(s_rad: f32, dist: f32) -> f32 {
     if (dist > s_rad) { return 0.0; }
 
@@ -333,23 +2255,6 @@ fn smoothing_kernel_spikey_near_derivative(s_rad: f32, dist: f32) -> f32 {
 }
 
 
-// poly6 kernel (s^2 - d^2)^3
-fn smoothing_kernel_poly6(s_rad: f32, dist: f32) -> f32 {
-    if (dist > s_rad) { return 0.0; }
-
-    var v: f32 = pow(s_rad * s_rad - dist * dist + 1e-5, 3.0);
-    var volume: f32 = 315.0 / (64.0 * PI * pow(s_rad, 9.0));
-    return volume * v;
-}
-
-fn smoothing_kernel_poly6_derivative(s_rad: f32, dist: f32) -> f32 {
-    if (dist > s_rad) { return 0.0; }
-
-    var v: f32 = pow(s_rad * s_rad - dist * dist + 1e-5, 2.0);
-    var volume: f32 = 945.0 / (32.0 * PI * pow(s_rad, 9.0));
-    return - volume * v;
-}
-
 // old and not used
 
 // fn update_density(particle_index: u32) -> f32 {
@@ -379,25 +2284,20 @@ fn fast_update_density(particle_index: u32) -> vec2f {
     var density = 0.0;
     var near_density = 0.0;
     var particle_position = predicted_p_position[particle_index].position;
-    var norm_particle_position = get_shifted_2D_pos(particle_position.xy, calculateBoundries());
-    var sqrRadius = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
-    var neighbor_offsets_2D = array<vec2<i32>, 9>(
-        vec2<i32>(-1, -1), vec2<i32>(0, -1), vec2<i32>(1, -1),
-        vec2<i32>(-1, 0), vec2<i32>(0, 0), vec2<i32>(1, 0),
-        vec2<i32>(-1, 1), vec2<i32>(0, 1), vec2<i32>(1, 1)
-    );
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
 
+    var stencil = stencil_keys(norm_particle_position);
     for (var i: u32 = 0; i < 9; i++) {
-        var pos_offset = norm_particle_position + neighbor_offsets_2D[i];   
-        var hash_key = hash_position(pos_offset);
+        var hash_key = stencil[i];
 
-        if hash_key < 0 || hash_key >= i32(max_particles) {
+        if hash_key == SKIPPED_KEY {
             continue;
         }
 
         var curr_index = start_indices[hash_key];
 
-        while (curr_index < max_particles && spatial_hash[curr_index].cell_key == hash_key) {
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
             var neighbour_index = spatial_hash[curr_index].particle_index;
 
             if (neighbour_index >= num_particles) {
@@ -409,7 +2309,7 @@ fn fast_update_density(particle_index: u32) -> vec2f {
                 continue;
             }
             var neighbour_pos = predicted_p_position[neighbour_index].position;
-            var offset_to_neighbour = neighbour_pos - particle_position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
             var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
 
             if (sqr_dst_to_neighbour > sqrRadius) {
@@ -418,12 +2318,14 @@ fn fast_update_density(particle_index: u32) -> vec2f {
             }
 
             var dist = sqrt(sqr_dst_to_neighbour);
-            density += smoothing_kernel_spikey(SMOOTHING_RADIUS, dist);
-            near_density += smoothing_kernel_spikey_near(SMOOTHING_RADIUS, dist);
+            density += smoothing_kernel(params.kernel, params.smoothing_radius, dist);
+            near_density += smoothing_kernel_spikey_near(params.smoothing_radius, dist);
         
             curr_index += 1u;
         }
     }
+    density += boundary_data[particle_index].density;
+    near_density += boundary_data[particle_index].near_density;
     var max_density = max(density, 0.1);
     var max_near_density = max(near_density, 0.1);
 
@@ -436,12 +2338,21 @@ fn calculate_symmetric_pressure(density_a: f32, density_b: f32) -> f32 {
     return (pressure_a + pressure_b) / 2.0;
 }
 
+// mirrors EquationOfState in simulation/params.rs
 fn convert_density_to_pressure(density: f32) -> f32 {
-    return PRESSURE_MULTIPLIER * (density - TARGET_DENSITY);
+    let eos = params.equation_of_state;
+    var pressure = params.pressure_multiplier * (density - params.target_density);
+    if (eos == EOS_TAIT || eos == EOS_CLAMPED_TAIT) {
+        pressure = params.tait_stiffness * (pow(density / params.target_density, params.tait_gamma) - 1.0);
+    }
+    if (eos == EOS_CLAMPED_LINEAR || eos == EOS_CLAMPED_TAIT) {
+        pressure = max(pressure, 0.0);
+    }
+    return pressure;
 }
 
 fn convert_near_density_to_pressure(near_density: f32) -> f32 {
-    return NEAR_PRESSURE_MULTIPLIER * near_density;
+    return params.near_pressure_multiplier * near_density;
 }
 
 
@@ -485,8 +2396,8 @@ fn convert_near_density_to_pressure(near_density: f32) -> f32 {
 fn fast_calculate_pressure_force(particle_index: u32) -> vec3f {
     var pressure_force = vec3f(0.0, 0.0, 0.0);
     var particle_position = predicted_p_position[particle_index].position;
-    var norm_particle_position = get_shifted_2D_pos(particle_position.xy, calculateBoundries());
-    var sqrRadius = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
 
     var density = p_density[particle_index].density.x;
     var near_density = p_density[particle_index].density.y;
@@ -494,23 +2405,18 @@ fn fast_calculate_pressure_force(particle_index: u32) -> vec3f {
     var near_pressure = convert_near_density_to_pressure(near_density);
 
 
-    var neighbor_offsets_2D = array<vec2<i32>, 9>(
-        vec2<i32>(-1, -1), vec2<i32>(0, -1), vec2<i32>(1, -1),
-        vec2<i32>(-1, 0), vec2<i32>(0, 0), vec2<i32>(1, 0),
-        vec2<i32>(-1, 1), vec2<i32>(0, 1), vec2<i32>(1, 1)
-    );
 
+    var stencil = stencil_keys(norm_particle_position);
     for (var i: u32 = 0; i < 9; i++) {
-        var pos_offset = norm_particle_position + neighbor_offsets_2D[i];   
-        var hash_key = hash_position(pos_offset);
+        var hash_key = stencil[i];
 
-        if hash_key < 0 || hash_key >= i32(max_particles) {
+        if hash_key == SKIPPED_KEY {
             continue;
         }
 
         var curr_index = start_indices[hash_key];
 
-        while (curr_index < max_particles && spatial_hash[curr_index].cell_key == hash_key) {
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
             var neighbour_index = spatial_hash[curr_index].particle_index;
 
             if (neighbour_index >= num_particles) {
@@ -522,7 +2428,7 @@ fn fast_calculate_pressure_force(particle_index: u32) -> vec3f {
                 continue;
             }
             var neighbour_pos = predicted_p_position[neighbour_index].position;
-            var offset_to_neighbour = neighbour_pos - particle_position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
             var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
 
             if (sqr_dst_to_neighbour > sqrRadius) {
@@ -541,8 +2447,8 @@ fn fast_calculate_pressure_force(particle_index: u32) -> vec3f {
             var neighbor_pressure = convert_density_to_pressure(neighbor_density);
             var neighbor_near_pressure = convert_near_density_to_pressure(neighbor_near_density);
 
-            var slope = smoothing_kernel_spike_derivative(SMOOTHING_RADIUS, dst);
-            var slope_near = smoothing_kernel_spikey_near_derivative(SMOOTHING_RADIUS, dst);
+            var slope = smoothing_kernel_derivative(params.kernel, params.smoothing_radius, dst);
+            var slope_near = smoothing_kernel_spikey_near_derivative(params.smoothing_radius, dst);
 
             var shared_pressure = (pressure + neighbor_pressure) * 0.5;
             var shared_near_pressure = (near_pressure + neighbor_near_pressure) * 0.5;
@@ -553,6 +2459,13 @@ fn fast_calculate_pressure_force(particle_index: u32) -> vec3f {
             curr_index += 1u;
         }
     }
+
+    // the boundary particles mirror this particle's pressure and density. a negative pressure
+    // would pull the fluid onto the wall, so only the push is kept
+    let boundary = boundary_data[particle_index];
+    pressure_force -= max(pressure, 0.0) / density * boundary.gradient;
+    pressure_force -= near_pressure / near_density * boundary.near_gradient;
+
     var acceleration = pressure_force / density;
     return acceleration;
 }
@@ -586,47 +2499,112 @@ fn get_random_direction(seed: u32) -> vec3<f32> {
 // Spatial hash functions
 
 
-const hv1: i32 = 73856093;
-const hv2: i32 = 19349663;
+const hv1: u32 = 73856093u;
+const hv2: u32 = 19349663u;
+const SKIPPED_KEY: u32 = 0xffffffffu;
 const hv3: i32 = 83492791; // for 3d hashing
 
+// same as SimParams::cell_size, cells as wide as the smoothing radius so the 3x3 stencil covers it
+fn cell_size() -> f32 {
+    return params.smoothing_radius;
+}
+
 fn get_shifted_2D_pos(pos: vec2<f32>, half_boundries: vec2<f32>) -> vec2<i32> {
-    var floor_x = i32( floor( (pos.x + half_boundries.x) / SMOOTHING_RADIUS ) );
-    var floor_y = i32( floor( (pos.y + half_boundries.y) / SMOOTHING_RADIUS ) );
+    var floor_x = i32( floor( (pos.x + half_boundries.x) / cell_size() ) );
+    var floor_y = i32( floor( (pos.y + half_boundries.y) / cell_size() ) );
     return vec2<i32>(floor_x, floor_y);
 }
 
+// cell of a point in the frame of the container. a periodic axis is split into whole cells no
+// narrower than a smoothing radius, so the stencil of a particle at one edge wraps onto the cells
+// at the other without a gap. same as Bounds::cell
+fn grid_cell(local: vec2f) -> vec2<i32> {
+    let size = boundry_box.boundry_box_size;
+    let cells = periodic_cells();
+    let wrapped = local - size * floor(local / size + 0.5);
+    let periodic_cell = min(vec2<i32>(floor((wrapped / size + 0.5) * vec2f(cells))), cells - 1);
+    return select(get_shifted_2D_pos(local, calculateBoundries()), periodic_cell, is_periodic());
+}
 
-// seems funky to use a float as a hash value, but it works for now
-fn hash_position(pos: vec2<i32>) -> i32 {
-    // var hash = (p.x * hv1 + p.y * hv2); //if wrapping behavior isnt supported
-    //seems to give more uniform distribution
-    var hash = (pos.x * hv1) ^ (pos.y * hv2); //if wrapping behavior is supported
-    return hash % i32(max_particles);
+// a stencil cell past a periodic edge is the one at the far side
+fn wrap_cell(cell: vec2<i32>) -> vec2<i32> {
+    let cells = periodic_cells();
+    return select(cell, ((cell % cells) + cells) % cells, is_periodic());
 }
 
-fn fast_calculate_viscosity_force(index: u32) -> vec3f{
-    var viscosity_force = vec3f(0.0, 0.0, 0.0);
-    var particle_position = predicted_p_position[index].position;
-    var norm_particle_position = get_shifted_2D_pos(particle_position.xy, calculateBoundries());
-    var sqrRadius = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+fn periodic_cells() -> vec2<i32> {
+    return max(vec2<i32>(floor(boundry_box.boundry_box_size / cell_size())), vec2<i32>(1, 1));
+}
+
+fn is_periodic() -> vec2<bool> {
+    return boundry_box.modes == vec2<u32>(BOUNDARY_PERIODIC, BOUNDARY_PERIODIC);
+}
+
+// the offset to whichever periodic image of a neighbour is closest, across a periodic edge that
+// one sits a whole container length away. same as Bounds::nearest_image
+fn nearest_image(offset: vec3f) -> vec3f {
+    if (!any(is_periodic())) {
+        return offset;
+    }
+    let local = nearest_local_image(rotate(offset.xy, -boundry_box.angle));
+    return vec3f(rotate(local, boundry_box.angle), offset.z);
+}
+
+fn nearest_local_image(offset: vec2f) -> vec2f {
+    let size = boundry_box.boundry_box_size;
+    return select(offset, offset - size * floor(offset / size + 0.5), is_periodic());
+}
+
+fn particle_cell_key(index: u32) -> u32 {
+    var pos = predicted_p_position[index].position;
+    var norm_pos = grid_cell(container_local(pos.xy));
+    return hash_position(norm_pos);
+}
+
+// wrapping u32 math so negative cells hash like any other, always lands inside the table
+fn hash_position(pos: vec2<i32>) -> u32 {
+    var hash = (bitcast<u32>(pos.x) * hv1) ^ (bitcast<u32>(pos.y) * hv2);
+    return hash % table_size;
+}
+
+// keys of the 3x3 cells around `center`. different cells can share a bucket, a bucket that
+// already appeared earlier in the stencil is marked skipped so no neighbour is visited twice
+fn stencil_keys(center: vec2<i32>) -> array<u32, 9> {
     var neighbor_offsets_2D = array<vec2<i32>, 9>(
         vec2<i32>(-1, -1), vec2<i32>(0, -1), vec2<i32>(1, -1),
         vec2<i32>(-1, 0), vec2<i32>(0, 0), vec2<i32>(1, 0),
         vec2<i32>(-1, 1), vec2<i32>(0, 1), vec2<i32>(1, 1)
     );
+    var keys: array<u32, 9>;
+    for (var i: u32 = 0; i < 9; i++) {
+        var key = hash_position(wrap_cell(center + neighbor_offsets_2D[i]));
+        for (var j: u32 = 0; j < i; j++) {
+            if (keys[j] == key) {
+                key = SKIPPED_KEY;
+            }
+        }
+        keys[i] = key;
+    }
+    return keys;
+}
+
+fn fast_calculate_viscosity_force(index: u32) -> vec3f{
+    var viscosity_force = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
 
+    var stencil = stencil_keys(norm_particle_position);
     for (var i: u32 = 0; i < 9; i++) {
-        var pos_offset = norm_particle_position + neighbor_offsets_2D[i];   
-        var hash_key = hash_position(pos_offset);
+        var hash_key = stencil[i];
 
-        if hash_key < 0 || hash_key >= i32(max_particles) {
+        if hash_key == SKIPPED_KEY {
             continue;
         }
 
         var curr_index = start_indices[hash_key];
 
-        while (curr_index < max_particles && spatial_hash[curr_index].cell_key == hash_key) {
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
             var neighbour_index = spatial_hash[curr_index].particle_index;
 
             if (neighbour_index >= num_particles) {
@@ -638,7 +2616,7 @@ fn fast_calculate_viscosity_force(index: u32) -> vec3f{
                 continue;
             }
             var neighbour_pos = predicted_p_position[neighbour_index].position;
-            var offset_to_neighbour = neighbour_pos - particle_position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
             var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
 
             if (sqr_dst_to_neighbour > sqrRadius) {
@@ -648,11 +2626,60 @@ fn fast_calculate_viscosity_force(index: u32) -> vec3f{
 
             var dist = sqrt(sqr_dst_to_neighbour);
             var vel_diff = p_velocity[neighbour_index].velocity - p_velocity[index].velocity;
-            var laplacian = smoothing_kernel_poly6(SMOOTHING_RADIUS, dist);
+            var laplacian = smoothing_kernel(params.kernel, params.smoothing_radius, dist);
             viscosity_force += vel_diff * laplacian * MASS;
 
             curr_index += 1u;
         }
     }
     return viscosity_force;
+}
+
+// XSPH, Monaghan 1989. sum_j m_j / rho_j * (v_j - v_i) * W_ij, blends the velocity towards the
+// neighbourhood average. not scaled by the time step, it is a smoothing and not a force
+fn fast_calculate_xsph_correction(index: u32) -> vec3f{
+    var correction = vec3f(0.0, 0.0, 0.0);
+    var particle_position = predicted_p_position[index].position;
+    var norm_particle_position = grid_cell(container_local(particle_position.xy));
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
+
+    var stencil = stencil_keys(norm_particle_position);
+    for (var i: u32 = 0; i < 9; i++) {
+        var hash_key = stencil[i];
+
+        if hash_key == SKIPPED_KEY {
+            continue;
+        }
+
+        var curr_index = start_indices[hash_key];
+
+        while (curr_index < num_particles && spatial_hash[curr_index].cell_key == hash_key) {
+            var neighbour_index = spatial_hash[curr_index].particle_index;
+
+            if (neighbour_index >= num_particles) {
+                break;
+            }
+
+            if (neighbour_index == index) {
+                curr_index += 1u;
+                continue;
+            }
+            var neighbour_pos = predicted_p_position[neighbour_index].position;
+            var offset_to_neighbour = nearest_image(neighbour_pos - particle_position);
+            var sqr_dst_to_neighbour = dot(offset_to_neighbour, offset_to_neighbour);
+
+            if (sqr_dst_to_neighbour > sqrRadius) {
+                curr_index += 1u;
+                continue;
+            }
+
+            var dist = sqrt(sqr_dst_to_neighbour);
+            var vel_diff = p_velocity[neighbour_index].velocity - p_velocity[index].velocity;
+            var neighbour_density = max(p_density[neighbour_index].density.x, 0.1);
+            correction += vel_diff * MASS / neighbour_density * smoothing_kernel(params.kernel, params.smoothing_radius, dist);
+
+            curr_index += 1u;
+        }
+    }
+    return correction;
 }
\ No newline at end of file
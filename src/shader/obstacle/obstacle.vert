@@ -0,0 +1,25 @@
+#version 450
+
+layout(set = 0, binding = 0) uniform MatrixUniform {
+    mat4 view;
+};
+layout(set = 0, binding = 1) uniform MatrixUniform {
+    mat4 proj;
+};
+
+
+const mat4 OPENGL_TO_WGPU_MATRIX = mat4(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.5,
+    0.0, 0.0, 0.0, 1.0
+);
+
+// already in world space, traced on the cpu
+layout(location = 0) in vec2 position;
+
+
+
+void main() {
+    gl_Position = OPENGL_TO_WGPU_MATRIX * proj * view * vec4(position, 0.0, 1.0);
+}
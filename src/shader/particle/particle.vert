@@ -21,6 +21,24 @@ layout(set = 2, binding = 0) uniform RadiusLl {
     float radius;
 };
 
+// mirrors BoundingBoxLl in simulation/bounding_box.rs
+layout(set = 3, binding = 0) buffer readonly BoundingBoxLl {
+    vec2 size;
+    vec2 origin;
+    vec2 origin_velocity;
+    uvec2 modes;
+    float angle;
+    float angular_velocity;
+    vec2 box_position;
+    vec2 velocity;
+};
+layout(set = 3, binding = 1) uniform NumParticles {
+    uint num_particles;
+};
+
+// same numbering as BoundaryMode in simulation/container.rs
+const uint BOUNDARY_PERIODIC = 1u;
+
 
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 fragTexCoord;
@@ -79,12 +97,27 @@ float get_max_density() {
     }
     return maxDens;
 }
+// the images of a periodic axis in draw order, the particle itself then one container length
+// to either side
+float image_shift(uint image, uint mode) {
+    if (mode != BOUNDARY_PERIODIC) {
+        return 0.0;
+    }
+    return image == 0u ? 0.0 : (image == 1u ? -1.0 : 1.0);
+}
+
 void main() {
-    uint particleIndex = gl_InstanceIndex;
+    // every instance past the first num_particles is a ghost, a periodic image of a particle so
+    // the fluid carries on past the edges. see BoundingBox::num_images
+    uint particleIndex = gl_InstanceIndex % num_particles;
+    uint image = gl_InstanceIndex / num_particles;
+    uint columns = modes.x == BOUNDARY_PERIODIC ? 3u : 1u;
+    vec2 shift = vec2(image_shift(image % columns, modes.x), image_shift(image / columns, modes.y)) * size;
+    shift = vec2(cos(angle) * shift.x - sin(angle) * shift.y, sin(angle) * shift.x + cos(angle) * shift.y);
     uint vertexId = gl_VertexIndex % 4;
 
     vec3 offset = quadVertices[vertexId] * radius; //particles[particleIndex].radius;
-    vec4 worldPosition = vec4(particles[particleIndex].position + offset, 1.0);
+    vec4 worldPosition = vec4(particles[particleIndex].position + vec3(shift, 0.0) + offset, 1.0);
 
     gl_Position = OPENGL_TO_WGPU_MATRIX * proj * view * worldPosition;
 
@@ -93,5 +126,8 @@ void main() {
 
     float maxDensity = get_max_density();
     fragColor = gradient(densities[particleIndex].density.x / maxDensity);
+    if (image > 0u) {
+        fragColor *= 0.4;
+    }
 }
 
@@ -29,14 +29,42 @@ struct Particle_density {
 @group(1) @binding(2)
 var<storage, read_write> p_density: array<Particle_density>;
 
+// mirrors BoundingBoxLl in simulation/bounding_box.rs
 struct BoundryBox {
-    center: vec2<f32>,
     size: vec2<f32>,
+    origin: vec2<f32>,
+    origin_velocity: vec2<f32>,
+    modes: vec2<u32>,
+    angle: f32,
+    angular_velocity: f32,
+    center: vec2<f32>,
+    velocity: vec2<f32>,
 };
 
 @group(2) @binding(0) var<uniform> radius: f32;
 @group(2) @binding(1) var<uniform> num_particles: u32;
-@group(2) @binding(2) var<storage, read> boundry_box: BoundryBox;
+@group(2) @binding(2) var<storage, read_write> boundry_box: BoundryBox;
+@group(2) @binding(8) var<uniform> params: SimParams;
+
+// mirrors SimParams in simulation/params.rs
+struct SimParams {
+    gravity: f32,
+    smoothing_radius: f32,
+    pressure_multiplier: f32,
+    near_pressure_multiplier: f32,
+    target_density: f32,
+    viscosity_strength: f32,
+    interaction_radius: f32,
+    interaction_strength: f32,
+    boundary_restitution: f32,
+    equation_of_state: u32,
+    tait_stiffness: f32,
+    tait_gamma: f32,
+    kernel: u32,
+    surface_tension: f32,
+    vorticity_confinement: f32,
+    xsph: f32,
+};
 
 
 const OPENGL_TO_WGPU_MATRIX: mat4x4<f32> = mat4x4<f32>(
@@ -71,7 +99,10 @@ fn vs_main(
 
 
     var out: VertexOutput;
-    var pos: vec2<f32> = ( boundry_box.size * quadVertices[vertexId] / 2.0 ) + boundry_box.center;
+    var corner = boundry_box.size * quadVertices[vertexId] / 2.0;
+    let c = cos(boundry_box.angle);
+    let s = sin(boundry_box.angle);
+    var pos: vec2<f32> = vec2<f32>(c * corner.x - s * corner.y, s * corner.x + c * corner.y) + boundry_box.center;
     out.clip_position = OPENGL_TO_WGPU_MATRIX * proj.matrix * view.matrix * vec4<f32>(pos, 0.0, 1.0);
     out.fragCoords = pos;
     return out; 
@@ -87,11 +118,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return get_color(pressure);
 }
 
-const PI: f32 = 3.14159265359;
-const SMOOTHING_RADIUS: f32 = 0.4;
-const TARGET_DENSITY: f32 = 10.0;
-
 fn get_color(pressure: f32) -> vec4<f32> {
+    let target_density = params.target_density;
     // Define the colors for the gradient
     let color_neg = vec4<f32>(0.0, 0.0, 1.0, 0.8); // Blue for very low pressure
     let color_low = vec4<f32>(0.0, 1.0, 1.0, 0.8); // Cyan for low pressure
@@ -104,15 +132,15 @@ fn get_color(pressure: f32) -> vec4<f32> {
     // Map pressure to color
     if (pressure < 0.0) {
         // Interpolate between blue and cyan for negative pressures
-        let t = clamp((pressure + TARGET_DENSITY) / TARGET_DENSITY, 0.0, 1.0);
+        let t = clamp((pressure + target_density) / target_density, 0.0, 1.0);
         color = mix(color_neg, color_low, t);
     } else {
         // Interpolate between white and red for positive pressures
-        if (pressure < TARGET_DENSITY) {
-            let t = clamp(pressure / TARGET_DENSITY, 0.0, 1.0);
+        if (pressure < target_density) {
+            let t = clamp(pressure / target_density, 0.0, 1.0);
             color = mix(color_low, color_target, t);
         } else {
-            let t = clamp((pressure - TARGET_DENSITY) / TARGET_DENSITY, 0.0, 1.0);
+            let t = clamp((pressure - target_density) / target_density, 0.0, 1.0);
             color = mix(color_target, color_high, t);
             color = mix(color_high, color_pos, t);
         }
@@ -123,12 +151,12 @@ fn get_color(pressure: f32) -> vec4<f32> {
 
 fn interpolation(pos: vec2<f32>) -> f32 {
     var density = density_at_pos(pos);
-    return density - TARGET_DENSITY; 
+    return density - params.target_density;
 }
 
 fn density_at_pos(particle_position: vec2<f32>) -> f32 {
     var density = 0.0;
-    var sqrRadius = SMOOTHING_RADIUS * SMOOTHING_RADIUS;
+    var sqrRadius = params.smoothing_radius * params.smoothing_radius;
 
     for (var i: u32 = 0; i < num_particles; i++) {
         var neighbourPos = p_position[i].position.xy;
@@ -141,24 +169,8 @@ fn density_at_pos(particle_position: vec2<f32>) -> f32 {
         }
 
         var dist = sqrt(sqrDstToNeighbour);
-        density += smoothing_kernel_spiky(SMOOTHING_RADIUS, dist);
+        density += smoothing_kernel(params.kernel, params.smoothing_radius, dist);
 
     }
     return max(density, 0.1);
 }
-
-fn smoothing_kernel_spiky(s_rad: f32, dist: f32) -> f32 {
-    if (dist > s_rad) { return 0.0; }
-
-    var v: f32 = s_rad - dist;
-    var volume: f32 = PI * pow(s_rad, 4.0) / 6.0;
-    return v * v / volume;
-}
-
-// fm smoothing_kernel_spikey_near(s_rad: f32, dist: f32) -> f32 {
-//     if (dist > s_rad) { return 0.0; }
-
-//     var v: f32 = s_rad - dist;
-//     var volume: f32 = PI * pow(s_rad, 4.0) / 6.0;
-//     return v * v / volume;
-// }
\ No newline at end of file
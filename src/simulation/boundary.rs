@@ -0,0 +1,292 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use serde::Deserialize;
+use super::container::BoundaryMode;
+use super::cpu_solver::{counting_sort, hash_position, Bounds};
+use super::grid::HashCell;
+use super::params::SimParams;
+
+// upper bound of boundary particles the gpu buffers hold, a larger container samples its
+// walls more coarsely instead
+pub const MAX_BOUNDARY_PARTICLES: usize = 8192;
+// distance between two boundary particles along a wall, in smoothing radii
+pub(super) const BOUNDARY_SPACING: f32 = 0.25;
+
+const NEIGHBOR_OFFSETS_2D: [Vector2<i32>; 9] = [
+    Vector2 { x: -1, y: -1 }, Vector2 { x: 0, y: -1 }, Vector2 { x: 1, y: -1 },
+    Vector2 { x: -1, y: 0 }, Vector2 { x: 0, y: 0 }, Vector2 { x: 1, y: 0 },
+    Vector2 { x: -1, y: 1 }, Vector2 { x: 0, y: 1 }, Vector2 { x: 1, y: 1 },
+];
+
+// how the container keeps the fluid in, `[container] walls` in a scene file
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Walls {
+    // positions mirrored and velocities flipped at the edges, checkBoundaries in the shader
+    #[default]
+    Reflective,
+    // a layer of fixed particles along the edges that takes part in the density and pressure
+    // sums. the reflective clamp stays behind it for whatever still gets through
+    Particles,
+}
+
+// one fixed particle on a wall, mirrors `BoundaryParticle` in simulation.wgsl. `psi` stands in
+// for its mass, `cell_key` is its spatial hash bucket
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct BoundaryParticle {
+    pub position: Vector2<f32>,
+    pub psi: f32,
+    pub cell_key: u32,
+}
+
+unsafe impl Pod for BoundaryParticle {}
+unsafe impl Zeroable for BoundaryParticle {}
+
+// what the boundary particles around one fluid particle add to its sums, mirrors `BoundaryData`
+// in simulation.wgsl. a boundary particle takes the pressure of the fluid particle it is seen
+// from, so these sums are all the solvers need of it. the rigid bodies add theirs as well
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct BoundaryData {
+    // sum_b psi_b grad W_ib
+    pub gradient: Vector3<f32>,
+    // sum_b psi_b W_ib
+    pub density: f32,
+    pub near_gradient: Vector3<f32>,
+    pub near_density: f32,
+    // sum_b psi_b v_b . grad W_ib, only the body particles move. the walls are taken to stand still
+    pub flux: f32,
+    _padding: [f32; 3],
+}
+
+unsafe impl Pod for BoundaryData {}
+unsafe impl Zeroable for BoundaryData {}
+
+impl Default for BoundaryData {
+    fn default() -> Self {
+        Self::zeroed()
+    }
+}
+
+// everything the sampling depends on, the walls are sampled again once any of it changes
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Sampling {
+    walls: Walls,
+    size: [f32; 2],
+    modes: [BoundaryMode; 2],
+    radius: f32,
+    smoothing_radius: f32,
+    kernel: u32,
+    target_density: f32,
+    table_size: usize,
+}
+
+// the boundary particles of the container, Akinci et al. 2012. binned into the same spatial
+// hash buckets as the fluid, so the 3x3 stencil of a fluid particle finds them as well
+#[derive(Debug, Clone, Default)]
+pub struct Boundary {
+    // sorted by `cell_key`
+    pub particles: Vec<BoundaryParticle>,
+    // first particle of every bucket, `particles.len()` for an empty one
+    pub start_indices: Vec<u32>,
+    sampling: Option<Sampling>,
+}
+
+impl Boundary {
+    // samples the walls again if the container or the parameters changed since the last time,
+    // returns whether it did so the gpu copy can follow
+    pub fn update(&mut self, walls: Walls, size: [f32; 2], modes: [BoundaryMode; 2], radius: f32, params: &SimParams, table_size: usize) -> bool {
+        let sampling = Sampling {
+            walls,
+            size,
+            modes,
+            radius,
+            smoothing_radius: params.smoothing_radius,
+            kernel: params.kernel,
+            target_density: params.target_density,
+            table_size,
+        };
+        if self.sampling == Some(sampling) {
+            return false;
+        }
+        *self = Self::sample(walls, size, modes, radius, params, table_size);
+        self.sampling = Some(sampling);
+        true
+    }
+
+    pub fn sample(walls: Walls, size: [f32; 2], modes: [BoundaryMode; 2], radius: f32, params: &SimParams, table_size: usize) -> Self {
+        let positions = match walls {
+            Walls::Reflective => Vec::new(),
+            Walls::Particles => sample_walls(Vector2::from(size), modes, params.smoothing_radius * BOUNDARY_SPACING),
+        };
+        Self::from_positions(&positions, size, modes, radius, params, table_size)
+    }
+
+    fn from_positions(positions: &[Vector2<f32>], size: [f32; 2], modes: [BoundaryMode; 2], radius: f32, params: &SimParams, table_size: usize) -> Self {
+        // the particles sit in the frame of the container, so it is at rest on the origin here
+        let bounds = Bounds { modes, ..Bounds::at_rest(Vector2::new(0.0, 0.0), Vector2::from(size)) };
+        let half_boundries = Vector2::from(size) / 2.0 - Vector2::new(radius, radius);
+        let keys: Vec<u32> = positions
+            .iter()
+            .map(|p| hash_position(bounds.cell(*p, half_boundries, params.cell_size()), table_size))
+            .collect();
+        let mut sorted: Vec<HashCell> = Vec::new();
+        let mut start_indices = vec![0; table_size];
+        counting_sort(&keys, table_size, &mut sorted, &mut start_indices);
+
+        let mut boundary = Self {
+            particles: sorted
+                .iter()
+                .map(|cell| BoundaryParticle { position: positions[cell.particle_index as usize], psi: 0.0, cell_key: cell.cell_index })
+                .collect(),
+            start_indices,
+            sampling: None,
+        };
+
+        // psi_b = rho_0 / sum_k W_bk, the sum includes the particle itself. a densely sampled
+        // stretch of wall gives each particle less weight, so the wall adds the same density
+        // however it was sampled
+        let psis: Vec<f32> = boundary.particles
+            .iter()
+            .map(|particle| {
+                let mut volume = 0.0;
+                boundary.for_each_neighbour(particle.position.extend(0.0), params, bounds, half_boundries, |_, _, dist| {
+                    volume += params.kernel().value(params.smoothing_radius, dist);
+                });
+                params.target_density / volume
+            })
+            .collect();
+        for (particle, psi) in boundary.particles.iter_mut().zip(psis) {
+            particle.psi = psi;
+        }
+        boundary
+    }
+
+    // calls `f(particle, offset_to_particle, distance)` for every boundary particle inside the
+    // smoothing radius of `position`, the same stencil walk as for_each_neighbour in cpu_solver.
+    // `position` and the offsets are in the frame of the container
+    pub fn for_each_neighbour<F: FnMut(&BoundaryParticle, Vector3<f32>, f32)>(&self, position: Vector3<f32>, params: &SimParams, bounds: Bounds, half_boundries: Vector2<f32>, mut f: F) {
+        if self.particles.is_empty() {
+            return;
+        }
+        let norm_position = bounds.cell(position.truncate(), half_boundries, params.cell_size());
+        let sqr_radius = params.smoothing_radius * params.smoothing_radius;
+
+        let table_size = self.start_indices.len();
+        let mut visited = [u32::MAX; 9];
+        for (i, offset) in NEIGHBOR_OFFSETS_2D.iter().enumerate() {
+            let hash_key = hash_position(bounds.wrap_cell(norm_position + *offset, params.cell_size()), table_size);
+            if visited[..i].contains(&hash_key) {
+                continue;
+            }
+            visited[i] = hash_key;
+
+            let mut curr_index = self.start_indices[hash_key as usize] as usize;
+            while curr_index < self.particles.len() && self.particles[curr_index].cell_key == hash_key {
+                let particle = &self.particles[curr_index];
+                curr_index += 1;
+
+                let offset_to_particle = bounds.nearest_local_image(particle.position - position.truncate()).extend(0.0);
+                let sqr_dst = offset_to_particle.magnitude2();
+                if sqr_dst > sqr_radius {
+                    continue;
+                }
+                f(particle, offset_to_particle, sqr_dst.sqrt());
+            }
+        }
+    }
+}
+
+// a single layer of particles around the edges of a container centred on the origin, evenly
+// spaced along every wall with one particle in each corner. only the walls of reflective axes,
+// the spacing carries on across a periodic edge
+fn sample_walls(size: Vector2<f32>, modes: [BoundaryMode; 2], spacing: f32) -> Vec<Vector2<f32>> {
+    let perimeter = 2.0 * (size.x + size.y);
+    let mut spacing = spacing;
+    if perimeter / spacing > MAX_BOUNDARY_PARTICLES as f32 {
+        spacing = perimeter / MAX_BOUNDARY_PARTICLES as f32;
+        log::info!("Container needs more than {} boundary particles, spacing them {:.3} apart", MAX_BOUNDARY_PARTICLES, spacing);
+    }
+
+    let half = size / 2.0;
+    let columns = ((size.x / spacing).ceil() as usize).max(1);
+    let rows = ((size.y / spacing).ceil() as usize).max(1);
+    let step = Vector2::new(size.x / columns as f32, size.y / rows as f32);
+
+    let mut positions = Vec::with_capacity(2 * (columns + rows));
+    if modes[1] == BoundaryMode::Reflective {
+        for column in 0..columns {
+            positions.push(Vector2::new(-half.x + column as f32 * step.x, -half.y));
+            positions.push(Vector2::new(half.x - column as f32 * step.x, half.y));
+        }
+    }
+    if modes[0] == BoundaryMode::Reflective {
+        for row in 0..rows {
+            positions.push(Vector2::new(half.x, -half.y + row as f32 * step.y));
+            positions.push(Vector2::new(-half.x, half.y - row as f32 * step.y));
+        }
+    }
+    positions.truncate(MAX_BOUNDARY_PARTICLES);
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a fluid particle in the middle of a wall sees the same boundary density however finely
+    // the wall is sampled, that is what psi is for
+    #[test]
+    fn boundary_density_does_not_depend_on_spacing() {
+        let params = SimParams::default();
+        let size = Vector2::new(20.0, 20.0);
+        let half_boundries = size / 2.0;
+        let position = Vector3::new(0.0, -size.y / 2.0 + 0.3 * params.smoothing_radius, 0.0);
+
+        let densities: Vec<f32> = [0.1, 0.2, 0.3]
+            .iter()
+            .map(|spacing| {
+                let modes = [BoundaryMode::Reflective; 2];
+                let positions = sample_walls(size, modes, spacing * params.smoothing_radius);
+                let boundary = Boundary::from_positions(&positions, size.into(), modes, 0.0, &params, 257);
+                let bounds = Bounds::at_rest(Vector2::new(0.0, 0.0), size);
+
+                let mut density = 0.0;
+                boundary.for_each_neighbour(position, &params, bounds, half_boundries, |particle, _, dist| {
+                    density += particle.psi * params.kernel().value(params.smoothing_radius, dist);
+                });
+                density
+            })
+            .collect();
+        assert!(densities[0] > 0.0);
+        for density in &densities {
+            assert!((density - densities[0]).abs() < 0.05 * densities[0], "{:?}", densities);
+        }
+    }
+
+    // with a periodic floor the boundary particles on the far side of the seam are found too,
+    // so a particle right at the edge sees as much wall as one in the middle
+    #[test]
+    fn periodic_wall_continues_across_the_edge() {
+        let params = SimParams::default();
+        let size = Vector2::new(20.0, 10.0);
+        let modes = [BoundaryMode::Periodic, BoundaryMode::Reflective];
+        let bounds = Bounds { modes, ..Bounds::at_rest(Vector2::new(0.0, 0.0), size) };
+        let boundary = Boundary::sample(Walls::Particles, size.into(), modes, 0.0, &params, 257);
+        assert!(boundary.particles.iter().all(|particle| particle.position.y.abs() == size.y / 2.0));
+
+        let density_at = |x: f32| {
+            let position = Vector3::new(x, -size.y / 2.0 + 0.3 * params.smoothing_radius, 0.0);
+            let mut density = 0.0;
+            boundary.for_each_neighbour(position, &params, bounds, size / 2.0, |particle, _, dist| {
+                density += particle.psi * params.kernel().value(params.smoothing_radius, dist);
+            });
+            density
+        };
+        let middle = density_at(0.0);
+        for x in [-size.x / 2.0, size.x / 2.0 - 0.01, size.x / 2.0 - 0.3 * params.smoothing_radius] {
+            assert!((density_at(x) - middle).abs() < 0.05 * middle, "{} at {} against {}", density_at(x), x, middle);
+        }
+    }
+}
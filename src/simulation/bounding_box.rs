@@ -2,12 +2,18 @@ use cgmath::Vector2;
 use bytemuck::{Pod, Zeroable};
 use wgpu::{util::DeviceExt, BindGroupLayout};
 
+use super::container::BoundaryMode;
+use super::cpu_solver::Bounds;
 use crate::state::{managers::pipeline_manager::PipelineManager, shader_helper::create_shader_module};
 
 
+// `position` is where the container rests and `velocity` how fast that moves while it is dragged
+// around with the mouse, the scripted motion of the scene is applied on top of both
 pub struct BoundingBox {
     position: Vector2<f32>,
     size: Vector2<f32>,
+    velocity: Vector2<f32>,
+    modes: [BoundaryMode; 2],
     pub buffer: wgpu::Buffer,
     binding: BoundingBoxLl,
     pub vertex_buffer: wgpu::Buffer,
@@ -20,9 +26,9 @@ pub struct BoundingBox {
 
 impl BoundingBox {
 
-    pub fn new(position: Vector2<f32>, size: Vector2<f32>, device: &wgpu::Device, pipeline_manager: &PipelineManager, camera_group_layout: &BindGroupLayout) -> Self {
+    pub fn new(position: Vector2<f32>, size: Vector2<f32>, modes: [BoundaryMode; 2], device: &wgpu::Device, pipeline_manager: &PipelineManager, camera_group_layout: &BindGroupLayout) -> Self {
 
-        let binding = Self::make_binding(position, size);
+        let binding = Self::make_binding(Bounds { modes, ..Bounds::at_rest(position, size) });
         let buffer = Self::make_buffer(device, binding);
         let vertex_buffer = Self::make_vertex_buffer(device);
         let index_buffer = Self::make_index_buffer(device);
@@ -36,6 +42,8 @@ impl BoundingBox {
         Self {
             position,
             size,
+            velocity: Vector2::new(0.0, 0.0),
+            modes,
             buffer,
             binding,
             vertex_buffer,
@@ -47,6 +55,20 @@ impl BoundingBox {
         }
     }
 
+    // the storage buffer alone, for running the simulation without anything to draw on
+    pub fn create_buffer(device: &wgpu::Device, origin: Bounds) -> wgpu::Buffer {
+        Self::make_buffer(device, Self::make_binding(origin))
+    }
+
+    // only the part the cpu owns, the size, the edges and where the container rests. the gpu
+    // backend moves the container from there in update_container and keeps the rest of the
+    // buffer to itself
+    pub fn write_buffer(queue: &wgpu::Queue, buffer: &wgpu::Buffer, origin: Bounds) {
+        let binding = BoundingBoxLl::new(origin);
+        let prefix = std::mem::offset_of!(BoundingBoxLl, angle);
+        queue.write_buffer(buffer, 0, &bytemuck::bytes_of(&binding)[..prefix]);
+    }
+
     fn make_buffer(device: &wgpu::Device, binding: BoundingBoxLl) -> wgpu::Buffer {
         device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
@@ -125,8 +147,8 @@ impl BoundingBox {
         (vert_bounding_box_shader, frag_bounding_box_shader)
     }
 
-    fn make_binding(position: Vector2<f32>, size: Vector2<f32>) -> BoundingBoxLl {
-        BoundingBoxLl::new(position, size)
+    fn make_binding(bounds: Bounds) -> BoundingBoxLl {
+        BoundingBoxLl::new(bounds)
     }
 
     fn make_pipeline_layout(device: &wgpu::Device, bind_group_layout: &BindGroupLayout, camera_group_layout: &BindGroupLayout) -> wgpu::PipelineLayout {
@@ -157,28 +179,75 @@ impl BoundingBox {
         )
     }
 
-    pub fn update(&self, queue: &wgpu::Queue) {
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn size(&self) -> Vector2<f32> {
+        self.size
+    }
+
+    // how often every particle is drawn, itself and its ghosts a container length over across
+    // each periodic axis. the ghosts follow the particles in the instance range of particle.vert
+    pub fn num_images(&self) -> u32 {
+        self.modes.iter().map(|mode| if *mode == BoundaryMode::Periodic { 3 } else { 1 }).product()
+    }
+
+    // the container at rest, what the cpu solver applies the scripted motion to
+    pub fn origin(&self) -> Bounds {
+        Bounds { velocity: self.velocity, modes: self.modes, ..Bounds::at_rest(self.position, self.size) }
+    }
+
+    pub fn set_size(&mut self, size: Vector2<f32>, queue: &wgpu::Queue) {
+        self.size = size;
+        Self::write_buffer(queue, &self.buffer, self.origin());
+    }
+
+    pub fn set_position(&mut self, position: Vector2<f32>, velocity: Vector2<f32>, queue: &wgpu::Queue) {
+        self.position = position;
+        self.velocity = velocity;
+        Self::write_buffer(queue, &self.buffer, self.origin());
+    }
+
+    // where the cpu solver has moved the container, the gpu backend writes that on its own
+    pub fn update(&mut self, bounds: Bounds, queue: &wgpu::Queue) {
+        self.binding = BoundingBoxLl { origin: self.position, origin_velocity: self.velocity, ..Self::make_binding(bounds) };
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.binding]));
     }
 
 }
 
 
+// mirrors `BoundryBox` in simulation.wgsl. everything up to `angle` is written by the cpu, the
+// rest is where the container is this step
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 struct BoundingBoxLl {
-    position: Vector2<f32>,
     size: Vector2<f32>,
+    origin: Vector2<f32>,
+    origin_velocity: Vector2<f32>,
+    // a `BoundaryMode` as u32 for each axis
+    modes: [u32; 2],
+    angle: f32,
+    angular_velocity: f32,
+    center: Vector2<f32>,
+    velocity: Vector2<f32>,
 }
 
 unsafe impl Pod for BoundingBoxLl {}
 unsafe impl Zeroable for BoundingBoxLl {}
 
 impl BoundingBoxLl {
-    fn new(position: Vector2<f32>, size: Vector2<f32>) -> Self {
+    fn new(bounds: Bounds) -> Self {
         Self {
-            position,
-            size,
+            size: bounds.size,
+            origin: bounds.center,
+            origin_velocity: bounds.velocity,
+            modes: bounds.modes.map(|mode| mode as u32),
+            angle: bounds.angle,
+            angular_velocity: bounds.angular_velocity,
+            center: bounds.center,
+            velocity: bounds.velocity,
         }
     }
 }
@@ -193,6 +262,10 @@ unsafe impl Pod for Vertex {}
 unsafe impl Zeroable for Vertex {}
 
 impl Vertex{
+    pub fn new(position: Vector2<f32>) -> Self {
+        Self { position }
+    }
+
     const ATTRIBS: [wgpu::VertexAttribute; 1] = 
         wgpu::vertex_attr_array![0 => Float32x2];
 
@@ -0,0 +1,1518 @@
+use wgpu::util::DeviceExt;
+use std::iter;
+use super::grid::Grid;
+use super::simulation::WaterSimulation;
+use super::time_step::TimeStep;
+use super::solver::{Solver, SolverStats};
+use crate::utils::readback::read_buffer;
+use crate::state::camera::camera_controller::MouseDelta;
+use crate::state::shader_helper;
+
+// everything the compute passes need, shared by the windowed state and the headless simulator
+pub struct SimulationCompute {
+    pub particle_bind_layout: wgpu::BindGroupLayout,
+    pub particle_bind_group: wgpu::BindGroup,
+    pub settings_bind_layout: wgpu::BindGroupLayout,
+    pub settings_bind_group: wgpu::BindGroup,
+    pub camera_inverse_bind_group: wgpu::BindGroup,
+
+    pub time_step_buffer: wgpu::Buffer,
+    pub max_particles_buffer: wgpu::Buffer,
+    pub pressed_buffer: wgpu::Buffer,
+    pub mouse_delta_buffer: wgpu::Buffer,
+    pub cheat_depth_buffer: wgpu::Buffer,
+    pub solver_stats_buffer: wgpu::Buffer,
+
+    pub grid: Grid,
+
+    pub predict_position_pipeline: wgpu::ComputePipeline,
+    pub calculate_boundary_pipeline: wgpu::ComputePipeline,
+    pub resolve_obstacles_pipeline: wgpu::ComputePipeline,
+    pub collide_with_bodies_pipeline: wgpu::ComputePipeline,
+    pub integrate_bodies_pipeline: wgpu::ComputePipeline,
+    pub calculate_density_pipeline: wgpu::ComputePipeline,
+    pub update_position_pipeline: wgpu::ComputePipeline,
+    pub update_spatial_hash_pipeline: wgpu::ComputePipeline,
+    pub clear_cell_counts_pipeline: wgpu::ComputePipeline,
+    pub scan_cell_counts_pipeline: wgpu::ComputePipeline,
+    pub scatter_spatial_hash_pipeline: wgpu::ComputePipeline,
+    pub viscosity_pipeline: wgpu::ComputePipeline,
+    pub apply_viscosity_pipeline: wgpu::ComputePipeline,
+    pub surface_normal_pipeline: wgpu::ComputePipeline,
+    pub surface_tension_pipeline: wgpu::ComputePipeline,
+    pub vorticity_pipeline: wgpu::ComputePipeline,
+    pub vorticity_confinement_pipeline: wgpu::ComputePipeline,
+    pub update_time_step_pipeline: wgpu::ComputePipeline,
+
+    pub pbf_predict_position_pipeline: wgpu::ComputePipeline,
+    pub pbf_calculate_lambda_pipeline: wgpu::ComputePipeline,
+    pub pbf_calculate_delta_pipeline: wgpu::ComputePipeline,
+    pub pbf_apply_delta_pipeline: wgpu::ComputePipeline,
+    pub pbf_update_velocity_pipeline: wgpu::ComputePipeline,
+
+    pub dfsph_begin_step_pipeline: wgpu::ComputePipeline,
+    pub dfsph_compute_factors_pipeline: wgpu::ComputePipeline,
+    pub dfsph_divergence_error_pipeline: wgpu::ComputePipeline,
+    pub dfsph_check_divergence_pipeline: wgpu::ComputePipeline,
+    pub dfsph_apply_divergence_pipeline: wgpu::ComputePipeline,
+    pub dfsph_external_forces_pipeline: wgpu::ComputePipeline,
+    pub dfsph_density_error_pipeline: wgpu::ComputePipeline,
+    pub dfsph_check_density_pipeline: wgpu::ComputePipeline,
+    pub dfsph_apply_density_pipeline: wgpu::ComputePipeline,
+    pub dfsph_update_position_pipeline: wgpu::ComputePipeline,
+
+    pub iisph_predict_velocity_pipeline: wgpu::ComputePipeline,
+    pub iisph_calculate_density_pipeline: wgpu::ComputePipeline,
+    pub iisph_prepare_pipeline: wgpu::ComputePipeline,
+    pub iisph_sum_dij_pipeline: wgpu::ComputePipeline,
+    pub iisph_pressure_error_pipeline: wgpu::ComputePipeline,
+    pub iisph_check_pressure_pipeline: wgpu::ComputePipeline,
+    pub iisph_apply_pressure_pipeline: wgpu::ComputePipeline,
+    pub iisph_update_position_pipeline: wgpu::ComputePipeline,
+}
+
+impl SimulationCompute {
+    // features and limits the compute passes rely on, the storage limit comes from the adapter
+    // since the particle, settings and grid groups together go over the default of 8
+    pub async fn request_device(adapter: &wgpu::Adapter, label: &str) -> (wgpu::Device, wgpu::Queue) {
+        adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some(label),
+                required_features: wgpu::Features::VERTEX_WRITABLE_STORAGE,
+                required_limits: wgpu::Limits{
+                    max_storage_buffers_per_shader_stage: adapter.limits().max_storage_buffers_per_shader_stage,
+                    ..wgpu::Limits::default()
+                },
+            },
+            None,
+        ).await.expect("device request")
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        water_simulation: &WaterSimulation,
+        radius_buffer: &wgpu::Buffer,
+        bounding_box_buffer: &wgpu::Buffer,
+        proj_view_inv_buffer: &wgpu::Buffer,
+    ) -> Self {
+        let compute_shader = shader_helper::create_shader_module2(device, "Compute Shader", concat!(include_str!("../shader/compute/simulation.wgsl"), include_str!("../shader/common/kernel.wgsl")), naga::ShaderStage::Compute);
+
+        let camera_inverse_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("camera_bind_group_layout"),
+        }); 
+
+        let camera_inverse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_inverse_bind_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: proj_view_inv_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("camera_inverse_bind_group"),
+        });
+
+        let particle_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | 
+                                wgpu::ShaderStages::COMPUTE| 
+                                wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("particle_bind_group_layout"),
+        });
+
+        let particle_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &particle_bind_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: water_simulation.position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: water_simulation.velocity_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: water_simulation.density_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: water_simulation.predicted_position_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: water_simulation.solver_data_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("particle_bind_group"),
+        });
+
+        // written by the kernels only, the first step runs at max_delta_time
+        let time_step_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Time Step Buffer"),
+            contents: bytemuck::cast_slice(&[TimeStep::default()]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let max_particles_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Max Particles Buffer"),
+            contents: bytemuck::cast_slice(&[water_simulation.max_particles as u32]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let pressed_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Pressed Buffer"),
+            contents: bytemuck::cast_slice(&[0u32]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let mouse_delta_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Mouse Delta Buffer"),
+            contents: bytemuck::cast_slice(&[MouseDelta{previous_position: cgmath::vec2(0.0, 0.0), current_position: cgmath::vec2(0.0, 0.0)}]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let cheat_depth_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Cheat Depth Buffer"),
+            contents: bytemuck::cast_slice(&[0.33f32]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // written by the iterative solvers, read back for reporting
+        let solver_stats_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Solver Stats Buffer"),
+            contents: bytemuck::cast_slice(&[SolverStats::default()]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        });
+
+
+        let settings_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 12,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 13,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 14,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 15,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 16,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 17,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 18,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 19,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 20,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 21,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 22,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("settings_bind_layout"),
+        });
+
+        let settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &settings_bind_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: radius_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: water_simulation.num_particles_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: bounding_box_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: time_step_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: max_particles_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: pressed_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: mouse_delta_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: cheat_depth_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: water_simulation.params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: water_simulation.time_step_params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: water_simulation.solver_params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: solver_stats_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 12,
+                    resource: water_simulation.obstacle_shape_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 13,
+                    resource: water_simulation.obstacle_point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 14,
+                    resource: water_simulation.obstacle_info_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 15,
+                    resource: water_simulation.obstacle_field_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 16,
+                    resource: water_simulation.body_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 17,
+                    resource: water_simulation.body_point_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 18,
+                    resource: water_simulation.body_impulse_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 19,
+                    resource: water_simulation.num_bodies_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 20,
+                    resource: water_simulation.container_motion_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 21,
+                    resource: water_simulation.body_sample_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 22,
+                    resource: water_simulation.body_sample_range_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("settings_bind_group"),
+        });
+
+        let grid = Grid::new(device, queue, water_simulation.max_particles, water_simulation.table_size);
+        grid.write_boundary(queue, &water_simulation.boundary);
+
+        let compute_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Simulation Pipeline Layout"),
+            bind_group_layouts: &[
+                &particle_bind_layout,
+                &settings_bind_layout,
+                &grid.grid_bind_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let compute_mouse_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Simulation Pipeline Layout"),
+            bind_group_layouts: &[
+                &particle_bind_layout,
+                &settings_bind_layout,
+                &grid.grid_bind_layout,
+                &camera_inverse_bind_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let predict_position_pipeline = Self::create_compute_pipeline(
+            device,
+            "predict_position_compute_pipeline", 
+            &compute_mouse_layout, 
+            &compute_shader,
+            "predict_position",
+        );
+
+        let calculate_boundary_pipeline = Self::create_compute_pipeline(
+            device,
+            "calculate_boundary_compute_pipeline",
+            &compute_layout,
+            &compute_shader,
+            "calculate_boundary",
+        );
+
+        let resolve_obstacles_pipeline = Self::create_compute_pipeline(
+            device,
+            "resolve_obstacles_compute_pipeline",
+            &compute_layout,
+            &compute_shader,
+            "resolve_obstacles",
+        );
+
+        let collide_with_bodies_pipeline = Self::create_compute_pipeline(
+            device,
+            "collide_with_bodies_compute_pipeline",
+            &compute_layout,
+            &compute_shader,
+            "collide_with_bodies",
+        );
+
+        let integrate_bodies_pipeline = Self::create_compute_pipeline(
+            device,
+            "integrate_bodies_compute_pipeline",
+            &compute_layout,
+            &compute_shader,
+            "integrate_bodies",
+        );
+
+        let calculate_density_pipeline = Self::create_compute_pipeline(
+            device,
+            "calculate_density_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "calculate_density",
+        );
+
+        let update_position_pipeline = Self::create_compute_pipeline(
+            device,
+            "update_position_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "update_position",
+        );
+
+        let update_spatial_hash_pipeline = Self::create_compute_pipeline(
+            device,
+            "spatial_hash_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "update_spatial_hash",
+        );
+
+        let clear_cell_counts_pipeline = Self::create_compute_pipeline(
+            device,
+            "clear_cell_counts_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "clear_cell_counts",
+        );
+
+        let scan_cell_counts_pipeline = Self::create_compute_pipeline(
+            device,
+            "scan_cell_counts_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "scan_cell_counts",
+        );
+
+        let scatter_spatial_hash_pipeline = Self::create_compute_pipeline(
+            device,
+            "scatter_spatial_hash_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "scatter_spatial_hash",
+        );
+
+        let viscosity_pipeline = Self::create_compute_pipeline(
+            device,
+            "viscosity_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "calculate_viscosity",
+        );
+
+        let apply_viscosity_pipeline = Self::create_compute_pipeline(
+            device,
+            "apply_viscosity_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "apply_viscosity",
+        );
+
+        let surface_normal_pipeline = Self::create_compute_pipeline(
+            device,
+            "surface_normal_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "calculate_surface_normal",
+        );
+
+        let surface_tension_pipeline = Self::create_compute_pipeline(
+            device,
+            "surface_tension_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "apply_surface_tension",
+        );
+
+        let vorticity_pipeline = Self::create_compute_pipeline(
+            device,
+            "vorticity_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "calculate_vorticity",
+        );
+
+        let vorticity_confinement_pipeline = Self::create_compute_pipeline(
+            device,
+            "vorticity_confinement_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "apply_vorticity_confinement",
+        );
+
+        let update_time_step_pipeline = Self::create_compute_pipeline(
+            device,
+            "update_time_step_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "update_time_step",
+        );
+
+        let pbf_predict_position_pipeline = Self::create_compute_pipeline(
+            device,
+            "pbf_predict_position_compute_pipeline", 
+            &compute_mouse_layout, 
+            &compute_shader,
+            "pbf_predict_position",
+        );
+
+        let pbf_calculate_lambda_pipeline = Self::create_compute_pipeline(
+            device,
+            "pbf_calculate_lambda_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "pbf_calculate_lambda",
+        );
+
+        let pbf_calculate_delta_pipeline = Self::create_compute_pipeline(
+            device,
+            "pbf_calculate_delta_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "pbf_calculate_delta",
+        );
+
+        let pbf_apply_delta_pipeline = Self::create_compute_pipeline(
+            device,
+            "pbf_apply_delta_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "pbf_apply_delta",
+        );
+
+        let pbf_update_velocity_pipeline = Self::create_compute_pipeline(
+            device,
+            "pbf_update_velocity_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "pbf_update_velocity",
+        );
+
+        let dfsph_begin_step_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_begin_step_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_begin_step",
+        );
+
+        let dfsph_compute_factors_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_compute_factors_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_compute_factors",
+        );
+
+        let dfsph_divergence_error_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_divergence_error_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_divergence_error",
+        );
+
+        let dfsph_check_divergence_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_check_divergence_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_check_divergence",
+        );
+
+        let dfsph_apply_divergence_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_apply_divergence_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_apply_divergence",
+        );
+
+        let dfsph_external_forces_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_external_forces_compute_pipeline", 
+            &compute_mouse_layout, 
+            &compute_shader,
+            "dfsph_external_forces",
+        );
+
+        let dfsph_density_error_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_density_error_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_density_error",
+        );
+
+        let dfsph_check_density_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_check_density_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_check_density",
+        );
+
+        let dfsph_apply_density_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_apply_density_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_apply_density",
+        );
+
+        let dfsph_update_position_pipeline = Self::create_compute_pipeline(
+            device,
+            "dfsph_update_position_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "dfsph_update_position",
+        );
+
+        let iisph_predict_velocity_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_predict_velocity_compute_pipeline", 
+            &compute_mouse_layout, 
+            &compute_shader,
+            "iisph_predict_velocity",
+        );
+
+        let iisph_calculate_density_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_calculate_density_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_calculate_density",
+        );
+
+        let iisph_prepare_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_prepare_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_prepare",
+        );
+
+        let iisph_sum_dij_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_sum_dij_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_sum_dij",
+        );
+
+        let iisph_pressure_error_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_pressure_error_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_pressure_error",
+        );
+
+        let iisph_check_pressure_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_check_pressure_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_check_pressure",
+        );
+
+        let iisph_apply_pressure_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_apply_pressure_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_apply_pressure",
+        );
+
+        let iisph_update_position_pipeline = Self::create_compute_pipeline(
+            device,
+            "iisph_update_position_compute_pipeline", 
+            &compute_layout, 
+            &compute_shader,
+            "iisph_update_position",
+        );
+
+        Self {
+            particle_bind_layout,
+            particle_bind_group,
+            settings_bind_layout,
+            settings_bind_group,
+            camera_inverse_bind_group,
+
+            time_step_buffer,
+            max_particles_buffer,
+            pressed_buffer,
+            mouse_delta_buffer,
+            cheat_depth_buffer,
+            solver_stats_buffer,
+
+            grid,
+
+            predict_position_pipeline,
+            calculate_boundary_pipeline,
+            resolve_obstacles_pipeline,
+            collide_with_bodies_pipeline,
+            integrate_bodies_pipeline,
+            calculate_density_pipeline,
+            update_position_pipeline,
+            update_spatial_hash_pipeline,
+            clear_cell_counts_pipeline,
+            scan_cell_counts_pipeline,
+            scatter_spatial_hash_pipeline,
+            viscosity_pipeline,
+            apply_viscosity_pipeline,
+            surface_normal_pipeline,
+            surface_tension_pipeline,
+            vorticity_pipeline,
+            vorticity_confinement_pipeline,
+            update_time_step_pipeline,
+
+            pbf_predict_position_pipeline,
+            pbf_calculate_lambda_pipeline,
+            pbf_calculate_delta_pipeline,
+            pbf_apply_delta_pipeline,
+            pbf_update_velocity_pipeline,
+
+            dfsph_begin_step_pipeline,
+            dfsph_compute_factors_pipeline,
+            dfsph_divergence_error_pipeline,
+            dfsph_check_divergence_pipeline,
+            dfsph_apply_divergence_pipeline,
+            dfsph_external_forces_pipeline,
+            dfsph_density_error_pipeline,
+            dfsph_check_density_pipeline,
+            dfsph_apply_density_pipeline,
+            dfsph_update_position_pipeline,
+
+            iisph_predict_velocity_pipeline,
+            iisph_calculate_density_pipeline,
+            iisph_prepare_pipeline,
+            iisph_sum_dij_pipeline,
+            iisph_pressure_error_pipeline,
+            iisph_check_pressure_pipeline,
+            iisph_apply_pressure_pipeline,
+            iisph_update_position_pipeline,
+        }
+    }
+
+    fn create_compute_pipeline(
+        device: &wgpu::Device,
+        label: &str,
+        layout: &wgpu::PipelineLayout,
+        compute_shader: &wgpu::ShaderModule,
+        entery_point: &str,
+    ) -> wgpu::ComputePipeline {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor   {
+            label: Some(label),
+            layout: Some(layout),
+            module: compute_shader,
+            entry_point: entery_point,
+            compilation_options: Default::default(),
+        })
+    }
+
+    // one full simulation step, the uniforms are expected to be written already
+    pub fn step(&self, device: &wgpu::Device, queue: &wgpu::Queue, water_simulation: &WaterSimulation) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Compute Encoder")
+        });
+        self.encode(&mut encoder, water_simulation);
+        queue.submit(iter::once(encoder.finish()));
+    }
+
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder, water_simulation: &WaterSimulation) {
+        let mut compute_pass = encoder.begin_compute_pass(
+            &wgpu::ComputePassDescriptor { 
+                label: Some("Compute Pass"), timestamp_writes: None,
+        });
+        let workgroups = water_simulation.num_particles.div_ceil(16);
+
+        // picks this step's delta time from the maxima the last step left behind
+        compute_pass.set_pipeline(&self.update_time_step_pipeline);
+        self.set_bind_groups(&mut compute_pass);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+
+        match water_simulation.solver {
+            Solver::Sph => self.encode_sph(&mut compute_pass, workgroups),
+            Solver::Pbf => self.encode_pbf(&mut compute_pass, workgroups, water_simulation.solver_params.iterations),
+            Solver::Dfsph => self.encode_dfsph(&mut compute_pass, workgroups, water_simulation.solver_params.max_iterations),
+            Solver::Iisph => self.encode_iisph(&mut compute_pass, workgroups, water_simulation.solver_params.max_iterations),
+        }
+        self.encode_obstacles(&mut compute_pass, workgroups);
+        if !water_simulation.bodies.is_empty() {
+            self.encode_bodies(&mut compute_pass, workgroups, water_simulation.bodies.len() as u32);
+        }
+    }
+
+    fn encode_sph<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        // particle predictioning
+        compute_pass.set_pipeline(&self.predict_position_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.set_bind_group(3, &self.camera_inverse_bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_spatial_hash(compute_pass, workgroups);
+        self.encode_boundary(compute_pass, workgroups);
+
+        // particle density calculation
+        compute_pass.set_pipeline(&self.calculate_density_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        //viscosity calculation
+        self.encode_viscosity(compute_pass, workgroups);
+        self.encode_surface_tension(compute_pass, workgroups);
+        self.encode_vorticity_confinement(compute_pass, workgroups);
+
+        // particle force calculation
+        compute_pass.set_pipeline(&self.update_position_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // predict with the full step, then project the density constraints on the predicted
+    // positions `iterations` times and derive the velocity from how far they moved
+    fn encode_pbf<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32, iterations: u32) {
+        compute_pass.set_pipeline(&self.pbf_predict_position_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.set_bind_group(3, &self.camera_inverse_bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_spatial_hash(compute_pass, workgroups);
+
+        for _ in 0..iterations {
+            self.encode_boundary(compute_pass, workgroups);
+
+            compute_pass.set_pipeline(&self.pbf_calculate_lambda_pipeline);
+            self.set_bind_groups(compute_pass);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+            compute_pass.set_pipeline(&self.pbf_calculate_delta_pipeline);
+            self.set_bind_groups(compute_pass);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+            compute_pass.set_pipeline(&self.pbf_apply_delta_pipeline);
+            self.set_bind_groups(compute_pass);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+
+        compute_pass.set_pipeline(&self.pbf_update_velocity_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_viscosity(compute_pass, workgroups);
+        self.encode_surface_tension(compute_pass, workgroups);
+        self.encode_vorticity_confinement(compute_pass, workgroups);
+    }
+
+    // hash and factors on the positions, make the velocity field divergence-free, add the
+    // external forces, then correct the density the new velocities would lead to
+    fn encode_dfsph<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32, max_iterations: u32) {
+        compute_pass.set_pipeline(&self.dfsph_begin_step_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_spatial_hash(compute_pass, workgroups);
+        self.encode_boundary(compute_pass, workgroups);
+
+        compute_pass.set_pipeline(&self.dfsph_compute_factors_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_iterative_solve(
+            compute_pass,
+            workgroups,
+            max_iterations,
+            &[&self.dfsph_divergence_error_pipeline],
+            &self.dfsph_check_divergence_pipeline,
+            &self.dfsph_apply_divergence_pipeline,
+        );
+
+        compute_pass.set_pipeline(&self.dfsph_external_forces_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.set_bind_group(3, &self.camera_inverse_bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_viscosity(compute_pass, workgroups);
+        self.encode_surface_tension(compute_pass, workgroups);
+        self.encode_vorticity_confinement(compute_pass, workgroups);
+
+        self.encode_iterative_solve(
+            compute_pass,
+            workgroups,
+            max_iterations,
+            &[&self.dfsph_density_error_pipeline],
+            &self.dfsph_check_density_pipeline,
+            &self.dfsph_apply_density_pipeline,
+        );
+
+        compute_pass.set_pipeline(&self.dfsph_update_position_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // the explicit scheme up to the viscosity, without the look ahead of the predicted
+    // positions, then a relaxed jacobi solve of the pressure poisson equation in place of its
+    // pressure force
+    fn encode_iisph<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32, max_iterations: u32) {
+        compute_pass.set_pipeline(&self.iisph_predict_velocity_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.set_bind_group(3, &self.camera_inverse_bind_group, &[]);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_spatial_hash(compute_pass, workgroups);
+        self.encode_boundary(compute_pass, workgroups);
+
+        compute_pass.set_pipeline(&self.iisph_calculate_density_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_viscosity(compute_pass, workgroups);
+        self.encode_surface_tension(compute_pass, workgroups);
+        self.encode_vorticity_confinement(compute_pass, workgroups);
+
+        compute_pass.set_pipeline(&self.iisph_prepare_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        self.encode_iterative_solve(
+            compute_pass,
+            workgroups,
+            max_iterations,
+            &[&self.iisph_sum_dij_pipeline, &self.iisph_pressure_error_pipeline],
+            &self.iisph_check_pressure_pipeline,
+            &self.iisph_apply_pressure_pipeline,
+        );
+
+        compute_pass.set_pipeline(&self.iisph_update_position_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // error -> check -> apply, max_iterations times. the check runs on the gpu and turns the
+    // remaining passes into no-ops once the error is below the tolerance, so nothing is read
+    // back mid step. the last error pass measures what the final correction left.
+    // `error_pipelines` run in order, the last one sums up the error
+    fn encode_iterative_solve<'p>(
+        &'p self,
+        compute_pass: &mut wgpu::ComputePass<'p>,
+        workgroups: u32,
+        max_iterations: u32,
+        error_pipelines: &[&'p wgpu::ComputePipeline],
+        check_pipeline: &'p wgpu::ComputePipeline,
+        apply_pipeline: &'p wgpu::ComputePipeline,
+    ) {
+        for iteration in 0..=max_iterations {
+            for error_pipeline in error_pipelines {
+                compute_pass.set_pipeline(error_pipeline);
+                self.set_bind_groups(compute_pass);
+                compute_pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+
+            compute_pass.set_pipeline(check_pipeline);
+            self.set_bind_groups(compute_pass);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+
+            if iteration == max_iterations {
+                break;
+            }
+            compute_pass.set_pipeline(apply_pipeline);
+            self.set_bind_groups(compute_pass);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+    }
+
+    // what the boundary particles add to the sums of every fluid particle, on the current
+    // predicted positions. zeros while the container has none
+    fn encode_boundary<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        compute_pass.set_pipeline(&self.calculate_boundary_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // pushes whatever the solver moved into an obstacle back out, returns right away while the
+    // scene has none
+    fn encode_obstacles<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        compute_pass.set_pipeline(&self.resolve_obstacles_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // pushes out whatever got past the boundary particles of the bodies, then the bodies move
+    // with that and what the pressure pushed into them
+    fn encode_bodies<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32, num_bodies: u32) {
+        compute_pass.set_pipeline(&self.collide_with_bodies_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        compute_pass.set_pipeline(&self.integrate_bodies_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(num_bodies.div_ceil(16), 1, 1);
+    }
+
+    // viscosity from the velocities before the pass, then applied in a second dispatch
+    fn encode_viscosity<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        compute_pass.set_pipeline(&self.viscosity_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        compute_pass.set_pipeline(&self.apply_viscosity_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // surface normals first, the cohesion and curvature forces need the neighbours' normals.
+    // both passes return right away while the strength is 0
+    fn encode_surface_tension<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        compute_pass.set_pipeline(&self.surface_normal_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        compute_pass.set_pipeline(&self.surface_tension_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // vorticity first, the confinement force needs its gradient. both passes return right away
+    // while the strength is 0
+    fn encode_vorticity_confinement<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        compute_pass.set_pipeline(&self.vorticity_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        compute_pass.set_pipeline(&self.vorticity_confinement_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // particle hashing for faster neighbor search, a counting sort over the hash keys
+    fn encode_spatial_hash<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>, workgroups: u32) {
+        compute_pass.set_pipeline(&self.clear_cell_counts_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups((self.grid.table_size as u32).div_ceil(16), 1, 1);
+
+        compute_pass.set_pipeline(&self.update_spatial_hash_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+
+        // exclusive scan of the counts, also fills start_indices
+        compute_pass.set_pipeline(&self.scan_cell_counts_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(1, 1, 1);
+
+        compute_pass.set_pipeline(&self.scatter_spatial_hash_pipeline);
+        self.set_bind_groups(compute_pass);
+        compute_pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+
+    // delta time of the last step and the simulated time so far
+    pub async fn read_time_step(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> TimeStep {
+        read_buffer::<TimeStep>(device, queue, &self.time_step_buffer, 0..1).await[0]
+    }
+
+    // same as CpuSolver::set_time
+    pub fn write_time(&self, queue: &wgpu::Queue, time: f32) {
+        queue.write_buffer(&self.time_step_buffer, 0, bytemuck::cast_slice(&[TimeStep { time, ..TimeStep::default() }]));
+    }
+
+    // iterations and remaining error of the last step's solves
+    pub async fn read_solver_stats(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> SolverStats {
+        read_buffer::<SolverStats>(device, queue, &self.solver_stats_buffer, 0..1).await[0]
+    }
+
+    fn set_bind_groups<'p>(&'p self, compute_pass: &mut wgpu::ComputePass<'p>) {
+        compute_pass.set_bind_group(0, &self.particle_bind_group, &[]);
+        compute_pass.set_bind_group(1, &self.settings_bind_group, &[]);
+        compute_pass.set_bind_group(2, &self.grid.grid_bind_group, &[]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::cpu_solver::{hash_position, MASS};
+    use super::super::params::SimParams;
+    use super::super::rigid_body::Body;
+    use super::super::scene::{Block, Container, GridConfig, Scene};
+    use super::super::simulation::{Backend, WaterSimulation};
+    use super::super::simulator::Simulator;
+    use super::super::solver::{Solver, SolverParams, DENSITY_SOLVE};
+    use crate::utils::readback::read_buffer;
+    use futures::executor::block_on;
+
+    #[test]
+    #[ignore = "needs a gpu adapter, run with --ignored"]
+    fn spatial_hash_matches_cpu_sort() {
+        // not a power of two, and a prime table smaller than the particle count so buckets collide.
+        // without gravity the particles stand still, so the gpu hashes the uploaded positions
+        let scene = Scene {
+            grid: GridConfig { table_size: Some(1021) },
+            params: SimParams { gravity: 0.0, ..SimParams::default() },
+            blocks: vec![Block::Random { min: [-14.0, -9.0], max: [14.0, 9.0], count: 3001, spacing: Some(0.0), velocity: [0.0, 0.0] }],
+            ..Scene::default()
+        };
+        let mut simulator = block_on(Simulator::new(Backend::Gpu, &scene, false));
+
+        // the keys the shader should come up with, worked out on the cpu before the step
+        let water_simulation = &simulator.water_simulation;
+        let bounds = simulator.bounds;
+        let half_boundries = bounds.size / 2.0 - cgmath::vec2(water_simulation.radius.radius, water_simulation.radius.radius);
+        let keys: Vec<u32> = water_simulation.positions[..water_simulation.num_particles as usize]
+            .iter()
+            .map(|p| {
+                let cell = bounds.cell(bounds.to_local(p.position.truncate()), half_boundries, water_simulation.params.cell_size());
+                hash_position(cell, water_simulation.table_size)
+            })
+            .collect();
+
+        simulator.advance(1);
+
+        let water_simulation = &simulator.water_simulation;
+        let grid = &simulator.compute.grid;
+        let table_size = water_simulation.table_size;
+        assert_eq!(grid.table_size, table_size);
+        let n = water_simulation.num_particles as usize;
+        let sorted = block_on(water_simulation.read_spatial_hash(&simulator.device, &simulator.queue, grid));
+        let start_indices: Vec<u32> = block_on(read_buffer(&simulator.device, &simulator.queue, &grid.start_indices_buffer, 0..table_size));
+
+        // every particle exactly once, in the bin of its own key
+        assert_eq!(sorted.len(), n);
+        let mut seen = vec![false; n];
+        for cell in &sorted {
+            let index = cell.particle_index as usize;
+            assert!(!std::mem::replace(&mut seen[index], true), "particle {} hashed twice", index);
+            assert_eq!(cell.cell_index, keys[index], "key of particle {}", index);
+        }
+
+        let mut expected: Vec<(u32, u32)> = keys.iter().enumerate().map(|(index, key)| (*key, index as u32)).collect();
+        expected.sort();
+
+        // order inside a cell depends on atomics, so compare per cell
+        let mut actual: Vec<(u32, u32)> = sorted.iter().map(|c| (c.cell_index, c.particle_index)).collect();
+        let gpu_bins: Vec<u32> = actual.iter().map(|(bin, _)| *bin).collect();
+        let cpu_bins: Vec<u32> = expected.iter().map(|(bin, _)| *bin).collect();
+        assert_eq!(gpu_bins, cpu_bins);
+        actual.sort();
+        assert_eq!(actual, expected);
+
+        for (cell, start) in start_indices.iter().enumerate() {
+            match cpu_bins.iter().position(|bin| *bin as usize == cell) {
+                Some(first) => assert_eq!(*start as usize, first, "start index of cell {}", cell),
+                None => assert_eq!(*start as usize, water_simulation.max_particles, "empty cell {}", cell),
+            }
+        }
+    }
+
+    // at this spacing the block is a little over the rest density once every particle counts its
+    // own W(0), so the density solve has to run, on both backends
+    #[test]
+    #[ignore = "needs a gpu adapter, run with --ignored"]
+    fn dfsph_solves_compressed_block_to_tolerance() {
+        let solver_params = SolverParams { max_iterations: 100, ..SolverParams::default() };
+        let scene = Scene {
+            solver: Solver::Dfsph,
+            solver_params,
+            blocks: vec![Block::Rectangle { min: [-5.0, -9.5], max: [5.0, 0.0], spacing: Some(0.45), velocity: [0.0, 0.0] }],
+            ..Scene::default()
+        };
+        for backend in [Backend::Cpu, Backend::Gpu] {
+            let mut simulator = block_on(Simulator::new(backend, &scene, false));
+            simulator.advance(1);
+
+            let stats = simulator.solver_stats();
+            assert!(stats.iterations[DENSITY_SOLVE] > 0, "{:?}: {}", backend, stats);
+            assert!(stats.iterations[DENSITY_SOLVE] < solver_params.max_iterations, "{:?}: {}", backend, stats);
+            assert!(stats.error[DENSITY_SOLVE] <= solver_params.tolerance, "{:?}: {}", backend, stats);
+        }
+    }
+
+    // the cpu solver exists to diff the kernels against, so a few steps of a small block have to
+    // end up in the same place on both. float sums run in another order, hence the tolerance
+    #[test]
+    #[ignore = "needs a gpu adapter, run with --ignored"]
+    fn gpu_steps_match_cpu_solver() {
+        let scene = Scene {
+            blocks: vec![Block::Rectangle { min: [-4.0, -9.5], max: [4.0, -2.0], spacing: Some(0.45), velocity: [1.0, 0.0] }],
+            ..Scene::default()
+        };
+        let mut runs = [Backend::Cpu, Backend::Gpu].map(|backend| {
+            let mut simulator = block_on(Simulator::new(backend, &scene, false));
+            simulator.advance(5);
+            simulator.sync();
+            simulator
+        });
+        let [cpu, gpu] = &mut runs;
+        let (cpu, gpu) = (&cpu.water_simulation, &gpu.water_simulation);
+        assert_eq!(cpu.num_particles, gpu.num_particles);
+        let n = cpu.num_particles as usize;
+        assert!(n > 100);
+
+        let mut max_position_error: f32 = 0.0;
+        let mut max_density_error: f32 = 0.0;
+        for i in 0..n {
+            let offset = cpu.positions[i].position - gpu.positions[i].position;
+            max_position_error = max_position_error.max(offset.x.abs().max(offset.y.abs()));
+            let density = cpu.densities[i].density.x;
+            max_density_error = max_density_error.max((density - gpu.densities[i].density.x).abs() / density.max(1e-3));
+        }
+        assert!(max_position_error < 1e-3, "positions differ by up to {}", max_position_error);
+        assert!(max_density_error < 1e-3, "densities differ by up to {}%", max_density_error * 100.0);
+    }
+
+    // a box at half the rest density has to settle with half of it under the surface, which only
+    // happens if the pressure on its boundary particles adds up to the weight of what it displaced.
+    // it keeps bobbing a little on the sloshing fluid, so the depth is averaged
+    #[test]
+    #[ignore = "needs a gpu adapter, run with --ignored"]
+    fn body_at_half_the_rest_density_floats_half_immersed() {
+        let params = SimParams::default();
+        let scene = Scene {
+            solver: Solver::Dfsph,
+            container: Container { size: [16.0, 12.0], ..Container::default() },
+            blocks: vec![Block::Rectangle { min: [-7.8, -5.8], max: [7.8, -1.0], spacing: Some(0.47), velocity: [0.0, 0.0] }],
+            bodies: vec![Body::Polygon {
+                points: vec![[-1.0, -0.5], [1.0, -0.5], [1.0, 1.5], [-1.0, 1.5]],
+                density: params.target_density / 2.0,
+                velocity: [0.0, 0.0],
+                angular_velocity: 0.0,
+                pinned: false,
+            }],
+            params,
+            ..Scene::default()
+        };
+        // the top particles of the columns away from the box, plus the half rest spacing they
+        // fill above themselves
+        let immersion = |water_simulation: &WaterSimulation| {
+            let body = water_simulation.bodies.bodies[0];
+            let mut tops = [f32::MIN; 16];
+            for p in &water_simulation.positions {
+                let column = ((p.position.x + 8.0).floor().max(0.0) as usize).min(15);
+                tops[column] = tops[column].max(p.position.y);
+            }
+            let far: Vec<f32> = tops
+                .iter()
+                .enumerate()
+                .filter(|(column, _)| (*column as f32 - 7.5 - body.position[0]).abs() > 2.5)
+                .map(|(_, top)| *top)
+                .collect();
+            let surface = far.iter().sum::<f32>() / far.len() as f32 + (MASS / params.target_density).sqrt() / 2.0;
+            (surface - (body.position[1] - 1.0)) / 2.0
+        };
+
+        const SAMPLES: u32 = 20;
+        for backend in [Backend::Cpu, Backend::Gpu] {
+            let mut simulator = block_on(Simulator::new(backend, &scene, false));
+            simulator.advance(750);
+            let mut average = 0.0;
+            for _ in 0..SAMPLES {
+                simulator.advance(25);
+                simulator.sync();
+                average += immersion(&simulator.water_simulation) / SAMPLES as f32;
+            }
+            assert!((average - 0.5).abs() < 0.1, "{:?}: {:.0}% under the surface", backend, average * 100.0);
+        }
+    }
+}
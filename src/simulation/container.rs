@@ -0,0 +1,111 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::Vector2;
+use serde::Deserialize;
+use super::cpu_solver::Bounds;
+
+// what an edge of the container does with the fluid, one per axis as `[container] boundary` in a
+// scene file. mirrors the BOUNDARY_ constants in simulation.wgsl
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundaryMode {
+    // walls on both sides that the fluid bounces off
+    #[default]
+    Reflective = 0,
+    // whatever leaves on one side comes back in on the other, and particles near one edge see
+    // the ones near the opposite edge as neighbours. for channel flows and endless domains
+    Periodic = 1,
+    // no walls, the fluid flows out and falls away. particles out there stop limiting the time step
+    Open = 2,
+}
+
+impl BoundaryMode {
+    // unknown values fall back to reflective walls, same as the shader
+    pub fn from_index(index: u32) -> Self {
+        match index {
+            1 => BoundaryMode::Periodic,
+            2 => BoundaryMode::Open,
+            _ => BoundaryMode::Reflective,
+        }
+    }
+}
+
+// scripted motion of the container, `[container.motion]` in a scene file. the centre drifts
+// with `velocity` and swings by `amplitude` around where it started, the walls turn with
+// `angular_velocity` and tilt by `angle_amplitude`. both swings share `frequency` in hertz,
+// angles are in degrees. mirrors `ContainerMotion` in simulation.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContainerMotion {
+    pub velocity: [f32; 2],
+    pub amplitude: [f32; 2],
+    pub angular_velocity: f32,
+    pub angle_amplitude: f32,
+    pub frequency: f32,
+    #[serde(skip)]
+    _padding: f32,
+}
+
+unsafe impl Pod for ContainerMotion {}
+unsafe impl Zeroable for ContainerMotion {}
+
+impl ContainerMotion {
+    pub fn is_moving(&self) -> bool {
+        *self != Self::default()
+    }
+
+    // where the motion has taken a container resting at `origin` after `time` seconds, along
+    // with the velocity of its walls. same as update_container in the shader
+    pub fn apply(&self, origin: Bounds, time: f32) -> Bounds {
+        let omega = 2.0 * std::f32::consts::PI * self.frequency;
+        let (sin, cos) = (omega * time).sin_cos();
+        let velocity = Vector2::from(self.velocity);
+        let amplitude = Vector2::from(self.amplitude);
+        Bounds {
+            center: origin.center + velocity * time + amplitude * sin,
+            velocity: origin.velocity + velocity + amplitude * (omega * cos),
+            angle: (self.angular_velocity * time + self.angle_amplitude * sin).to_radians(),
+            angular_velocity: (self.angular_velocity + self.angle_amplitude * omega * cos).to_radians(),
+            ..origin
+        }
+    }
+
+    // size of the area around the origin the container can reach while it swings and turns,
+    // what anything baked over the container has to cover. a steady drift is left out
+    pub fn reach(&self, size: [f32; 2]) -> [f32; 2] {
+        let mut size = Vector2::from(size);
+        if self.angular_velocity != 0.0 || self.angle_amplitude != 0.0 {
+            let diagonal = (size.x * size.x + size.y * size.y).sqrt();
+            size = Vector2::new(diagonal, diagonal);
+        }
+        [size.x + 2.0 * self.amplitude[0].abs(), size.y + 2.0 * self.amplitude[1].abs()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wall_velocity_matches_motion() {
+        let motion = ContainerMotion {
+            velocity: [0.5, 0.0],
+            amplitude: [2.0, 1.0],
+            angle_amplitude: 10.0,
+            frequency: 0.5,
+            ..Default::default()
+        };
+        let origin = Bounds::at_rest(Vector2::new(1.0, -2.0), Vector2::new(20.0, 10.0));
+        assert_eq!(ContainerMotion::default().apply(origin, 3.0).center, origin.center);
+
+        // the velocity handed to the walls is the derivative of the scripted position
+        let (time, h) = (0.7, 1e-3);
+        let now = motion.apply(origin, time);
+        let before = motion.apply(origin, time - h);
+        let after = motion.apply(origin, time + h);
+        let velocity = (after.center - before.center) / (2.0 * h);
+        let angular_velocity = (after.angle - before.angle) / (2.0 * h);
+        assert!((velocity - now.velocity).x.abs() < 1e-2 && (velocity - now.velocity).y.abs() < 1e-2);
+        assert!((angular_velocity - now.angular_velocity).abs() < 1e-3);
+    }
+}
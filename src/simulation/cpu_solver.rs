@@ -0,0 +1,1354 @@
+use cgmath::{InnerSpace, Vector2, Vector3};
+use rayon::prelude::*;
+use super::boundary::{Boundary, BoundaryData};
+use super::container::BoundaryMode;
+use super::grid::{HashCell, HashStats};
+use super::params::{EquationOfState, SimParams};
+use super::rigid_body::{cross, Bodies, BodyImpulse};
+use super::simulation::WaterSimulation;
+use super::time_step::TimeStep;
+use super::solver::{Solver, SolverParams, SolverStats, DENSITY_SOLVE, DIVERGENCE_SOLVE};
+
+// cpu reference of the compute pipeline in simulation.wgsl, stage for stage.
+// any change to the kernels there has to be mirrored here, otherwise the
+// two backends can no longer be diffed against each other
+
+const PI: f32 = std::f32::consts::PI;
+pub(super) const MASS: f32 = 1.0;
+// see CURVATURE_WEIGHT in simulation.wgsl
+const CURVATURE_WEIGHT: f32 = 0.01;
+// sweeps of the jacobi pressure solve before its average error is trusted, as in the paper
+const IISPH_MIN_ITERATIONS: u32 = 2;
+
+const HV1: u32 = 73856093;
+const HV2: u32 = 19349663;
+
+const NEIGHBOR_OFFSETS_2D: [Vector2<i32>; 9] = [
+    Vector2 { x: -1, y: -1 }, Vector2 { x: 0, y: -1 }, Vector2 { x: 1, y: -1 },
+    Vector2 { x: -1, y: 0 }, Vector2 { x: 0, y: 0 }, Vector2 { x: 1, y: 0 },
+    Vector2 { x: -1, y: 1 }, Vector2 { x: 0, y: 1 }, Vector2 { x: 1, y: 1 },
+];
+
+pub struct CpuSolver {
+    predicted_positions: Vec<Vector3<f32>>,
+    spatial_hash: Vec<HashCell>,
+    start_indices: Vec<u32>,
+    time_step: TimeStep,
+    solver_stats: SolverStats,
+    // iisph pressures, kept for the first guess of the next step
+    pressures: Vec<f32>,
+    boundary_data: Vec<BoundaryData>,
+    // what the fluid pressure pushed into every body this step, through their boundary particles
+    body_impulses: Vec<BodyImpulse>,
+    // where the container is this step, the neighbour search wraps around its periodic edges
+    bounds: Bounds,
+}
+
+// what iisph_prepare leaves in solver_data on the gpu
+struct IisphParticle {
+    a_ii: f32,
+    advected_density: f32,
+    d_ii: Vector3<f32>,
+    density: f32,
+}
+
+// everything a single step needs to know about the container, same as `BoundryBox` in the shader.
+// `angle` turns it around its centre, the velocities are those of its walls. `modes` is what the
+// edges across x and across y do
+#[derive(Debug, Copy, Clone)]
+pub struct Bounds {
+    pub center: Vector2<f32>,
+    pub size: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+    pub angle: f32,
+    pub angular_velocity: f32,
+    pub modes: [BoundaryMode; 2],
+}
+
+impl Bounds {
+    pub fn at_rest(center: Vector2<f32>, size: Vector2<f32>) -> Self {
+        Self { center, size, velocity: Vector2::new(0.0, 0.0), angle: 0.0, angular_velocity: 0.0, modes: [BoundaryMode::Reflective; 2] }
+    }
+
+    pub fn is_periodic(&self, axis: usize) -> bool {
+        self.modes[axis] == BoundaryMode::Periodic
+    }
+
+    // spatial hash cell of a point in the frame of the container, same as grid_cell in the shader.
+    // a periodic axis is split into whole cells no narrower than `cell_size`, so the stencil of a
+    // particle at one edge wraps onto the cells at the other without a gap
+    pub fn cell(&self, local: Vector2<f32>, half_boundries: Vector2<f32>, cell_size: f32) -> Vector2<i32> {
+        let mut cell = get_shifted_2d_pos(local, half_boundries, cell_size);
+        for axis in 0..2 {
+            if self.is_periodic(axis) {
+                let cells = self.periodic_cells(axis, cell_size);
+                let size = self.size[axis];
+                let wrapped = local[axis] - size * (local[axis] / size + 0.5).floor();
+                cell[axis] = (((wrapped / size + 0.5) * cells as f32).floor() as i32).min(cells - 1);
+            }
+        }
+        cell
+    }
+
+    // a stencil cell past a periodic edge is the one at the far side
+    pub fn wrap_cell(&self, cell: Vector2<i32>, cell_size: f32) -> Vector2<i32> {
+        let mut cell = cell;
+        for axis in 0..2 {
+            if self.is_periodic(axis) {
+                cell[axis] = cell[axis].rem_euclid(self.periodic_cells(axis, cell_size));
+            }
+        }
+        cell
+    }
+
+    fn periodic_cells(&self, axis: usize, cell_size: f32) -> i32 {
+        ((self.size[axis] / cell_size).floor() as i32).max(1)
+    }
+
+    // the offset to whichever periodic image of a neighbour is closest, in the frame of the
+    // container. across a periodic edge that one sits a whole container length away
+    pub fn nearest_local_image(&self, offset: Vector2<f32>) -> Vector2<f32> {
+        let mut offset = offset;
+        for axis in 0..2 {
+            if self.is_periodic(axis) {
+                offset[axis] -= self.size[axis] * (offset[axis] / self.size[axis] + 0.5).floor();
+            }
+        }
+        offset
+    }
+
+    // same as nearest_image in the shader
+    pub fn nearest_image(&self, offset: Vector3<f32>) -> Vector3<f32> {
+        if !self.is_periodic(0) && !self.is_periodic(1) {
+            return offset;
+        }
+        let local = self.nearest_local_image(rotate(offset.truncate(), -self.angle));
+        self.rotate(local).extend(offset.z)
+    }
+
+    // brings a point that left through a periodic edge back in at the other one
+    pub fn wrap(&self, p: Vector2<f32>) -> Vector2<f32> {
+        if !self.is_periodic(0) && !self.is_periodic(1) {
+            return p;
+        }
+        let mut local = self.to_local(p);
+        for axis in 0..2 {
+            if self.is_periodic(axis) {
+                local[axis] -= self.size[axis] * (local[axis] / self.size[axis] + 0.5).floor();
+            }
+        }
+        self.to_world(local)
+    }
+
+    // out past an open edge, such a particle no longer limits the time step
+    pub fn has_escaped(&self, p: Vector2<f32>) -> bool {
+        let local = self.to_local(p);
+        (0..2).any(|axis| self.modes[axis] == BoundaryMode::Open && local[axis].abs() > self.size[axis] / 2.0)
+    }
+
+    // from world space into the frame of the container, centred on it and upright
+    pub fn to_local(&self, p: Vector2<f32>) -> Vector2<f32> {
+        rotate(p - self.center, -self.angle)
+    }
+
+    pub fn to_world(&self, local: Vector2<f32>) -> Vector2<f32> {
+        self.center + rotate(local, self.angle)
+    }
+
+    // a direction in the container's frame, in world space
+    pub fn rotate(&self, v: Vector2<f32>) -> Vector2<f32> {
+        rotate(v, self.angle)
+    }
+
+    // how fast the container moves at `p`, both from its centre moving and from it turning
+    pub fn velocity_at(&self, p: Vector2<f32>) -> Vector2<f32> {
+        let arm = p - self.center;
+        self.velocity + Vector2::new(-arm.y, arm.x) * self.angular_velocity
+    }
+}
+
+impl CpuSolver {
+    pub fn new(max_particles: usize) -> Self {
+        Self {
+            predicted_positions: Vec::with_capacity(max_particles),
+            spatial_hash: Vec::with_capacity(max_particles),
+            start_indices: Vec::new(),
+            time_step: TimeStep::default(),
+            solver_stats: SolverStats::default(),
+            pressures: Vec::new(),
+            boundary_data: Vec::with_capacity(max_particles),
+            body_impulses: Vec::new(),
+            bounds: Bounds::at_rest(Vector2::new(0.0, 0.0), Vector2::new(0.0, 0.0)),
+        }
+    }
+
+    // one step of the scene's solver, `mouse` is the interaction point in world space while
+    // the mouse is held
+    pub fn step(&mut self, sim: &mut WaterSimulation, bounds: Bounds, mouse: Option<Vector2<f32>>) {
+        let params = sim.params;
+        let half_boundries = Vector2 {
+            x: bounds.size.x / 2.0 - sim.radius.radius,
+            y: bounds.size.y / 2.0 - sim.radius.radius,
+        };
+
+        let delta_time = sim.time_step_params.delta_time(self.time_step.max_speed, self.time_step.max_acceleration, params.smoothing_radius);
+        self.time_step = TimeStep { delta_time, time: self.time_step.time + delta_time, ..TimeStep::default() };
+        // `bounds` is where the container rests, the scripted motion takes it from there
+        let bounds = sim.container_motion.apply(bounds, self.time_step.time);
+        self.bounds = bounds;
+        self.body_impulses.clear();
+        self.body_impulses.resize(sim.bodies.len(), BodyImpulse::default());
+
+        let (max_speed, max_acceleration) = match sim.solver {
+            Solver::Sph => self.step_sph(sim, bounds, mouse, half_boundries, delta_time),
+            Solver::Pbf => self.step_pbf(sim, bounds, mouse, half_boundries, delta_time),
+            Solver::Dfsph => self.step_dfsph(sim, bounds, mouse, half_boundries, delta_time),
+            Solver::Iisph => self.step_iisph(sim, bounds, mouse, half_boundries, delta_time),
+        };
+        resolve_obstacles(sim);
+        couple_bodies(sim, &self.body_impulses, bounds, delta_time);
+        self.time_step.max_speed = max_speed;
+        self.time_step.max_acceleration = max_acceleration;
+    }
+
+    // predict -> hash -> density -> viscosity -> pressure/integrate. returns the largest speed
+    // and acceleration for the next step's delta time
+    fn step_sph(&mut self, sim: &mut WaterSimulation, bounds: Bounds, mouse: Option<Vector2<f32>>, half_boundries: Vector2<f32>, delta_time: f32) -> (f32, f32) {
+        let num_particles = sim.num_particles as usize;
+        let params = sim.params;
+
+        self.predict_positions(sim, &params, mouse, num_particles, delta_time);
+        self.update_spatial_hash(&params, half_boundries, num_particles, sim.table_size);
+        self.update_boundary_data(&sim.boundary, &sim.bodies, &params, bounds, half_boundries, num_particles);
+
+        let densities: Vec<Vector2<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.density(index, &params, half_boundries))
+            .collect();
+        for (density, new) in sim.densities.iter_mut().zip(densities) {
+            density.density = new;
+        }
+
+        self.apply_viscosity(sim, &params, half_boundries, delta_time);
+        self.apply_surface_tension(sim, &params, half_boundries, delta_time);
+        self.apply_vorticity_confinement(sim, &params, half_boundries, delta_time);
+
+        let densities: Vec<Vector2<f32>> = sim.densities[..num_particles].iter().map(|d| d.density).collect();
+        let pressure: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.pressure_force(index, &densities, &params, half_boundries))
+            .collect();
+        // the boundary part of pressure_force, the near pressure on its own near gradient
+        let pushes: Vec<Vector2<f32>> = densities
+            .iter()
+            .map(|density| {
+                let pressure = convert_density_to_pressure(density.x, &params).max(0.0) / (density.x * density.x);
+                let near_pressure = convert_near_density_to_pressure(density.y, &params) / (density.y * density.x);
+                Vector2::new(pressure, near_pressure) * delta_time
+            })
+            .collect();
+        self.push_bodies(&sim.bodies, &pushes, &params);
+
+        integrate_positions(sim, &pressure, bounds, half_boundries, delta_time)
+    }
+
+    // the explicit scheme up to the viscosity, without the look ahead of the predicted
+    // positions, then a relaxed jacobi solve of the pressure poisson equation in place of its
+    // pressure force, the same passes as encode_iisph
+    fn step_iisph(&mut self, sim: &mut WaterSimulation, bounds: Bounds, mouse: Option<Vector2<f32>>, half_boundries: Vector2<f32>, delta_time: f32) -> (f32, f32) {
+        let num_particles = sim.num_particles as usize;
+        let params = sim.params;
+        let solver_params = sim.solver_params;
+
+        // same as iisph_predict_velocity
+        self.predict_positions(sim, &params, mouse, num_particles, delta_time);
+        for (predicted, position) in self.predicted_positions.iter_mut().zip(sim.positions.iter()) {
+            *predicted = position.position;
+        }
+        self.update_spatial_hash(&params, half_boundries, num_particles, sim.table_size);
+        self.update_boundary_data(&sim.boundary, &sim.bodies, &params, bounds, half_boundries, num_particles);
+
+        // same as iisph_calculate_density, with the own W(0)
+        let densities: Vec<Vector2<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.density(index, &params, half_boundries) + Vector2::new(self_density(&params), 0.0))
+            .collect();
+        for (density, new) in sim.densities.iter_mut().zip(&densities) {
+            density.density = *new;
+        }
+        let densities: Vec<f32> = densities.iter().map(|d| d.x).collect();
+
+        self.apply_viscosity(sim, &params, half_boundries, delta_time);
+        self.apply_surface_tension(sim, &params, half_boundries, delta_time);
+        self.apply_vorticity_confinement(sim, &params, half_boundries, delta_time);
+
+        let velocities: Vec<Vector3<f32>> = sim.velocities[..num_particles].iter().map(|v| v.position).collect();
+        let prepared: Vec<IisphParticle> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.iisph_prepare(index, &velocities, &densities, &params, half_boundries, delta_time))
+            .collect();
+
+        // half of the last step's pressure as the first guess
+        self.pressures.resize(num_particles, 0.0);
+        for pressure in self.pressures.iter_mut() {
+            *pressure *= 0.5;
+        }
+
+        self.solver_stats = SolverStats::default();
+        let mut iterations = 0;
+        loop {
+            let sum_dij: Vec<Vector3<f32>> = (0..num_particles)
+                .into_par_iter()
+                .map(|index| self.iisph_sum_dij(index, &densities, &params, half_boundries, delta_time))
+                .collect();
+            let (next_pressures, errors): (Vec<f32>, Vec<f32>) = (0..num_particles)
+                .into_par_iter()
+                .map(|index| {
+                    let particle = &prepared[index];
+                    let pressure = self.pressures[index];
+                    let pressure_term = self.iisph_pressure_term(index, &prepared, &sum_dij, &params, half_boundries, delta_time);
+
+                    // only compression is corrected, pressures stay non-negative
+                    let density = particle.advected_density + particle.a_ii * pressure + pressure_term;
+                    let error = (density - params.target_density).max(0.0) / params.target_density;
+                    let next_pressure = if particle.a_ii.abs() > 1e-9 {
+                        let omega = solver_params.omega;
+                        ((1.0 - omega) * pressure + omega / particle.a_ii * (params.target_density - particle.advected_density - pressure_term)).max(0.0)
+                    } else {
+                        0.0
+                    };
+                    (next_pressure, error)
+                })
+                .unzip();
+
+            let average_error = errors.iter().sum::<f32>() / num_particles.max(1) as f32;
+            self.solver_stats.error[DENSITY_SOLVE] = average_error;
+            if (iterations >= IISPH_MIN_ITERATIONS && average_error <= solver_params.tolerance) || iterations >= solver_params.max_iterations {
+                break;
+            }
+            iterations += 1;
+            self.pressures = next_pressures;
+        }
+        self.solver_stats.iterations[DENSITY_SOLVE] = iterations;
+
+        let pressure: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.iisph_pressure_acceleration(index, &densities, &params, half_boundries))
+            .collect();
+        let pushes: Vec<Vector2<f32>> = self.pressures
+            .iter()
+            .zip(&densities)
+            .map(|(pressure, density)| Vector2::new(pressure / (density * density) * delta_time, 0.0))
+            .collect();
+        self.push_bodies(&sim.bodies, &pushes, &params);
+
+        integrate_positions(sim, &pressure, bounds, half_boundries, delta_time)
+    }
+
+    // predict -> hash -> iterations x (lambda -> delta p) -> velocity update -> viscosity
+    fn step_pbf(&mut self, sim: &mut WaterSimulation, bounds: Bounds, mouse: Option<Vector2<f32>>, half_boundries: Vector2<f32>, delta_time: f32) -> (f32, f32) {
+        let num_particles = sim.num_particles as usize;
+        let params = sim.params;
+        let solver_params = sim.solver_params;
+
+        // the velocities keep gravity and the mouse, the predicted positions take the full step
+        self.predict_positions(sim, &params, mouse, num_particles, delta_time);
+        for (predicted, (position, velocity)) in self.predicted_positions.iter_mut().zip(sim.positions.iter().zip(sim.velocities.iter())) {
+            *predicted = position.position + velocity.position * delta_time;
+            clamp_to_boundaries(predicted, bounds, half_boundries);
+        }
+        self.update_spatial_hash(&params, half_boundries, num_particles, sim.table_size);
+
+        for _ in 0..solver_params.iterations {
+            self.update_boundary_data(&sim.boundary, &sim.bodies, &params, bounds, half_boundries, num_particles);
+            let lambdas: Vec<(f32, f32)> = (0..num_particles)
+                .into_par_iter()
+                .map(|index| self.pbf_lambda(index, &params, &solver_params, half_boundries))
+                .collect();
+            let deltas: Vec<Vector3<f32>> = (0..num_particles)
+                .into_par_iter()
+                .map(|index| self.pbf_delta(index, &lambdas, &params, &solver_params, half_boundries))
+                .collect();
+            // the boundary part of the position correction, as the momentum it gives the particle
+            let pushes: Vec<Vector2<f32>> = lambdas
+                .iter()
+                .map(|(_, lambda)| Vector2::new(-lambda / (params.target_density * delta_time), 0.0))
+                .collect();
+            self.push_bodies(&sim.bodies, &pushes, &params);
+            for (predicted, delta) in self.predicted_positions.iter_mut().zip(deltas) {
+                *predicted += delta;
+                clamp_to_boundaries(predicted, bounds, half_boundries);
+            }
+            for (density, (new, _)) in sim.densities.iter_mut().zip(lambdas) {
+                density.density = Vector2::new(new, 0.0);
+            }
+        }
+
+        let gravity = Vector3::new(0.0, -params.gravity, 0.0);
+        let maxima = sim.positions[..num_particles]
+            .par_iter_mut()
+            .zip(sim.velocities[..num_particles].par_iter_mut())
+            .zip(self.predicted_positions.par_iter())
+            .map(|((position, velocity), predicted)| {
+                let vel = (predicted - position.position) / delta_time;
+                let acceleration = ((vel - velocity.position) / delta_time + gravity).magnitude();
+                position.position = bounds.wrap(predicted.truncate()).extend(predicted.z);
+                velocity.position = vel;
+                if bounds.has_escaped(predicted.truncate()) {
+                    return (0.0, 0.0);
+                }
+                (vel.magnitude(), acceleration)
+            })
+            .reduce(|| (0.0, 0.0), |a, b| (a.0.max(b.0), a.1.max(b.1)));
+
+        self.apply_viscosity(sim, &params, half_boundries, delta_time);
+        self.apply_surface_tension(sim, &params, half_boundries, delta_time);
+        self.apply_vorticity_confinement(sim, &params, half_boundries, delta_time);
+        maxima
+    }
+
+    // begin step -> hash -> factors -> divergence solve -> external forces -> viscosity ->
+    // density solve -> integrate, the same passes as encode_dfsph
+    fn step_dfsph(&mut self, sim: &mut WaterSimulation, bounds: Bounds, mouse: Option<Vector2<f32>>, half_boundries: Vector2<f32>, delta_time: f32) -> (f32, f32) {
+        let num_particles = sim.num_particles as usize;
+        let params = sim.params;
+        let solver_params = sim.solver_params;
+
+        self.predicted_positions.clear();
+        self.predicted_positions.extend(sim.positions[..num_particles].iter().map(|p| p.position));
+        let start_velocities: Vec<Vector3<f32>> = sim.velocities[..num_particles].iter().map(|v| v.position).collect();
+        self.update_spatial_hash(&params, half_boundries, num_particles, sim.table_size);
+        self.update_boundary_data(&sim.boundary, &sim.bodies, &params, bounds, half_boundries, num_particles);
+
+        let (densities, factors): (Vec<f32>, Vec<f32>) = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.dfsph_factors(index, &params, half_boundries))
+            .unzip();
+        for (density, new) in sim.densities.iter_mut().zip(&densities) {
+            density.density = Vector2::new(*new, 0.0);
+        }
+
+        self.solver_stats = SolverStats::default();
+        let (iterations, error) = self.iterative_solve(sim, half_boundries, delta_time, solver_params.divergence_tolerance, |solver, index, velocities| {
+            // only compression is corrected, a free surface may spread out
+            let divergence = solver.dfsph_density_change(index, velocities, &params, half_boundries).max(0.0);
+            (divergence / delta_time * factors[index], divergence / params.target_density)
+        });
+        self.solver_stats.iterations[DIVERGENCE_SOLVE] = iterations;
+        self.solver_stats.error[DIVERGENCE_SOLVE] = error;
+
+        for (position, velocity) in sim.positions[..num_particles].iter().zip(sim.velocities[..num_particles].iter_mut()) {
+            external_forces(position.position, &mut velocity.position, &params, mouse, delta_time);
+        }
+        self.apply_viscosity(sim, &params, half_boundries, delta_time);
+        self.apply_surface_tension(sim, &params, half_boundries, delta_time);
+        self.apply_vorticity_confinement(sim, &params, half_boundries, delta_time);
+
+        let (iterations, error) = self.iterative_solve(sim, half_boundries, delta_time, solver_params.tolerance, |solver, index, velocities| {
+            let predicted_density = densities[index] + delta_time * solver.dfsph_density_change(index, velocities, &params, half_boundries);
+            let compression = (predicted_density - params.target_density).max(0.0);
+            (compression / (delta_time * delta_time) * factors[index], compression / params.target_density)
+        });
+        self.solver_stats.iterations[DENSITY_SOLVE] = iterations;
+        self.solver_stats.error[DENSITY_SOLVE] = error;
+
+        sim.positions[..num_particles]
+            .par_iter_mut()
+            .zip(sim.velocities[..num_particles].par_iter_mut())
+            .zip(start_velocities.par_iter())
+            .map(|((position, velocity), start_velocity)| {
+                let mut pos = position.position;
+                let mut vel = velocity.position;
+
+                let acceleration = (vel - start_velocity).magnitude() / delta_time;
+                pos += vel * delta_time;
+                check_boundaries(&mut pos, &mut vel, bounds, half_boundries, &params);
+
+                position.position = pos;
+                velocity.position = vel;
+                if bounds.has_escaped(pos.truncate()) {
+                    return (0.0, 0.0);
+                }
+                (vel.magnitude(), acceleration)
+            })
+            .reduce(|| (0.0, 0.0), |a, b| (a.0.max(b.0), a.1.max(b.1)))
+    }
+
+    // iterations and remaining average error of the last step's solves
+    pub fn solver_stats(&self) -> SolverStats {
+        self.solver_stats
+    }
+
+    // `error(solver, index, velocities)` gives kappa / density and the error of one particle.
+    // corrects the velocities until the average error is below `tolerance`, and the bodies get
+    // back what every correction pushed through their boundary particles. same as
+    // encode_iterative_solve and check_solver_error
+    fn iterative_solve<F>(&mut self, sim: &mut WaterSimulation, half_boundries: Vector2<f32>, delta_time: f32, tolerance: f32, error: F) -> (u32, f32)
+    where
+        F: Fn(&Self, usize, &[Vector3<f32>]) -> (f32, f32) + Sync,
+    {
+        let num_particles = sim.num_particles as usize;
+        let params = sim.params;
+        let mut iterations = 0;
+        loop {
+            let velocities: Vec<Vector3<f32>> = sim.velocities[..num_particles].iter().map(|v| v.position).collect();
+            let (kappas, errors): (Vec<f32>, Vec<f32>) = (0..num_particles)
+                .into_par_iter()
+                .map(|index| error(self, index, &velocities))
+                .unzip();
+            let average_error = errors.iter().sum::<f32>() / num_particles.max(1) as f32;
+            if average_error <= tolerance || iterations >= sim.solver_params.max_iterations {
+                return (iterations, average_error);
+            }
+            iterations += 1;
+
+            let accelerations: Vec<Vector3<f32>> = (0..num_particles)
+                .into_par_iter()
+                .map(|index| self.dfsph_pressure_acceleration(index, &kappas, &params, half_boundries))
+                .collect();
+            for (velocity, acceleration) in sim.velocities.iter_mut().zip(accelerations) {
+                velocity.position -= acceleration * delta_time;
+            }
+            let pushes: Vec<Vector2<f32>> = kappas.iter().map(|kappa| Vector2::new(kappa * delta_time, 0.0)).collect();
+            self.push_bodies(&sim.bodies, &pushes, &params);
+        }
+    }
+
+    // d_ii = -dt^2 sum_j m / rho_i^2 grad W_ij, a_ii = sum_j m (d_ii - d_ji) . grad W_ij with
+    // d_ji = dt^2 m / rho_i^2 grad W_ij, and the density the velocities alone would lead to
+    fn iisph_prepare(&self, particle_index: usize, velocities: &[Vector3<f32>], densities: &[f32], params: &SimParams, half_boundries: Vector2<f32>, delta_time: f32) -> IisphParticle {
+        let density = densities[particle_index];
+        let mut density_change = 0.0;
+        let mut sum_grad = Vector3::new(0.0, 0.0, 0.0);
+        let mut sum_grad_sqr = 0.0;
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let grad = MASS * kernel_gradient(particle_index, offset, dst, params);
+            density_change += (velocities[particle_index] - velocities[neighbour_index]).dot(grad);
+            sum_grad += grad;
+            sum_grad_sqr += grad.magnitude2();
+        });
+
+        // the boundary particles count into d_ii, but have no d_ji of their own
+        let boundary = &self.boundary_data[particle_index];
+        density_change += velocities[particle_index].dot(boundary.gradient) - boundary.flux;
+        sum_grad += boundary.gradient;
+
+        let scale = delta_time * delta_time / (density * density);
+        let d_ii = -scale * sum_grad;
+        IisphParticle {
+            a_ii: d_ii.dot(sum_grad) - scale * sum_grad_sqr,
+            advected_density: density + delta_time * density_change,
+            d_ii,
+            density,
+        }
+    }
+
+    fn iisph_sum_dij(&self, particle_index: usize, densities: &[f32], params: &SimParams, half_boundries: Vector2<f32>, delta_time: f32) -> Vector3<f32> {
+        let mut sum_dij = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let neighbour_density = densities[neighbour_index];
+            let grad = MASS * kernel_gradient(particle_index, offset, dst, params);
+            sum_dij -= delta_time * delta_time / (neighbour_density * neighbour_density) * self.pressures[neighbour_index] * grad;
+        });
+
+        sum_dij
+    }
+
+    // sum_j m (sum_k d_ik p_k - d_jj p_j - sum_{k != i} d_jk p_k) . grad W_ij
+    fn iisph_pressure_term(&self, particle_index: usize, prepared: &[IisphParticle], sum_dij: &[Vector3<f32>], params: &SimParams, half_boundries: Vector2<f32>, delta_time: f32) -> f32 {
+        let density = prepared[particle_index].density;
+        let pressure = self.pressures[particle_index];
+        let mut pressure_term = 0.0;
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let grad = MASS * kernel_gradient(particle_index, offset, dst, params);
+            let d_ji = delta_time * delta_time / (density * density) * grad;
+            let neighbour_sum = sum_dij[neighbour_index] - d_ji * pressure;
+            pressure_term += (sum_dij[particle_index] - prepared[neighbour_index].d_ii * self.pressures[neighbour_index] - neighbour_sum).dot(grad);
+        });
+
+        pressure_term + sum_dij[particle_index].dot(self.boundary_data[particle_index].gradient)
+    }
+
+    fn iisph_pressure_acceleration(&self, particle_index: usize, densities: &[f32], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let density = densities[particle_index];
+        let pressure_over_density = self.pressures[particle_index] / (density * density);
+        let mut acceleration = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let neighbour_density = densities[neighbour_index];
+            let shared_pressure = pressure_over_density + self.pressures[neighbour_index] / (neighbour_density * neighbour_density);
+            acceleration -= MASS * shared_pressure * kernel_gradient(particle_index, offset, dst, params);
+        });
+
+        acceleration - pressure_over_density * self.boundary_data[particle_index].gradient
+    }
+
+    // density and 1 / (|sum grad W|^2 + sum |grad W|^2) of one particle
+    fn dfsph_factors(&self, particle_index: usize, params: &SimParams, half_boundries: Vector2<f32>) -> (f32, f32) {
+        let mut density = 0.0;
+        let mut grad_i = Vector3::new(0.0, 0.0, 0.0);
+        let mut sum_grad_sqr = 0.0;
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |_, offset, dst| {
+            density += MASS * params.kernel().value(params.smoothing_radius, dst);
+            let grad_j = MASS * kernel_gradient(particle_index, offset, dst, params);
+            grad_i += grad_j;
+            sum_grad_sqr += grad_j.magnitude2();
+        });
+        // the own term has no gradient, it only adds to the density
+        density += self_density(params) + self.boundary_data[particle_index].density;
+        grad_i += self.boundary_data[particle_index].gradient;
+
+        // a particle without neighbours has nothing to push against
+        let denominator = grad_i.magnitude2() + sum_grad_sqr;
+        let factor = if denominator > 1e-6 { 1.0 / denominator } else { 0.0 };
+        (density, factor)
+    }
+
+    fn dfsph_density_change(&self, particle_index: usize, velocities: &[Vector3<f32>], params: &SimParams, half_boundries: Vector2<f32>) -> f32 {
+        let mut density_change = 0.0;
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let relative_velocity = velocities[particle_index] - velocities[neighbour_index];
+            density_change += MASS * relative_velocity.dot(kernel_gradient(particle_index, offset, dst, params));
+        });
+
+        // the walls stand still, the bodies move their boundary particles along
+        let boundary = &self.boundary_data[particle_index];
+        density_change + velocities[particle_index].dot(boundary.gradient) - boundary.flux
+    }
+
+    fn dfsph_pressure_acceleration(&self, particle_index: usize, kappas: &[f32], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut acceleration = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            acceleration += MASS * (kappas[particle_index] + kappas[neighbour_index]) * kernel_gradient(particle_index, offset, dst, params);
+        });
+
+        acceleration + kappas[particle_index] * self.boundary_data[particle_index].gradient
+    }
+
+    // density and lambda of one particle, the lambda pass of pbf_calculate_lambda
+    fn pbf_lambda(&self, particle_index: usize, params: &SimParams, solver_params: &SolverParams, half_boundries: Vector2<f32>) -> (f32, f32) {
+        let mut density = 0.0;
+        let mut grad_i = Vector3::new(0.0, 0.0, 0.0);
+        let mut sum_grad_sqr = 0.0;
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |_, offset, dst| {
+            density += params.kernel().value(params.smoothing_radius, dst);
+            let grad_j = kernel_gradient(particle_index, offset, dst, params) / params.target_density;
+            grad_i += grad_j;
+            sum_grad_sqr += grad_j.magnitude2();
+        });
+        // the boundary particles don't move, they only add to the density and the own gradient
+        density += self_density(params) + self.boundary_data[particle_index].density;
+        grad_i += self.boundary_data[particle_index].gradient / params.target_density;
+        sum_grad_sqr += grad_i.magnitude2();
+
+        let constraint = (density / params.target_density - 1.0).max(0.0);
+        (density, -constraint / (sum_grad_sqr + solver_params.relaxation))
+    }
+
+    fn pbf_delta(&self, particle_index: usize, lambdas: &[(f32, f32)], params: &SimParams, solver_params: &SolverParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut delta = Vector3::new(0.0, 0.0, 0.0);
+        let lambda = lambdas[particle_index].1;
+        let tensile_reference = params.kernel().value(params.smoothing_radius, solver_params.tensile_dq * params.smoothing_radius);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let tensile_correction = -solver_params.tensile_k * (params.kernel().value(params.smoothing_radius, dst) / tensile_reference).powf(solver_params.tensile_n);
+            delta += (lambda + lambdas[neighbour_index].1 + tensile_correction) * kernel_gradient(particle_index, offset, dst, params);
+        });
+        delta += lambda * self.boundary_data[particle_index].gradient;
+
+        delta / params.target_density
+    }
+
+    // delta time of the last step and the simulated time so far
+    pub fn time_step(&self) -> TimeStep {
+        self.time_step
+    }
+
+    // carries on from `time`, the next step picks its delta time as if the scene were still
+    pub fn set_time(&mut self, time: f32) {
+        self.time_step = TimeStep { time, ..TimeStep::default() };
+    }
+
+    fn predict_positions(&mut self, sim: &mut WaterSimulation, params: &SimParams, mouse: Option<Vector2<f32>>, num_particles: usize, delta_time: f32) {
+        self.predicted_positions.clear();
+        self.predicted_positions.resize(num_particles, Vector3::new(0.0, 0.0, 0.0));
+
+        self.predicted_positions
+            .par_iter_mut()
+            .zip(sim.positions[..num_particles].par_iter())
+            .zip(sim.velocities[..num_particles].par_iter_mut())
+            .for_each(|((predicted, position), velocity)| {
+                *predicted = external_forces(position.position, &mut velocity.position, params, mouse, delta_time);
+            });
+    }
+
+    fn update_spatial_hash(&mut self, params: &SimParams, half_boundries: Vector2<f32>, num_particles: usize, table_size: usize) {
+        self.start_indices.resize(table_size, 0);
+        let keys: Vec<u32> = self.predicted_positions[..num_particles]
+            .par_iter()
+            .map(|pos| {
+                let cell = self.bounds.cell(self.bounds.to_local(pos.truncate()), half_boundries, params.cell_size());
+                hash_position(cell, table_size)
+            })
+            .collect();
+        counting_sort(&keys, table_size, &mut self.spatial_hash, &mut self.start_indices);
+    }
+
+    // collision statistics of the last step's spatial hash, in the container frame it was built in
+    pub fn hash_stats(&self, sim: &WaterSimulation) -> HashStats {
+        let bounds = self.bounds;
+        let half_boundries = bounds.size / 2.0 - Vector2::new(sim.radius.radius, sim.radius.radius);
+        let cells: Vec<Vector2<i32>> = self.predicted_positions
+            .iter()
+            .map(|p| bounds.cell(bounds.to_local(p.truncate()), half_boundries, sim.params.cell_size()))
+            .collect();
+        HashStats::new(&self.spatial_hash, &cells, self.start_indices.len())
+    }
+
+    // calls `f(neighbour_index, offset_to_neighbour, distance)` for every particle inside the
+    // smoothing radius, walking the same 3x3 cell stencil as the shader. across a periodic edge
+    // the offset is to the neighbour's nearest image
+    fn for_each_neighbour<F: FnMut(usize, Vector3<f32>, f32)>(&self, particle_index: usize, params: &SimParams, half_boundries: Vector2<f32>, mut f: F) {
+        let bounds = self.bounds;
+        let particle_position = self.predicted_positions[particle_index];
+        let norm_particle_position = bounds.cell(bounds.to_local(particle_position.truncate()), half_boundries, params.cell_size());
+        let sqr_radius = params.smoothing_radius * params.smoothing_radius;
+
+        let table_size = self.start_indices.len();
+        let mut visited = [u32::MAX; 9];
+        for (i, offset) in NEIGHBOR_OFFSETS_2D.iter().enumerate() {
+            // two stencil cells can hash to the same bucket, walk it only once
+            let hash_key = hash_position(bounds.wrap_cell(norm_particle_position + *offset, params.cell_size()), table_size);
+            if visited[..i].contains(&hash_key) {
+                continue;
+            }
+            visited[i] = hash_key;
+
+            let mut curr_index = self.start_indices[hash_key as usize] as usize;
+            while curr_index < self.spatial_hash.len() && self.spatial_hash[curr_index].cell_index == hash_key {
+                let neighbour_index = self.spatial_hash[curr_index].particle_index as usize;
+                curr_index += 1;
+
+                if neighbour_index == particle_index {
+                    continue;
+                }
+
+                let offset_to_neighbour = bounds.nearest_image(self.predicted_positions[neighbour_index] - particle_position);
+                let sqr_dst_to_neighbour = offset_to_neighbour.magnitude2();
+                if sqr_dst_to_neighbour > sqr_radius {
+                    continue;
+                }
+
+                f(neighbour_index, offset_to_neighbour, sqr_dst_to_neighbour.sqrt());
+            }
+        }
+    }
+
+    // what the boundary particles of the walls and the bodies add to the sums of every particle
+    // on the current predicted positions, same as calculate_boundary in the shader
+    fn update_boundary_data(&mut self, boundary: &Boundary, bodies: &Bodies, params: &SimParams, bounds: Bounds, half_boundries: Vector2<f32>, num_particles: usize) {
+        let boundary_data: Vec<BoundaryData> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.boundary_sums(index, boundary, bodies, params, bounds, half_boundries))
+            .collect();
+        self.boundary_data = boundary_data;
+    }
+
+    // the boundary particles of the walls sit in the frame of the container, so the lookup
+    // happens there and the offsets are turned back into world space. those of the bodies are
+    // few enough to go through body by body
+    fn boundary_sums(&self, particle_index: usize, boundary: &Boundary, bodies: &Bodies, params: &SimParams, bounds: Bounds, half_boundries: Vector2<f32>) -> BoundaryData {
+        let mut data = BoundaryData::default();
+
+        let local = bounds.to_local(self.predicted_positions[particle_index].truncate());
+        boundary.for_each_neighbour(local.extend(0.0), params, bounds, half_boundries, |particle, offset, dist| {
+            let offset = bounds.rotate(offset.truncate()).extend(0.0);
+            data.density += particle.psi * params.kernel().value(params.smoothing_radius, dist);
+            data.near_density += particle.psi * smoothing_kernel_spikey_near(params.smoothing_radius, dist);
+            data.gradient += particle.psi * kernel_gradient(particle_index, offset, dist, params);
+            data.near_gradient += particle.psi * near_kernel_gradient(particle_index, offset, dist, params);
+        });
+
+        bodies.for_each_sample(self.predicted_positions[particle_index], params.smoothing_radius, bounds, |body, psi, offset, dist, arm| {
+            let gradient = psi * kernel_gradient(particle_index, offset, dist, params);
+            data.density += psi * params.kernel().value(params.smoothing_radius, dist);
+            data.near_density += psi * smoothing_kernel_spikey_near(params.smoothing_radius, dist);
+            data.gradient += gradient;
+            data.near_gradient += psi * near_kernel_gradient(particle_index, offset, dist, params);
+            data.flux += bodies.surface_velocity(body, arm).extend(0.0).dot(gradient);
+        });
+
+        data
+    }
+
+    // every solver moves a particle by -push.x psi_b grad W_ib - push.y psi_b grad_near W_ib for
+    // a boundary particle b, one way or another. on a body that is pushed back with the opposite
+    // impulse, which adds up to the buoyancy and whatever else the fluid does to it. same as
+    // push_bodies in the shader
+    fn push_bodies(&mut self, bodies: &Bodies, pushes: &[Vector2<f32>], params: &SimParams) {
+        if bodies.is_empty() {
+            return;
+        }
+        let num_bodies = bodies.len();
+        let bounds = self.bounds;
+        let received = (0..pushes.len())
+            .into_par_iter()
+            .fold(
+                || vec![BodyImpulse::default(); num_bodies],
+                |mut impulses, particle_index| {
+                    let push = pushes[particle_index];
+                    if push.x == 0.0 && push.y == 0.0 {
+                        return impulses;
+                    }
+                    bodies.for_each_sample(self.predicted_positions[particle_index], params.smoothing_radius, bounds, |body, psi, offset, dist, arm| {
+                        let gradient = push.x * kernel_gradient(particle_index, offset, dist, params) + push.y * near_kernel_gradient(particle_index, offset, dist, params);
+                        let impulse = (MASS * psi * gradient).truncate();
+                        impulses[body].impulse += impulse;
+                        impulses[body].torque += cross(arm, impulse);
+                    });
+                    impulses
+                },
+            )
+            .reduce(|| vec![BodyImpulse::default(); num_bodies], add_impulses);
+        self.body_impulses = add_impulses(std::mem::take(&mut self.body_impulses), received);
+    }
+
+    fn density(&self, particle_index: usize, params: &SimParams, half_boundries: Vector2<f32>) -> Vector2<f32> {
+        let mut density = 0.0;
+        let mut near_density = 0.0;
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |_, _, dist| {
+            density += params.kernel().value(params.smoothing_radius, dist);
+            near_density += smoothing_kernel_spikey_near(params.smoothing_radius, dist);
+        });
+        density += self.boundary_data[particle_index].density;
+        near_density += self.boundary_data[particle_index].near_density;
+
+        Vector2::new(f32::max(density, 0.1), f32::max(near_density, 0.1))
+    }
+
+    fn apply_viscosity(&self, sim: &mut WaterSimulation, params: &SimParams, half_boundries: Vector2<f32>, delta_time: f32) {
+        let num_particles = sim.num_particles as usize;
+        let velocities: Vec<Vector3<f32>> = sim.velocities[..num_particles].iter().map(|v| v.position).collect();
+        let densities: Vec<f32> = sim.densities[..num_particles].iter().map(|d| d.density.x.max(0.1)).collect();
+        let viscosity: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| {
+                let mut velocity_change = self.viscosity_force(index, &velocities, params, half_boundries) * params.viscosity_strength * delta_time;
+                if params.xsph != 0.0 {
+                    velocity_change += self.xsph_correction(index, &velocities, &densities, params, half_boundries) * params.xsph;
+                }
+                velocity_change
+            })
+            .collect();
+        for (velocity, change) in sim.velocities.iter_mut().zip(viscosity) {
+            velocity.position += change;
+        }
+    }
+
+    fn viscosity_force(&self, particle_index: usize, velocities: &[Vector3<f32>], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut viscosity_force = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, _, dist| {
+            let vel_diff = velocities[neighbour_index] - velocities[particle_index];
+            viscosity_force += vel_diff * params.kernel().value(params.smoothing_radius, dist) * MASS;
+        });
+
+        viscosity_force
+    }
+
+    fn xsph_correction(&self, particle_index: usize, velocities: &[Vector3<f32>], densities: &[f32], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut correction = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, _, dist| {
+            let vel_diff = velocities[neighbour_index] - velocities[particle_index];
+            correction += vel_diff * MASS / densities[neighbour_index] * params.kernel().value(params.smoothing_radius, dist);
+        });
+
+        correction
+    }
+
+    // akinci cohesion and curvature, the same two passes as encode_surface_tension
+    fn apply_surface_tension(&self, sim: &mut WaterSimulation, params: &SimParams, half_boundries: Vector2<f32>, delta_time: f32) {
+        if params.surface_tension == 0.0 {
+            return;
+        }
+        let num_particles = sim.num_particles as usize;
+        // the density floor of `density`, pbf and dfsph store theirs without one
+        let densities: Vec<f32> = sim.densities[..num_particles].iter().map(|d| d.density.x.max(0.1)).collect();
+        let normals: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.surface_normal(index, &densities, params, half_boundries))
+            .collect();
+        let forces: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.surface_tension_force(index, &densities, &normals, params, half_boundries))
+            .collect();
+        for (velocity, force) in sim.velocities.iter_mut().zip(forces) {
+            velocity.position += force * delta_time;
+        }
+    }
+
+    fn surface_normal(&self, particle_index: usize, densities: &[f32], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dist| {
+            normal += MASS / densities[neighbour_index] * kernel_gradient(particle_index, offset, dist, params);
+        });
+
+        normal * params.smoothing_radius
+    }
+
+    fn surface_tension_force(&self, particle_index: usize, densities: &[f32], normals: &[Vector3<f32>], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut force = Vector3::new(0.0, 0.0, 0.0);
+        let density = densities[particle_index];
+        let normal = normals[particle_index];
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dist| {
+            let cohesion = if dist > 1e-6 {
+                MASS * MASS * cohesion_kernel(params.smoothing_radius, dist) * offset / dist
+            } else {
+                Vector3::new(0.0, 0.0, 0.0)
+            };
+            let curvature = CURVATURE_WEIGHT * MASS * (normals[neighbour_index] - normal);
+            let correction = 2.0 * params.target_density / (density + densities[neighbour_index]);
+            force += correction * (cohesion + curvature);
+        });
+
+        force * params.surface_tension
+    }
+
+    // vorticity, then the confinement force from its gradient, as in encode_vorticity_confinement
+    fn apply_vorticity_confinement(&self, sim: &mut WaterSimulation, params: &SimParams, half_boundries: Vector2<f32>, delta_time: f32) {
+        if params.vorticity_confinement == 0.0 {
+            return;
+        }
+        let num_particles = sim.num_particles as usize;
+        let densities: Vec<f32> = sim.densities[..num_particles].iter().map(|d| d.density.x.max(0.1)).collect();
+        let velocities: Vec<Vector3<f32>> = sim.velocities[..num_particles].iter().map(|v| v.position).collect();
+        let vorticities: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.vorticity(index, &densities, &velocities, params, half_boundries))
+            .collect();
+        let forces: Vec<Vector3<f32>> = (0..num_particles)
+            .into_par_iter()
+            .map(|index| self.vorticity_confinement_force(index, &densities, &vorticities, params, half_boundries))
+            .collect();
+        for (velocity, force) in sim.velocities.iter_mut().zip(forces) {
+            velocity.position += force * delta_time;
+        }
+    }
+
+    fn vorticity(&self, particle_index: usize, densities: &[f32], velocities: &[Vector3<f32>], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut vorticity = Vector3::new(0.0, 0.0, 0.0);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dist| {
+            let vel_diff = velocities[neighbour_index] - velocities[particle_index];
+            vorticity += MASS / densities[neighbour_index] * kernel_gradient(particle_index, offset, dist, params).cross(vel_diff);
+        });
+
+        vorticity
+    }
+
+    fn vorticity_confinement_force(&self, particle_index: usize, densities: &[f32], vorticities: &[Vector3<f32>], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut eta = Vector3::new(0.0, 0.0, 0.0);
+        let vorticity = vorticities[particle_index];
+        let magnitude = vorticity.magnitude();
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dist| {
+            let magnitude_diff = vorticities[neighbour_index].magnitude() - magnitude;
+            eta += MASS / densities[neighbour_index] * magnitude_diff * kernel_gradient(particle_index, offset, dist, params);
+        });
+
+        if eta.magnitude() < 1e-6 {
+            return Vector3::new(0.0, 0.0, 0.0);
+        }
+        eta.normalize().cross(vorticity) * params.vorticity_confinement
+    }
+
+    fn pressure_force(&self, particle_index: usize, densities: &[Vector2<f32>], params: &SimParams, half_boundries: Vector2<f32>) -> Vector3<f32> {
+        let mut pressure_force = Vector3::new(0.0, 0.0, 0.0);
+        let density = densities[particle_index].x;
+        let near_density = densities[particle_index].y;
+        let pressure = convert_density_to_pressure(density, params);
+        let near_pressure = convert_near_density_to_pressure(near_density, params);
+
+        self.for_each_neighbour(particle_index, params, half_boundries, |neighbour_index, offset, dst| {
+            let direction = if dst < 0.01 {
+                get_random_direction(particle_index as u32)
+            } else {
+                offset.normalize()
+            };
+
+            let neighbor_density = densities[neighbour_index].x;
+            let neighbor_near_density = densities[neighbour_index].y;
+            let neighbor_pressure = convert_density_to_pressure(neighbor_density, params);
+            let neighbor_near_pressure = convert_near_density_to_pressure(neighbor_near_density, params);
+
+            let slope = params.kernel().derivative(params.smoothing_radius, dst);
+            let slope_near = smoothing_kernel_spikey_near_derivative(params.smoothing_radius, dst);
+
+            let shared_pressure = (pressure + neighbor_pressure) * 0.5;
+            let shared_near_pressure = (near_pressure + neighbor_near_pressure) * 0.5;
+
+            pressure_force += direction * slope * shared_pressure * MASS / neighbor_density;
+            pressure_force += direction * slope_near * shared_near_pressure * MASS / neighbor_near_density;
+        });
+
+        // the boundary particles mirror this particle's pressure and density. a negative pressure
+        // would pull the fluid onto the wall, so only the push is kept
+        let boundary = &self.boundary_data[particle_index];
+        pressure_force -= pressure.max(0.0) / density * boundary.gradient;
+        pressure_force -= near_pressure / near_density * boundary.near_gradient;
+
+        pressure_force / density
+    }
+}
+
+// applies the pressure acceleration and moves the particles, returns the largest speed and
+// acceleration. same as integrate_position in the shader
+fn integrate_positions(sim: &mut WaterSimulation, pressure: &[Vector3<f32>], bounds: Bounds, half_boundries: Vector2<f32>, delta_time: f32) -> (f32, f32) {
+    let num_particles = sim.num_particles as usize;
+    let params = sim.params;
+    let gravity = Vector3::new(0.0, -params.gravity, 0.0);
+    sim.positions[..num_particles]
+        .par_iter_mut()
+        .zip(sim.velocities[..num_particles].par_iter_mut())
+        .zip(pressure.par_iter())
+        .map(|((position, velocity), pressure_force)| {
+            let mut pos = position.position;
+            let mut vel = velocity.position;
+
+            vel += *pressure_force * delta_time;
+            pos += vel * delta_time;
+            check_boundaries(&mut pos, &mut vel, bounds, half_boundries, &params);
+
+            position.position = pos;
+            velocity.position = vel;
+            if bounds.has_escaped(pos.truncate()) {
+                return (0.0, 0.0);
+            }
+            (vel.magnitude(), (*pressure_force + gravity).magnitude())
+        })
+        .reduce(|| (0.0, 0.0), |a, b| (a.0.max(b.0), a.1.max(b.1)))
+}
+
+// pushes whatever the solver moved into an obstacle back out, same as resolve_obstacles in
+// the shader
+fn resolve_obstacles(sim: &mut WaterSimulation) {
+    let obstacles = &sim.obstacles;
+    if obstacles.is_empty() {
+        return;
+    }
+    let num_particles = sim.num_particles as usize;
+    let radius = sim.radius.radius;
+    let restitution = sim.params.boundary_restitution;
+    sim.positions[..num_particles]
+        .par_iter_mut()
+        .zip(sim.velocities[..num_particles].par_iter_mut())
+        .for_each(|(position, velocity)| obstacles.collide(&mut position.position, &mut velocity.position, radius, restitution));
+}
+
+// whatever got past the boundary particles of a body is pushed out of it, then the bodies move
+// with that and what the pressure pushed into them. same as collide_with_bodies and
+// integrate_bodies in the shader
+fn couple_bodies(sim: &mut WaterSimulation, pushed: &[BodyImpulse], bounds: Bounds, delta_time: f32) {
+    if sim.bodies.is_empty() {
+        return;
+    }
+    let num_particles = sim.num_particles as usize;
+    let radius = sim.radius.radius;
+    let num_bodies = sim.bodies.len();
+    let bodies = &sim.bodies;
+    let impulses = sim.positions[..num_particles]
+        .par_iter_mut()
+        .zip(sim.velocities[..num_particles].par_iter_mut())
+        .fold(
+            || vec![BodyImpulse::default(); num_bodies],
+            |mut impulses, (position, velocity)| {
+                for (index, sum) in impulses.iter_mut().enumerate() {
+                    let received = bodies.contact(index, &mut position.position, &mut velocity.position, radius, bounds);
+                    sum.impulse += received.impulse;
+                    sum.torque += received.torque;
+                }
+                impulses
+            },
+        )
+        .reduce(|| vec![BodyImpulse::default(); num_bodies], add_impulses);
+    let impulses = add_impulses(impulses, pushed.to_vec());
+    let params = sim.params;
+    sim.bodies.integrate(&impulses, &params, bounds, delta_time);
+}
+
+fn add_impulses(mut a: Vec<BodyImpulse>, b: Vec<BodyImpulse>) -> Vec<BodyImpulse> {
+    for (sum, received) in a.iter_mut().zip(b) {
+        sum.impulse += received.impulse;
+        sum.torque += received.torque;
+    }
+    a
+}
+
+fn external_forces(pos: Vector3<f32>, vel: &mut Vector3<f32>, params: &SimParams, mouse: Option<Vector2<f32>>, delta_time: f32) -> Vector3<f32> {
+    vel.y -= params.gravity * delta_time;
+
+    if let Some(mouse_pos_world) = mouse {
+        let diff = mouse_pos_world - pos.truncate();
+        let sqr_dst = diff.magnitude2();
+
+        if sqr_dst < params.interaction_radius * params.interaction_radius {
+            let dist = sqr_dst.sqrt();
+            let force = diff.normalize() * (params.interaction_radius - dist) * params.interaction_strength;
+            vel.x += force.x * delta_time;
+            vel.y += force.y * delta_time;
+        }
+    }
+
+    pos + *vel * delta_time
+}
+
+// mirrors the particle back in and bounces it off the wall in the frame of the container,
+// relative to the wall's own velocity so a moving wall pushes the fluid along. across a periodic
+// axis it comes back in at the far edge instead, across an open one it just leaves
+fn check_boundaries(pos: &mut Vector3<f32>, vel: &mut Vector3<f32>, bounds: Bounds, half_boundaries: Vector2<f32>, params: &SimParams) {
+    let mut local = bounds.to_local(pos.truncate());
+    if local.x.abs() <= half_boundaries.x && local.y.abs() <= half_boundaries.y {
+        return;
+    }
+    let wall_velocity = bounds.velocity_at(pos.truncate());
+    let mut local_vel = rotate(vel.truncate() - wall_velocity, -bounds.angle);
+
+    for axis in 0..2 {
+        let half = half_boundaries[axis];
+        match bounds.modes[axis] {
+            BoundaryMode::Reflective => {
+                if local[axis] < -half {
+                    local[axis] = -half + (-half - local[axis]);
+                    local_vel[axis] = -local_vel[axis] * params.boundary_restitution;
+                } else if local[axis] > half {
+                    local[axis] = half - (local[axis] - half);
+                    local_vel[axis] = -local_vel[axis] * params.boundary_restitution;
+                }
+            }
+            BoundaryMode::Periodic => {
+                let size = bounds.size[axis];
+                if local[axis] < -size / 2.0 {
+                    local[axis] += size;
+                } else if local[axis] > size / 2.0 {
+                    local[axis] -= size;
+                }
+            }
+            BoundaryMode::Open => {}
+        }
+    }
+
+    let world = bounds.to_world(local);
+    let world_vel = bounds.rotate(local_vel) + wall_velocity;
+    *pos = world.extend(pos.z);
+    *vel = world_vel.extend(vel.z);
+}
+
+// only the walls, a position past a periodic edge is wrapped once the step is done
+fn clamp_to_boundaries(pos: &mut Vector3<f32>, bounds: Bounds, half_boundaries: Vector2<f32>) {
+    let mut local = bounds.to_local(pos.truncate());
+    if local.x.abs() <= half_boundaries.x && local.y.abs() <= half_boundaries.y {
+        return;
+    }
+    for axis in 0..2 {
+        if bounds.modes[axis] == BoundaryMode::Reflective {
+            local[axis] = local[axis].clamp(-half_boundaries[axis], half_boundaries[axis]);
+        }
+    }
+    *pos = bounds.to_world(local).extend(pos.z);
+}
+
+pub(super) fn rotate(v: Vector2<f32>, angle: f32) -> Vector2<f32> {
+    let (sin, cos) = angle.sin_cos();
+    Vector2::new(cos * v.x - sin * v.y, sin * v.x + cos * v.y)
+}
+
+// gradient of the selected kernel with respect to the particle itself, same as the shader
+fn kernel_gradient(particle_index: usize, offset_to_neighbour: Vector3<f32>, dst: f32, params: &SimParams) -> Vector3<f32> {
+    let direction = if dst < 0.01 {
+        get_random_direction(particle_index as u32)
+    } else {
+        -offset_to_neighbour / dst.max(1e-6)
+    };
+    direction * params.kernel().derivative(params.smoothing_radius, dst)
+}
+
+// m W(0), same as the shader. for_each_neighbour skips the particle itself
+fn self_density(params: &SimParams) -> f32 {
+    MASS * params.kernel().value(params.smoothing_radius, 0.0)
+}
+
+// the same for the near density kernel
+fn near_kernel_gradient(particle_index: usize, offset_to_neighbour: Vector3<f32>, dst: f32, params: &SimParams) -> Vector3<f32> {
+    let direction = if dst < 0.01 {
+        get_random_direction(particle_index as u32)
+    } else {
+        -offset_to_neighbour / dst.max(1e-6)
+    };
+    direction * smoothing_kernel_spikey_near_derivative(params.smoothing_radius, dst)
+}
+
+pub fn convert_density_to_pressure(density: f32, params: &SimParams) -> f32 {
+    let eos = params.equation_of_state();
+    let pressure = match eos {
+        EquationOfState::Linear | EquationOfState::ClampedLinear => params.pressure_multiplier * (density - params.target_density),
+        EquationOfState::Tait | EquationOfState::ClampedTait => params.tait_stiffness * ((density / params.target_density).powf(params.tait_gamma) - 1.0),
+    };
+    if eos.is_clamped() { pressure.max(0.0) } else { pressure }
+}
+
+pub fn convert_near_density_to_pressure(near_density: f32, params: &SimParams) -> f32 {
+    params.near_pressure_multiplier * near_density
+}
+
+// the near density kernel and the cohesion spline, same constants and epsilons as the
+// shader. the selectable ones are in kernel.rs
+
+fn smoothing_kernel_spikey_near(s_rad: f32, dist: f32) -> f32 {
+    if dist > s_rad { return 0.0; }
+
+    let volume = 10.0 / (PI * s_rad.powi(5));
+    let v = s_rad - dist + 1e-5;
+    v * v * v * volume
+}
+
+fn smoothing_kernel_spikey_near_derivative(s_rad: f32, dist: f32) -> f32 {
+    if dist > s_rad { return 0.0; }
+
+    let v = s_rad - dist + 1e-5;
+    let volume = 30.0 / (PI * s_rad.powi(5));
+    -v * v * volume
+}
+
+fn cohesion_kernel(s_rad: f32, dist: f32) -> f32 {
+    if dist > s_rad { return 0.0; }
+
+    let scale = 32.0 / (PI * s_rad.powi(9));
+    let v = (s_rad - dist).powi(3) * dist.powi(3);
+    if 2.0 * dist > s_rad {
+        return scale * v;
+    }
+    scale * (2.0 * v - s_rad.powi(6) / 64.0)
+}
+
+// Spatial hash functions
+
+// same binning as the compute shader, one bin per table slot. keys must be below table_size,
+// empty buckets get keys.len() in `start_indices` so a lookup runs straight off the end
+pub fn counting_sort(keys: &[u32], table_size: usize, sorted: &mut Vec<HashCell>, start_indices: &mut [u32]) {
+    let mut offsets = vec![0u32; table_size];
+    for key in keys {
+        offsets[*key as usize] += 1;
+    }
+
+    let mut running = 0;
+    for (cell, offset) in offsets.iter_mut().enumerate() {
+        let count = *offset;
+        *offset = running;
+        start_indices[cell] = if count > 0 { running } else { keys.len() as u32 };
+        running += count;
+    }
+
+    sorted.clear();
+    sorted.resize(keys.len(), HashCell { particle_index: 0, cell_index: 0 });
+    for (index, key) in keys.iter().enumerate() {
+        let slot = &mut offsets[*key as usize];
+        sorted[*slot as usize] = HashCell { particle_index: index as u32, cell_index: *key };
+        *slot += 1;
+    }
+}
+
+pub fn get_shifted_2d_pos(pos: Vector2<f32>, half_boundries: Vector2<f32>, cell_size: f32) -> Vector2<i32> {
+    Vector2 {
+        x: ((pos.x + half_boundries.x) / cell_size).floor() as i32,
+        y: ((pos.y + half_boundries.y) / cell_size).floor() as i32,
+    }
+}
+
+// negative cells wrap to large u32s, so the bucket is always in 0..table_size
+pub fn hash_position(pos: Vector2<i32>, table_size: usize) -> u32 {
+    let hash = (pos.x as u32).wrapping_mul(HV1) ^ (pos.y as u32).wrapping_mul(HV2);
+    hash % table_size as u32
+}
+
+// Other helper functions, the lcg based direction used when two particles overlap
+
+fn lcg(seed: u32) -> u32 {
+    1664525u32.wrapping_mul(seed).wrapping_add(1013904223)
+}
+
+fn random(seed: u32) -> f32 {
+    let next_seed = lcg(seed);
+    let x = (next_seed as f32).sin() * 43758.547;
+    x - x.floor()
+}
+
+fn get_random_direction(seed: u32) -> Vector3<f32> {
+    let s = lcg(seed);
+    let theta = random(s) * 2.0 * PI;
+    Vector3::new(theta.cos(), theta.sin(), 0.0).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params_with(eos: EquationOfState) -> SimParams {
+        SimParams { equation_of_state: eos as u32, target_density: 5.0, tait_stiffness: 40.0, tait_gamma: 7.0, ..SimParams::default() }
+    }
+
+    #[test]
+    fn equations_of_state_match_their_formulas() {
+        let tait = params_with(EquationOfState::Tait);
+        assert_eq!(convert_density_to_pressure(tait.target_density, &tait), 0.0);
+        // B ((rho / rho0)^gamma - 1) at 1.1 rho0
+        let expected = 40.0 * (1.1f32.powi(7) - 1.0);
+        let pressure = convert_density_to_pressure(5.5, &tait);
+        assert!((pressure - expected).abs() < 1e-4 * expected, "{} vs {}", pressure, expected);
+        // unclamped, an expanded fluid pulls
+        assert!(convert_density_to_pressure(4.0, &tait) < 0.0);
+
+        for eos in [EquationOfState::ClampedLinear, EquationOfState::ClampedTait] {
+            let params = params_with(eos);
+            for density in [0.0, 1.0, 2.5, 4.9, 5.0] {
+                assert_eq!(convert_density_to_pressure(density, &params), 0.0, "{:?} at {}", eos, density);
+            }
+            assert!(convert_density_to_pressure(5.5, &params) > 0.0);
+        }
+    }
+
+    #[test]
+    fn counting_sort_matches_std_sort() {
+        let table_size = 64;
+        let keys: Vec<u32> = (-16..80)
+            .flat_map(|x| (0..10).map(move |y| hash_position(Vector2 { x, y: y - 5 }, table_size)))
+            .collect();
+
+        let mut sorted = Vec::new();
+        let mut start_indices = vec![0; table_size];
+        counting_sort(&keys, table_size, &mut sorted, &mut start_indices);
+
+        let mut expected: Vec<(usize, u32)> = keys.iter().copied().enumerate().collect();
+        expected.sort_by_key(|(_, key)| *key);
+
+        let actual: Vec<(usize, u32)> = sorted.iter().map(|c| (c.particle_index as usize, c.cell_index)).collect();
+        assert_eq!(actual, expected);
+
+        for (cell, start) in start_indices.iter().enumerate() {
+            match expected.iter().position(|(_, key)| *key == cell as u32) {
+                Some(first) => assert_eq!(*start as usize, first),
+                None => assert_eq!(*start as usize, keys.len()),
+            }
+        }
+    }
+}
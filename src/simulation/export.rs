@@ -0,0 +1,116 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use super::cpu_solver::{convert_density_to_pressure, convert_near_density_to_pressure};
+use super::simulation::WaterSimulation;
+
+// writes every `every`-th step as an ascii .vtu point cloud plus a .pvd collection
+// tying them to simulation time, so the whole run opens as one series in ParaView
+pub struct VtkExporter {
+    directory: PathBuf,
+    every: u32,
+    frames: Vec<(f32, String)>,
+}
+
+impl VtkExporter {
+    pub fn new(directory: &Path, every: u32) -> Result<Self, String> {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("could not create export directory '{}': {}", directory.display(), e))?;
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            every: every.max(1),
+            frames: Vec::new(),
+        })
+    }
+
+    pub fn is_due(&self, step: u64) -> bool {
+        step.is_multiple_of(self.every as u64)
+    }
+
+    // expects the cpu side of `sim` to be current, sync first on the gpu backend
+    pub fn write_frame(&mut self, sim: &WaterSimulation, step: u64, time: f32) -> Result<(), String> {
+        let file_name = format!("frame_{:06}.vtu", step);
+        let path = self.directory.join(&file_name);
+        std::fs::write(&path, vtu(sim))
+            .map_err(|e| format!("could not write '{}': {}", path.display(), e))?;
+
+        self.frames.push((time, file_name));
+        let path = self.directory.join("simulation.pvd");
+        std::fs::write(&path, self.pvd())
+            .map_err(|e| format!("could not write '{}': {}", path.display(), e))
+    }
+
+    fn pvd(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\"?>\n");
+        out.push_str("<VTKFile type=\"Collection\" version=\"0.1\" byte_order=\"LittleEndian\">\n");
+        out.push_str("  <Collection>\n");
+        for (time, file_name) in &self.frames {
+            let _ = writeln!(out, "    <DataSet timestep=\"{}\" group=\"\" part=\"0\" file=\"{}\"/>", time, file_name);
+        }
+        out.push_str("  </Collection>\n");
+        out.push_str("</VTKFile>\n");
+        out
+    }
+}
+
+fn vtu(sim: &WaterSimulation) -> String {
+    let n = sim.num_particles as usize;
+    let positions = &sim.positions[..n];
+    let velocities = &sim.velocities[..n];
+    let densities = &sim.densities[..n];
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\"?>\n");
+    out.push_str("<VTKFile type=\"UnstructuredGrid\" version=\"0.1\" byte_order=\"LittleEndian\">\n");
+    out.push_str("  <UnstructuredGrid>\n");
+    let _ = writeln!(out, "    <Piece NumberOfPoints=\"{}\" NumberOfCells=\"{}\">", n, n);
+
+    out.push_str("      <Points>\n");
+    data_array(&mut out, "Position", 3, positions.iter().flat_map(|p| [p.position.x, p.position.y, p.position.z]));
+    out.push_str("      </Points>\n");
+
+    // one vertex cell per particle, otherwise ParaView has nothing to draw
+    out.push_str("      <Cells>\n");
+    out.push_str("        <DataArray type=\"Int32\" Name=\"connectivity\" format=\"ascii\">\n");
+    write_values(&mut out, 0..n);
+    out.push_str("        </DataArray>\n");
+    out.push_str("        <DataArray type=\"Int32\" Name=\"offsets\" format=\"ascii\">\n");
+    write_values(&mut out, 1..n + 1);
+    out.push_str("        </DataArray>\n");
+    out.push_str("        <DataArray type=\"UInt8\" Name=\"types\" format=\"ascii\">\n");
+    write_values(&mut out, std::iter::repeat_n(1, n));
+    out.push_str("        </DataArray>\n");
+    out.push_str("      </Cells>\n");
+
+    out.push_str("      <PointData Scalars=\"density\" Vectors=\"velocity\">\n");
+    data_array(&mut out, "velocity", 3, velocities.iter().flat_map(|v| [v.position.x, v.position.y, v.position.z]));
+    data_array(&mut out, "density", 1, densities.iter().map(|d| d.density.x));
+    data_array(&mut out, "near_density", 1, densities.iter().map(|d| d.density.y));
+    data_array(&mut out, "pressure", 1, densities.iter().map(|d| convert_density_to_pressure(d.density.x, &sim.params)));
+    data_array(&mut out, "near_pressure", 1, densities.iter().map(|d| convert_near_density_to_pressure(d.density.y, &sim.params)));
+    out.push_str("      </PointData>\n");
+
+    out.push_str("    </Piece>\n");
+    out.push_str("  </UnstructuredGrid>\n");
+    out.push_str("</VTKFile>\n");
+    out
+}
+
+fn data_array<I: Iterator<Item = f32>>(out: &mut String, name: &str, components: u32, values: I) {
+    let _ = writeln!(out, "        <DataArray type=\"Float32\" Name=\"{}\" NumberOfComponents=\"{}\" format=\"ascii\">", name, components);
+    write_values(out, values);
+    out.push_str("        </DataArray>\n");
+}
+
+fn write_values<T: std::fmt::Display, I: Iterator<Item = T>>(out: &mut String, values: I) {
+    for (i, value) in values.enumerate() {
+        out.push_str(if i.is_multiple_of(12) { "          " } else { " " });
+        let _ = write!(out, "{}", value);
+        if i % 12 == 11 {
+            out.push('\n');
+        }
+    }
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
@@ -1,17 +1,30 @@
-use bytemuck::{Pod, Zeroable, cast_slice};
-use futures::channel::oneshot;
-use image::buffer;
+use std::collections::HashSet;
+use cgmath::Vector2;
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+use super::boundary::{Boundary, BoundaryData, BoundaryParticle, MAX_BOUNDARY_PARTICLES};
 
 pub struct Grid {
     pub spatial_lookup_buffer: wgpu::Buffer,
     pub start_indices_buffer: wgpu::Buffer,
     pub entries_buffer: wgpu::Buffer,
+    pub cell_counts_buffer: wgpu::Buffer,
+    pub cell_offsets_buffer: wgpu::Buffer,
+    pub table_size_buffer: wgpu::Buffer,
+    pub table_size: usize,
+    // the boundary particles are static, they are binned on the cpu and only uploaded when
+    // they are sampled again
+    pub boundary_particles_buffer: wgpu::Buffer,
+    pub boundary_start_indices_buffer: wgpu::Buffer,
+    pub num_boundary_particles_buffer: wgpu::Buffer,
+    pub boundary_data_buffer: wgpu::Buffer,
     pub grid_bind_layout: wgpu::BindGroupLayout,
     pub grid_bind_group: wgpu::BindGroup,
 } 
 
 impl Grid {
-    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, max_particles: usize) -> Self {
+    // `table_size` is the number of hash buckets, independent of how many particles there are
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, max_particles: usize, table_size: usize) -> Self {
         let spatial_lookup_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Spatial Lookup Buffer"),
             size: (max_particles * std::mem::size_of::<HashCell>()) as wgpu::BufferAddress,
@@ -25,12 +38,12 @@ impl Grid {
 
         let start_indices_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Start Indices Buffer"),
-            size: (max_particles * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            size: (table_size * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         }); 
 
-        let data = vec![max_particles as u32; max_particles];
+        let data = vec![max_particles as u32; table_size];
 
         queue.write_buffer(&start_indices_buffer, 0, bytemuck::cast_slice(&data));
 
@@ -41,6 +54,54 @@ impl Grid {
             mapped_at_creation: false,
         });
 
+        let cell_counts_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell Counts Buffer"),
+            size: (table_size * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let cell_offsets_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cell Offsets Buffer"),
+            size: (table_size * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let table_size_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Table Size Buffer"),
+            contents: bytemuck::cast_slice(&[table_size as u32]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let boundary_particles_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Boundary Particles Buffer"),
+            size: (MAX_BOUNDARY_PARTICLES * std::mem::size_of::<BoundaryParticle>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let boundary_start_indices_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Boundary Start Indices Buffer"),
+            size: (table_size * std::mem::size_of::<u32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let num_boundary_particles_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Num Boundary Particles Buffer"),
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let boundary_data_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Boundary Data Buffer"),
+            size: (max_particles * std::mem::size_of::<BoundaryData>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
         let grid_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("Grid Bind Group Layout"),
             entries: &[
@@ -64,6 +125,76 @@ impl Grid {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false, 
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false, 
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false, 
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -79,6 +210,34 @@ impl Grid {
                     binding: 1,
                     resource: start_indices_buffer.as_entire_binding(),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cell_counts_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: cell_offsets_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: table_size_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: boundary_particles_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: boundary_start_indices_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: num_boundary_particles_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: boundary_data_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -86,130 +245,116 @@ impl Grid {
             spatial_lookup_buffer,
             start_indices_buffer,
             entries_buffer,
+            cell_counts_buffer,
+            cell_offsets_buffer,
+            table_size_buffer,
+            table_size,
+            boundary_particles_buffer,
+            boundary_start_indices_buffer,
+            num_boundary_particles_buffer,
+            boundary_data_buffer,
             grid_bind_layout,
             grid_bind_group,
         }
     }
 
-    pub async fn print_buffer(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        // Create a temporary buffer for reading
-        let temp_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Temp Buffer"),
-            size: self.spatial_lookup_buffer.size(),
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
-
-        // Create command encoder for copying data
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Buffer Copy Encoder"),
-        });
+    // uploads freshly sampled boundary particles, binned for the same table size as the grid
+    pub fn write_boundary(&self, queue: &wgpu::Queue, boundary: &Boundary) {
+        // without any the start indices are never looked at
+        let num_boundary_particles = boundary.particles.len().min(MAX_BOUNDARY_PARTICLES);
+        if num_boundary_particles > 0 {
+            queue.write_buffer(&self.boundary_particles_buffer, 0, bytemuck::cast_slice(&boundary.particles[..num_boundary_particles]));
+            queue.write_buffer(&self.boundary_start_indices_buffer, 0, bytemuck::cast_slice(&boundary.start_indices[..self.table_size]));
+        }
+        queue.write_buffer(&self.num_boundary_particles_buffer, 0, bytemuck::cast_slice(&[num_boundary_particles as u32]));
+    }
+} 
 
-        // Copy data from the storage buffer to the temporary buffer
-        encoder.copy_buffer_to_buffer(
-            &self.spatial_lookup_buffer,
-            0,
-            &temp_buffer,
-            0,
-            self.spatial_lookup_buffer.size(),
-        );
+// Hash table element struct
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+pub struct HashCell {
+    pub particle_index: u32,
+    pub cell_index: u32,
+}
 
-        // Submit the command
-        queue.submit(Some(encoder.finish()));
+// how well the hash spreads the occupied cells over the table. a colliding bucket holds
+// particles from more than one grid cell, so every neighbour lookup into it also walks strangers
+#[derive(Debug, Copy, Clone, Default)]
+pub struct HashStats {
+    pub table_size: usize,
+    pub particles: usize,
+    pub occupied_cells: usize,
+    pub occupied_buckets: usize,
+    pub colliding_buckets: usize,
+    pub max_bucket_len: usize,
+}
 
-        // Map the temporary buffer
-        let buffer_slice = temp_buffer.slice(..);
-        let (sender, receiver) = oneshot::channel();
+impl HashStats {
+    // `sorted` is the binned spatial hash, `cells` the grid cell of every particle
+    pub fn new(sorted: &[HashCell], cells: &[Vector2<i32>], table_size: usize) -> Self {
+        let mut stats = Self { table_size, particles: sorted.len(), ..Self::default() };
+        let mut all_cells = HashSet::new();
 
-        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-            sender.send(result).unwrap();
-        });
+        for bucket in sorted.chunk_by(|a, b| a.cell_index == b.cell_index) {
+            let bucket_cells: HashSet<(i32, i32)> = bucket
+                .iter()
+                .map(|entry| {
+                    let cell = cells[entry.particle_index as usize];
+                    (cell.x, cell.y)
+                })
+                .collect();
 
-        device.poll(wgpu::Maintain::Wait);
-
-        match receiver.await {
-            Ok(Ok(())) => {
-                let data = buffer_slice.get_mapped_range();
-                let cells = cast_slice::<u8, HashCell>(&data);
-                println!("Spatial Lookup Buffer:");
-                for cell in cells {
-                    println!("{:?}", cell);
-                }
-                drop(data);
-                temp_buffer.unmap();
-            }
-            _ => {
-                println!("Failed to map buffer");
+            stats.occupied_buckets += 1;
+            if bucket_cells.len() > 1 {
+                stats.colliding_buckets += 1;
             }
+            stats.max_bucket_len = stats.max_bucket_len.max(bucket.len());
+            all_cells.extend(bucket_cells);
         }
+        stats.occupied_cells = all_cells.len();
+        stats
     }
+}
 
-    pub async fn print_buffer2(&self, device: &wgpu::Device, queue: &wgpu::Queue) {
-        // Create a temporary buffer for reading
-        let temp_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Temp Buffer"),
-            size: self.start_indices_buffer.size(),
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
+impl std::fmt::Display for HashStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} particles in {} cells over {}/{} buckets, {} colliding, largest bucket {}",
+            self.particles, self.occupied_cells, self.occupied_buckets, self.table_size, self.colliding_buckets, self.max_bucket_len,
+        )
+    }
+}
 
-        // Create command encoder for copying data
-        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Buffer Copy Encoder"),
-        });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::cpu_solver::{counting_sort, hash_position};
 
-        // Copy data from the storage buffer to the temporary buffer
-        encoder.copy_buffer_to_buffer(
-            &self.start_indices_buffer,
-            0,
-            &temp_buffer,
-            0,
-            self.start_indices_buffer.size(),
-        );
+    fn stats(cells: &[Vector2<i32>], table_size: usize) -> HashStats {
+        let keys: Vec<u32> = cells.iter().map(|cell| hash_position(*cell, table_size)).collect();
+        let mut sorted = Vec::new();
+        let mut start_indices = vec![0; table_size];
+        counting_sort(&keys, table_size, &mut sorted, &mut start_indices);
+        HashStats::new(&sorted, cells, table_size)
+    }
 
-        // Submit the command
-        queue.submit(Some(encoder.finish()));
+    #[test]
+    fn distinct_cells_in_one_bucket_count_as_a_collision() {
+        let cells = [(0, 0), (0, 0), (3, -2), (3, -2), (3, -2), (7, 1)].map(|(x, y)| Vector2::new(x, y));
 
-        // Map the temporary buffer
-        let buffer_slice = temp_buffer.slice(..);
-        let (sender, receiver) = oneshot::channel();
+        // a single bucket has to take all three cells
+        let crowded = stats(&cells, 1);
+        assert_eq!((crowded.particles, crowded.occupied_cells, crowded.occupied_buckets), (6, 3, 1));
+        assert_eq!((crowded.colliding_buckets, crowded.max_bucket_len), (1, 6));
 
-        buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
-            sender.send(result).unwrap();
-        });
-
-        device.poll(wgpu::Maintain::Wait);
-
-        match receiver.await {
-            Ok(Ok(())) => {
-                let data = buffer_slice.get_mapped_range();
-                let cells = cast_slice::<u8, i32>(&data);
-                println!("inex Buffer:");
-                for cell in cells {
-                    println!("{:?}", cell);
-                }
-                drop(data);
-                temp_buffer.unmap();
-            }
-            _ => {
-                println!("Failed to map buffer");
-            }
-        }
+        // particles sharing a cell share a bucket without colliding
+        let table_size = 4096;
+        let keys: HashSet<u32> = cells.iter().map(|cell| hash_position(*cell, table_size)).collect();
+        assert_eq!(keys.len(), 3, "the cells should hash apart in a large table");
+        let spread = stats(&cells, table_size);
+        assert_eq!((spread.occupied_cells, spread.occupied_buckets), (3, 3));
+        assert_eq!((spread.colliding_buckets, spread.max_bucket_len), (0, 3));
     }
-} 
-
-// Hash table element struct
-#[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-pub struct HashCell {
-    pub particle_index: u32,
-    pub cell_index: u32,
-}
-
-#[repr(C)]
-#[derive(Debug, Copy, Clone, Pod, Zeroable)]
-pub struct Constants {
-    pub k: u32,
-    pub j: u32,
-    pub pwer_of_two: u32,
 }
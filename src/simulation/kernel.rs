@@ -0,0 +1,146 @@
+use serde::Deserialize;
+
+// smoothing kernels, mirrors src/shader/common/kernel.wgsl. every kernel is a shape in
+// q = dist / s_rad that drops to zero at q = 1, times a normalization that makes it integrate
+// to one over its support. the simulation is 2d, the 3d constants are there for reference
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kernel {
+    // (1 - q^2)^3, Müller et al. 2003
+    Poly6 = 0,
+    // (1 - q)^2, what density and pressure always used
+    #[default]
+    Spiky = 1,
+    // the M4 b-spline, Monaghan 1992, scaled to a support of s_rad
+    CubicSpline = 2,
+    // (1 - q)^4 (1 + 4q)
+    WendlandC2 = 3,
+    // (1 - q)^6 (1 + 6q + 35/3 q^2)
+    WendlandC4 = 4,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 5] = [Kernel::Poly6, Kernel::Spiky, Kernel::CubicSpline, Kernel::WendlandC2, Kernel::WendlandC4];
+
+    // unknown values fall back to the spiky kernel, same as the shader
+    pub fn from_index(index: u32) -> Self {
+        match index {
+            0 => Kernel::Poly6,
+            2 => Kernel::CubicSpline,
+            3 => Kernel::WendlandC2,
+            4 => Kernel::WendlandC4,
+            _ => Kernel::Spiky,
+        }
+    }
+
+    // W(dist) in 2d, `smoothing_kernel` in the shader
+    pub fn value(self, s_rad: f32, dist: f32) -> f32 {
+        if dist > s_rad {
+            return 0.0;
+        }
+        self.normalization(s_rad, 2) * self.shape(dist / s_rad)
+    }
+
+    // dW/dr in 2d, `smoothing_kernel_derivative` in the shader
+    pub fn derivative(self, s_rad: f32, dist: f32) -> f32 {
+        if dist > s_rad {
+            return 0.0;
+        }
+        self.normalization(s_rad, 2) * self.shape_derivative(dist / s_rad) / s_rad
+    }
+
+    // the factor in front of the shape for a support of `s_rad` in 2 or 3 dimensions
+    pub fn normalization(self, s_rad: f32, dimensions: u32) -> f32 {
+        const PI: f32 = std::f32::consts::PI;
+        if dimensions == 3 {
+            let volume = PI * s_rad.powi(3);
+            match self {
+                Kernel::Poly6 => 315.0 / (64.0 * volume),
+                Kernel::Spiky => 15.0 / (2.0 * volume),
+                Kernel::CubicSpline => 8.0 / volume,
+                Kernel::WendlandC2 => 21.0 / (2.0 * volume),
+                Kernel::WendlandC4 => 495.0 / (32.0 * volume),
+            }
+        } else {
+            let area = PI * s_rad * s_rad;
+            match self {
+                Kernel::Poly6 => 4.0 / area,
+                Kernel::Spiky => 6.0 / area,
+                Kernel::CubicSpline => 40.0 / (7.0 * area),
+                Kernel::WendlandC2 => 7.0 / area,
+                Kernel::WendlandC4 => 9.0 / area,
+            }
+        }
+    }
+
+    fn shape(self, q: f32) -> f32 {
+        let r = 1.0 - q;
+        match self {
+            Kernel::Poly6 => (1.0 - q * q).powi(3),
+            Kernel::Spiky => r * r,
+            Kernel::CubicSpline if q <= 0.5 => 6.0 * (q * q * q - q * q) + 1.0,
+            Kernel::CubicSpline => 2.0 * r * r * r,
+            Kernel::WendlandC2 => r.powi(4) * (1.0 + 4.0 * q),
+            Kernel::WendlandC4 => r.powi(6) * (1.0 + 6.0 * q + 35.0 / 3.0 * q * q),
+        }
+    }
+
+    fn shape_derivative(self, q: f32) -> f32 {
+        let r = 1.0 - q;
+        match self {
+            Kernel::Poly6 => -6.0 * q * (1.0 - q * q).powi(2),
+            Kernel::Spiky => -2.0 * r,
+            Kernel::CubicSpline if q <= 0.5 => 18.0 * q * q - 12.0 * q,
+            Kernel::CubicSpline => -6.0 * r * r,
+            Kernel::WendlandC2 => -20.0 * q * r.powi(3),
+            Kernel::WendlandC4 => -56.0 / 3.0 * q * (1.0 + 5.0 * q) * r.powi(5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // midpoint rule over the radius, the kernels are radial so the 2d integral is
+    // int 2 pi r W(r) dr and the 3d one int 4 pi r^2 W(r) dr
+    fn integrate(kernel: Kernel, s_rad: f32, dimensions: u32) -> f64 {
+        let steps = 20_000;
+        let dr = s_rad as f64 / steps as f64;
+        let normalization = kernel.normalization(s_rad, dimensions) as f64;
+        (0..steps)
+            .map(|i| {
+                let r = (i as f64 + 0.5) * dr;
+                let w = normalization * kernel.shape((r / s_rad as f64) as f32) as f64;
+                let shell = if dimensions == 3 { 4.0 * std::f64::consts::PI * r * r } else { 2.0 * std::f64::consts::PI * r };
+                shell * w * dr
+            })
+            .sum()
+    }
+
+    #[test]
+    fn kernels_integrate_to_one() {
+        for kernel in Kernel::ALL {
+            for s_rad in [0.4, 1.0, 2.5] {
+                for dimensions in [2, 3] {
+                    let integral = integrate(kernel, s_rad, dimensions);
+                    assert!((integral - 1.0).abs() < 1e-3, "{:?} with radius {} in {}d integrates to {}", kernel, s_rad, dimensions, integral);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let s_rad = 1.3;
+        for kernel in Kernel::ALL {
+            for i in 1..20 {
+                let dist = s_rad * i as f32 / 20.0;
+                let h = 1e-3;
+                let numeric = (kernel.value(s_rad, dist + h) - kernel.value(s_rad, dist - h)) / (2.0 * h);
+                let analytic = kernel.derivative(s_rad, dist);
+                assert!((numeric - analytic).abs() < 1e-2, "{:?} at {}: {} vs {}", kernel, dist, analytic, numeric);
+            }
+        }
+    }
+}
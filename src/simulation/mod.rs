@@ -1,3 +1,18 @@
 pub mod simulation;
 pub mod bounding_box;
-pub mod grid;
\ No newline at end of file
+pub mod grid;
+pub mod boundary;
+pub mod container;
+pub mod obstacle;
+pub mod rigid_body;
+pub mod params;
+pub mod kernel;
+pub mod time_step;
+pub mod stepper;
+pub mod solver;
+pub mod cpu_solver;
+pub mod compute;
+pub mod simulator;
+pub mod scene;
+pub mod snapshot;
+pub mod export;
@@ -0,0 +1,494 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+use super::bounding_box::Vertex;
+use crate::state::{managers::pipeline_manager::PipelineManager, shader_helper::create_shader_module};
+
+// upper bounds of what the gpu buffers hold, anything past them is dropped when the scene loads
+pub const MAX_OBSTACLE_SHAPES: usize = 256;
+pub const MAX_OBSTACLE_POINTS: usize = 4096;
+pub const MAX_SDF_CELLS: usize = 1 << 18;
+
+// same numbering as the SHAPE_ constants in simulation.wgsl
+const SHAPE_CIRCLE: u32 = 0;
+const SHAPE_BOX: u32 = 1;
+const SHAPE_CAPSULE: u32 = 2;
+const SHAPE_POLYGON: u32 = 3;
+
+// distance reported when there is nothing to collide with
+const FAR_AWAY: f32 = 1e9;
+// step of the central differences the surface normal is taken from
+pub(super) const NORMAL_EPSILON: f32 = 0.01;
+
+// one static obstacle inside the container, `[[obstacles]]` in a scene file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Obstacle {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+    },
+    // `angle` in degrees, counter clockwise around the center
+    Box {
+        center: [f32; 2],
+        size: [f32; 2],
+        #[serde(default)]
+        angle: f32,
+    },
+    // a line segment thickened by `radius`
+    Capsule {
+        start: [f32; 2],
+        end: [f32; 2],
+        radius: f32,
+    },
+    // corners in order, either winding, doesn't have to be convex
+    Polygon {
+        points: Vec<[f32; 2]>,
+    },
+    // its parts as a single obstacle, blended into each other over `smoothing`. nested unions
+    // take the smoothing of the outermost one
+    Union {
+        parts: Vec<Obstacle>,
+        #[serde(default)]
+        smoothing: f32,
+    },
+}
+
+// `bake` samples the obstacles into a grid of distances once and has the particles look them
+// up there instead of going through every shape, worth it for polygons with many corners.
+// the outlines are always traced on that grid
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SdfConfig {
+    pub bake: bool,
+    pub cell_size: f32,
+}
+
+impl Default for SdfConfig {
+    fn default() -> Self {
+        Self { bake: false, cell_size: 0.05 }
+    }
+}
+
+// one primitive, mirrors `ObstacleShape` in simulation.wgsl. shapes of the same obstacle share
+// `group` and sit next to each other
+//   circle:  a = center, radius
+//   box:     a = center, half size. b = cos, sin of the angle
+//   capsule: a = start, end. b.x = radius
+//   polygon: the corners are points[first_point..first_point + num_points]
+// b.w is the smoothing of the group
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ObstacleShape {
+    pub kind: u32,
+    pub group: u32,
+    pub first_point: u32,
+    pub num_points: u32,
+    pub a: [f32; 4],
+    pub b: [f32; 4],
+}
+
+unsafe impl Pod for ObstacleShape {}
+unsafe impl Zeroable for ObstacleShape {}
+
+// mirrors `ObstacleInfo` in simulation.wgsl, the baked grid covers the container with its
+// first cell at `field_min`
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct ObstacleInfo {
+    pub num_shapes: u32,
+    pub baked: u32,
+    pub field_size: [u32; 2],
+    pub field_min: [f32; 2],
+    pub cell_size: f32,
+    _padding: f32,
+}
+
+unsafe impl Pod for ObstacleInfo {}
+unsafe impl Zeroable for ObstacleInfo {}
+
+// the obstacles of a scene flattened into what the collision stage reads
+#[derive(Debug, Clone, Default)]
+pub struct Obstacles {
+    pub shapes: Vec<ObstacleShape>,
+    pub points: Vec<[f32; 2]>,
+    // signed distances on the grid of `info`, row by row
+    pub field: Vec<f32>,
+    pub info: ObstacleInfo,
+    // the field cell size the scene asked for, `info` has the one it was baked with
+    sdf_cell_size: f32,
+}
+
+impl Obstacles {
+    // `size` is the container, centred on the origin
+    pub fn new(obstacles: &[Obstacle], sdf: &SdfConfig, size: [f32; 2]) -> Self {
+        let mut flattened = Self::default();
+        for (group, obstacle) in obstacles.iter().enumerate() {
+            let smoothing = match obstacle {
+                Obstacle::Union { smoothing, .. } => smoothing.max(0.0),
+                _ => 0.0,
+            };
+            flattened.push(obstacle, group as u32, smoothing);
+        }
+        if flattened.shapes.len() > MAX_OBSTACLE_SHAPES {
+            log::info!("Scene has more than {} obstacle shapes, dropping {}", MAX_OBSTACLE_SHAPES, flattened.shapes.len() - MAX_OBSTACLE_SHAPES);
+            flattened.shapes.truncate(MAX_OBSTACLE_SHAPES);
+        }
+        flattened.info.num_shapes = flattened.shapes.len() as u32;
+        flattened.sdf_cell_size = sdf.cell_size;
+        flattened.bake(Vector2::from(size), sdf.cell_size);
+        flattened.info.baked = (sdf.bake && !flattened.shapes.is_empty()) as u32;
+        flattened
+    }
+
+    fn push(&mut self, obstacle: &Obstacle, group: u32, smoothing: f32) {
+        let mut shape = ObstacleShape { group, b: [0.0, 0.0, 0.0, smoothing], ..ObstacleShape::default() };
+        match obstacle {
+            Obstacle::Circle { center, radius } => {
+                shape.kind = SHAPE_CIRCLE;
+                shape.a = [center[0], center[1], *radius, 0.0];
+            }
+            Obstacle::Box { center, size, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                shape.kind = SHAPE_BOX;
+                shape.a = [center[0], center[1], size[0] / 2.0, size[1] / 2.0];
+                shape.b = [cos, sin, 0.0, smoothing];
+            }
+            Obstacle::Capsule { start, end, radius } => {
+                shape.kind = SHAPE_CAPSULE;
+                shape.a = [start[0], start[1], end[0], end[1]];
+                shape.b = [*radius, 0.0, 0.0, smoothing];
+            }
+            Obstacle::Polygon { points } => {
+                if points.len() < 3 || self.points.len() + points.len() > MAX_OBSTACLE_POINTS {
+                    log::info!("Skipping a polygon obstacle with {} corners", points.len());
+                    return;
+                }
+                shape.kind = SHAPE_POLYGON;
+                shape.first_point = self.points.len() as u32;
+                shape.num_points = points.len() as u32;
+                self.points.extend_from_slice(points);
+            }
+            Obstacle::Union { parts, .. } => {
+                for part in parts {
+                    self.push(part, group, smoothing);
+                }
+                return;
+            }
+        }
+        self.shapes.push(shape);
+    }
+
+    // samples the analytic distance on a grid over the container, with coarser cells if the
+    // container would need more than MAX_SDF_CELLS of them
+    fn bake(&mut self, size: Vector2<f32>, cell_size: f32) {
+        let mut cell_size = cell_size.max(1e-3);
+        let cells = |cell_size: f32| ((size.x / cell_size).ceil() as usize + 1) * ((size.y / cell_size).ceil() as usize + 1);
+        while cells(cell_size) > MAX_SDF_CELLS {
+            cell_size *= 1.25;
+        }
+        let columns = (size.x / cell_size).ceil() as usize + 1;
+        let rows = (size.y / cell_size).ceil() as usize + 1;
+        let min = -size / 2.0;
+
+        self.info.field_size = [columns as u32, rows as u32];
+        self.info.field_min = min.into();
+        self.info.cell_size = cell_size;
+        self.field = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| self.analytic_distance(min + Vector2::new(column as f32, row as f32) * cell_size))
+            .collect();
+    }
+
+    // bakes the field again over a container of another `size`
+    pub fn resize(&mut self, size: [f32; 2]) {
+        self.bake(Vector2::from(size), self.sdf_cell_size);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shapes.is_empty()
+    }
+
+    // signed distance from `p` to the closest obstacle surface, negative inside.
+    // same as obstacle_distance in the shader
+    pub fn distance(&self, p: Vector2<f32>) -> f32 {
+        if self.info.baked != 0 {
+            self.field_distance(p)
+        } else {
+            self.analytic_distance(p)
+        }
+    }
+
+    // the shapes of one obstacle are smooth-min'd together, the obstacles a plain min of those
+    fn analytic_distance(&self, p: Vector2<f32>) -> f32 {
+        let Some(first) = self.shapes.first() else { return FAR_AWAY };
+        let mut nearest = FAR_AWAY;
+        let mut group = first.group;
+        let mut group_distance = FAR_AWAY;
+        for shape in &self.shapes {
+            let d = self.shape_distance(shape, p);
+            if shape.group != group {
+                nearest = nearest.min(group_distance);
+                group = shape.group;
+                group_distance = d;
+            } else {
+                group_distance = smooth_min(group_distance, d, shape.b[3]);
+            }
+        }
+        nearest.min(group_distance)
+    }
+
+    fn shape_distance(&self, shape: &ObstacleShape, p: Vector2<f32>) -> f32 {
+        let a = shape.a;
+        match shape.kind {
+            SHAPE_CIRCLE => (p - Vector2::new(a[0], a[1])).magnitude() - a[2],
+            SHAPE_BOX => {
+                // into the frame of the box, then the usual axis aligned distance
+                let offset = p - Vector2::new(a[0], a[1]);
+                let (cos, sin) = (shape.b[0], shape.b[1]);
+                let local = Vector2::new(cos * offset.x + sin * offset.y, -sin * offset.x + cos * offset.y);
+                let d = Vector2::new(local.x.abs() - a[2], local.y.abs() - a[3]);
+                Vector2::new(d.x.max(0.0), d.y.max(0.0)).magnitude() + d.x.max(d.y).min(0.0)
+            }
+            SHAPE_CAPSULE => {
+                let start = Vector2::new(a[0], a[1]);
+                let along = Vector2::new(a[2], a[3]) - start;
+                let offset = p - start;
+                let h = (offset.dot(along) / along.magnitude2().max(1e-12)).clamp(0.0, 1.0);
+                (offset - along * h).magnitude() - shape.b[0]
+            }
+            _ => {
+                let first = shape.first_point as usize;
+                let corners = &self.points[first..first + shape.num_points as usize];
+                polygon_distance(corners, p)
+            }
+        }
+    }
+
+    // bilinear in the baked grid, clamped to its edges
+    fn field_distance(&self, p: Vector2<f32>) -> f32 {
+        let [columns, rows] = self.info.field_size;
+        let cell = (p - Vector2::from(self.info.field_min)) / self.info.cell_size;
+        let x = cell.x.clamp(0.0, (columns - 1) as f32);
+        let y = cell.y.clamp(0.0, (rows - 1) as f32);
+        let x0 = (x.floor() as u32).min(columns.saturating_sub(2));
+        let y0 = (y.floor() as u32).min(rows.saturating_sub(2));
+        let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+        let at = |column: u32, row: u32| self.field[(row * columns + column) as usize];
+        let bottom = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+        let top = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+        bottom * (1.0 - ty) + top * ty
+    }
+
+    // points away from the obstacles, central differences of the distance
+    pub fn normal(&self, p: Vector2<f32>) -> Vector2<f32> {
+        let dx = Vector2::new(NORMAL_EPSILON, 0.0);
+        let dy = Vector2::new(0.0, NORMAL_EPSILON);
+        let gradient = Vector2::new(
+            self.distance(p + dx) - self.distance(p - dx),
+            self.distance(p + dy) - self.distance(p - dy),
+        );
+        if gradient.magnitude2() < 1e-12 {
+            return Vector2::new(0.0, 1.0);
+        }
+        gradient.normalize()
+    }
+
+    // pushes a particle of `radius` that ended up inside an obstacle back onto its surface and
+    // reflects the part of the velocity going in, the same response as check_boundaries.
+    // same as collide_with_obstacles in the shader
+    pub fn collide(&self, pos: &mut Vector3<f32>, vel: &mut Vector3<f32>, radius: f32, restitution: f32) {
+        if self.is_empty() {
+            return;
+        }
+        let penetration = radius - self.distance(pos.truncate());
+        if penetration <= 0.0 {
+            return;
+        }
+        let normal = self.normal(pos.truncate()).extend(0.0);
+        *pos += normal * penetration;
+        let normal_speed = vel.dot(normal);
+        if normal_speed < 0.0 {
+            *vel -= normal * (1.0 + restitution) * normal_speed;
+        }
+    }
+
+    // the zero crossing of the baked grid as line segments, two points each, by marching squares
+    pub fn outline(&self) -> Vec<Vector2<f32>> {
+        let mut segments = Vec::new();
+        if self.is_empty() {
+            return segments;
+        }
+        let [columns, rows] = self.info.field_size;
+        let min = Vector2::from(self.info.field_min);
+        let corner = |column: u32, row: u32| {
+            (min + Vector2::new(column as f32, row as f32) * self.info.cell_size, self.field[(row * columns + column) as usize])
+        };
+
+        for row in 0..rows.saturating_sub(1) {
+            for column in 0..columns.saturating_sub(1) {
+                // counter clockwise from the bottom left
+                let corners = [corner(column, row), corner(column + 1, row), corner(column + 1, row + 1), corner(column, row + 1)];
+                let case = corners.iter().enumerate().fold(0, |case, (i, (_, d))| case | (((*d < 0.0) as usize) << i));
+                // edge i runs from corner i to corner i + 1
+                let crossing = |edge: usize| {
+                    let (a, da) = corners[edge];
+                    let (b, db) = corners[(edge + 1) % 4];
+                    a + (b - a) * (da / (da - db))
+                };
+                for pair in MARCHING_SQUARES[case].chunks(2) {
+                    segments.push(crossing(pair[0]));
+                    segments.push(crossing(pair[1]));
+                }
+            }
+        }
+        segments
+    }
+}
+
+// the edges the outline crosses for every combination of corners inside, in pairs
+const MARCHING_SQUARES: [&[usize]; 16] = [
+    &[],
+    &[3, 0],
+    &[0, 1],
+    &[3, 1],
+    &[1, 2],
+    &[3, 0, 1, 2],
+    &[0, 2],
+    &[3, 2],
+    &[2, 3],
+    &[0, 2],
+    &[0, 1, 2, 3],
+    &[1, 2],
+    &[1, 3],
+    &[0, 1],
+    &[0, 3],
+    &[],
+];
+
+// exact distance to a closed polygon, negative inside by the crossing number
+pub(super) fn polygon_distance(corners: &[[f32; 2]], p: Vector2<f32>) -> f32 {
+    let mut nearest = (p - Vector2::from(corners[0])).magnitude2();
+    let mut side = 1.0;
+    let mut j = corners.len() - 1;
+    for i in 0..corners.len() {
+        let vi = Vector2::from(corners[i]);
+        let vj = Vector2::from(corners[j]);
+        let edge = vj - vi;
+        let w = p - vi;
+        let closest = w - edge * (w.dot(edge) / edge.magnitude2().max(1e-12)).clamp(0.0, 1.0);
+        nearest = nearest.min(closest.magnitude2());
+
+        let crossing = [p.y >= vi.y, p.y < vj.y, edge.x * w.y > edge.y * w.x];
+        if crossing.iter().all(|c| *c) || crossing.iter().all(|c| !*c) {
+            side = -side;
+        }
+        j = i;
+    }
+    side * nearest.sqrt()
+}
+
+// polynomial smooth minimum, a plain min for k = 0
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - h * h * k * 0.25
+}
+
+// the obstacle outlines as orange lines, the same kind of pipeline as `BoundingBox` but with
+// the segments already in world space
+pub struct ObstacleOutline {
+    pub vertex_buffer: wgpu::Buffer,
+    pub num_vertices: u32,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl ObstacleOutline {
+    pub fn new(obstacles: &Obstacles, device: &wgpu::Device, pipeline_manager: &PipelineManager, camera_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let mut vertices: Vec<Vertex> = obstacles.outline().into_iter().map(Vertex::new).collect();
+        let num_vertices = vertices.len() as u32;
+        // a buffer can't be empty
+        if vertices.is_empty() {
+            vertices.push(Vertex::new(Vector2::new(0.0, 0.0)));
+        }
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Obstacle Outline Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let vert_shader = create_shader_module(device, "Vert Obstacle Shader", include_str!("../shader/obstacle/obstacle.vert"), naga::ShaderStage::Vertex);
+        let frag_shader = create_shader_module(device, "Frag Obstacle Shader", include_str!("../shader/obstacle/obstacle.frag"), naga::ShaderStage::Fragment);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Obstacle Pipeline Layout"),
+            bind_group_layouts: &[camera_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = pipeline_manager.create_render_pipeline(
+            "Obstacle Pipeline",
+            &pipeline_layout,
+            &vert_shader,
+            &frag_shader,
+            &[Vertex::desc()],
+            Some(wgpu::BlendState::REPLACE),
+            wgpu::PrimitiveTopology::LineList,
+            None,
+        );
+
+        Self {
+            vertex_buffer,
+            num_vertices,
+            pipeline,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene_obstacles() -> Vec<Obstacle> {
+        vec![
+            Obstacle::Circle { center: [-5.0, 0.0], radius: 2.0 },
+            Obstacle::Box { center: [5.0, 0.0], size: [4.0, 2.0], angle: 30.0 },
+            Obstacle::Union {
+                parts: vec![
+                    Obstacle::Capsule { start: [-3.0, -6.0], end: [3.0, -6.0], radius: 0.5 },
+                    Obstacle::Polygon { points: vec![[-1.0, 4.0], [1.0, 4.0], [0.0, 7.0]] },
+                ],
+                smoothing: 0.5,
+            },
+        ]
+    }
+
+    // the baked grid is only an approximation of the shapes, but not by more than a cell
+    #[test]
+    fn baked_distance_follows_the_shapes() {
+        let size = [30.0, 20.0];
+        let analytic = Obstacles::new(&scene_obstacles(), &SdfConfig { bake: false, cell_size: 0.1 }, size);
+        let baked = Obstacles::new(&scene_obstacles(), &SdfConfig { bake: true, cell_size: 0.1 }, size);
+
+        assert!(analytic.distance(Vector2::new(-5.0, 0.0)) < 0.0);
+        assert!(analytic.distance(Vector2::new(0.0, 5.0)) < 0.0);
+        assert!((analytic.distance(Vector2::new(-5.0, 3.0)) - 1.0).abs() < 1e-4);
+        for i in 0..200 {
+            let p = Vector2::new((i as f32 * 0.731).sin() * 14.0, (i as f32 * 0.377).cos() * 9.0);
+            assert!((analytic.distance(p) - baked.distance(p)).abs() < 0.1, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn resize_bakes_over_the_new_container() {
+        let mut obstacles = Obstacles::new(&scene_obstacles(), &SdfConfig { bake: true, cell_size: 0.1 }, [30.0, 20.0]);
+        obstacles.resize([60.0, 20.0]);
+        assert_eq!(obstacles.info.field_min, [-30.0, -10.0]);
+        assert_eq!(obstacles.info.cell_size, 0.1);
+        // out where the old field ended it still follows the shapes
+        let p = Vector2::new(25.0, 0.0);
+        assert!((obstacles.distance(p) - obstacles.analytic_distance(p)).abs() < 0.1);
+    }
+}
@@ -0,0 +1,119 @@
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Deserializer};
+use super::kernel::Kernel;
+
+// every physical knob the compute kernels read, mirrors `SimParams` in simulation.wgsl
+// uploaded as a single uniform each frame, so keep it a multiple of 16 bytes
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimParams {
+    pub gravity: f32,
+    pub smoothing_radius: f32,
+    pub pressure_multiplier: f32,
+    pub near_pressure_multiplier: f32,
+    pub target_density: f32,
+    pub viscosity_strength: f32,
+    pub interaction_radius: f32,
+    // acceleration towards the mouse per unit the particle is inside the interaction radius
+    pub interaction_strength: f32,
+    pub boundary_restitution: f32,
+    // an `EquationOfState` as u32 so the struct stays plain data, named in scene files
+    #[serde(deserialize_with = "deserialize_equation_of_state")]
+    pub equation_of_state: u32,
+    // B and gamma of the tait equation
+    pub tait_stiffness: f32,
+    pub tait_gamma: f32,
+    // a `Kernel` as u32, used for density, pressure and viscosity alike
+    #[serde(deserialize_with = "deserialize_kernel")]
+    pub kernel: u32,
+    // gamma of the akinci cohesion and curvature forces, 0 turns the stage off
+    pub surface_tension: f32,
+    // epsilon of the vorticity confinement force, 0 turns the stage off
+    pub vorticity_confinement: f32,
+    // c of the xsph velocity smoothing, alongside or instead of the viscosity. 0 turns it off
+    pub xsph: f32,
+}
+
+unsafe impl Pod for SimParams {}
+unsafe impl Zeroable for SimParams {}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self {
+            gravity: 9.81,
+            smoothing_radius: 1.0,
+            pressure_multiplier: 50.0,
+            near_pressure_multiplier: 10.0,
+            target_density: 5.0,
+            viscosity_strength: 0.1,
+            interaction_radius: 3.0,
+            // the push a particle used to get every 60 Hz frame
+            interaction_strength: 60.0,
+            boundary_restitution: 0.9,
+            equation_of_state: EquationOfState::Linear as u32,
+            // same slope as the linear law at the default target density
+            tait_stiffness: 35.0,
+            tait_gamma: 7.0,
+            kernel: Kernel::Spiky as u32,
+            surface_tension: 0.0,
+            vorticity_confinement: 0.0,
+            xsph: 0.0,
+        }
+    }
+}
+
+impl SimParams {
+    // width of a spatial hash cell. the 3x3 neighbour stencil only reaches every particle inside
+    // the smoothing radius when a cell is at least that wide, so it follows the radius at runtime
+    pub fn cell_size(&self) -> f32 {
+        self.smoothing_radius
+    }
+
+    pub fn equation_of_state(&self) -> EquationOfState {
+        EquationOfState::from_index(self.equation_of_state)
+    }
+
+    pub fn kernel(&self) -> Kernel {
+        Kernel::from_index(self.kernel)
+    }
+}
+
+// how a density turns into a pressure, `convert_density_to_pressure` in simulation.wgsl.
+// the discriminants are what the shader sees in `SimParams::equation_of_state`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EquationOfState {
+    // pressure_multiplier * (density - target_density)
+    #[default]
+    Linear = 0,
+    // the linear law without negative pressures, so sparse regions don't pull into clumps
+    ClampedLinear = 1,
+    // tait_stiffness * ((density / target_density)^tait_gamma - 1), the usual wcsph law
+    Tait = 2,
+    ClampedTait = 3,
+}
+
+impl EquationOfState {
+    // unknown values fall back to the linear law, same as the shader
+    pub fn from_index(index: u32) -> Self {
+        match index {
+            1 => EquationOfState::ClampedLinear,
+            2 => EquationOfState::Tait,
+            3 => EquationOfState::ClampedTait,
+            _ => EquationOfState::Linear,
+        }
+    }
+
+    pub fn is_clamped(self) -> bool {
+        matches!(self, EquationOfState::ClampedLinear | EquationOfState::ClampedTait)
+    }
+}
+
+fn deserialize_equation_of_state<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    EquationOfState::deserialize(deserializer).map(|eos| eos as u32)
+}
+
+fn deserialize_kernel<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    Kernel::deserialize(deserializer).map(|kernel| kernel as u32)
+}
@@ -0,0 +1,575 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use serde::Deserialize;
+use super::boundary::BOUNDARY_SPACING;
+use super::container::BoundaryMode;
+use super::cpu_solver::{rotate, Bounds, MASS};
+use super::obstacle::{polygon_distance, NORMAL_EPSILON};
+use super::params::SimParams;
+use crate::state::{managers::pipeline_manager::PipelineManager, shader_helper::create_shader_module};
+
+// upper bounds of what the gpu buffers hold, anything past them is dropped when the scene loads
+pub const MAX_BODIES: usize = 64;
+// corners of a polygon body, also the segments its outline is drawn with
+pub const MAX_BODY_POINTS: usize = 32;
+// boundary particles on the outlines of all bodies together, long outlines are sampled more
+// coarsely instead
+pub const MAX_BODY_SAMPLES: usize = 4096;
+
+// same numbering as the BODY_ constants in simulation.wgsl
+const BODY_CIRCLE: u32 = 0;
+const BODY_POLYGON: u32 = 1;
+
+// one rigid body floating in the fluid, `[[bodies]]` in a scene file. `density` is in the same
+// units as the target density of the fluid, so anything below it floats. a `pinned` body keeps
+// its position and only turns, like a paddle wheel on its axle
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Body {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+        density: f32,
+        #[serde(default)]
+        velocity: [f32; 2],
+        #[serde(default)]
+        angular_velocity: f32,
+        #[serde(default)]
+        pinned: bool,
+    },
+    // corners in world space and in order, either winding. turns around its centroid
+    Polygon {
+        points: Vec<[f32; 2]>,
+        density: f32,
+        #[serde(default)]
+        velocity: [f32; 2],
+        #[serde(default)]
+        angular_velocity: f32,
+        #[serde(default)]
+        pinned: bool,
+    },
+}
+
+// one body, mirrors `RigidBody` in simulation.wgsl and body.vert. `radius` is the circle's, or
+// the bounding radius of a polygon whose corners are
+// points[first_point..first_point + num_points] around its centroid
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RigidBody {
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    pub angle: f32,
+    pub angular_velocity: f32,
+    pub inverse_mass: f32,
+    pub inverse_inertia: f32,
+    pub kind: u32,
+    pub first_point: u32,
+    pub num_points: u32,
+    pub radius: f32,
+}
+
+unsafe impl Pod for RigidBody {}
+unsafe impl Zeroable for RigidBody {}
+
+// one boundary particle on the outline of a body, in body space. mirrors `BodySample` in
+// simulation.wgsl, `psi` stands in for its mass like for a BoundaryParticle
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct BodySample {
+    pub position: [f32; 2],
+    pub psi: f32,
+    _padding: f32,
+}
+
+unsafe impl Pod for BodySample {}
+unsafe impl Zeroable for BodySample {}
+
+// what the fluid pushed into one body over a step, the linear impulse and its torque
+#[derive(Debug, Copy, Clone)]
+pub struct BodyImpulse {
+    pub impulse: Vector2<f32>,
+    pub torque: f32,
+}
+
+impl Default for BodyImpulse {
+    fn default() -> Self {
+        Self { impulse: Vector2::new(0.0, 0.0), torque: 0.0 }
+    }
+}
+
+// everything the samples depend on besides the outlines, they are sampled again once it changes
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Sampling {
+    smoothing_radius: f32,
+    kernel: u32,
+    target_density: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bodies {
+    pub bodies: Vec<RigidBody>,
+    // polygon corners in body space
+    pub points: Vec<[f32; 2]>,
+    // boundary particles on the outlines, Akinci et al. 2012 like the walls. body by body, the
+    // ones of body i are samples[first..first + count] with [first, count] = sample_ranges[i]
+    pub samples: Vec<BodySample>,
+    pub sample_ranges: Vec<[u32; 2]>,
+    sampling: Option<Sampling>,
+}
+
+impl Bodies {
+    pub fn new(bodies: &[Body]) -> Self {
+        let mut flattened = Self::default();
+        for body in bodies {
+            if flattened.bodies.len() == MAX_BODIES {
+                log::info!("Scene has more than {} bodies, dropping the rest", MAX_BODIES);
+                break;
+            }
+            flattened.push(body);
+        }
+        flattened
+    }
+
+    fn push(&mut self, body: &Body) {
+        let (mut rigid_body, density, pinned) = match body {
+            Body::Circle { center, radius, density, velocity, angular_velocity, pinned } => {
+                let mass = density * std::f32::consts::PI * radius * radius;
+                let rigid_body = RigidBody {
+                    position: *center,
+                    velocity: *velocity,
+                    angular_velocity: *angular_velocity,
+                    inverse_mass: 1.0 / mass,
+                    inverse_inertia: 2.0 / (mass * radius * radius),
+                    kind: BODY_CIRCLE,
+                    radius: *radius,
+                    ..RigidBody::default()
+                };
+                (rigid_body, *density, *pinned)
+            }
+            Body::Polygon { points, density, velocity, angular_velocity, pinned } => {
+                if points.len() < 3 || points.len() > MAX_BODY_POINTS {
+                    log::info!("Skipping a polygon body with {} corners, it needs 3 to {}", points.len(), MAX_BODY_POINTS);
+                    return;
+                }
+                let (centroid, area, second_moment) = polygon_mass_properties(points);
+                let mass = density * area;
+                let local: Vec<[f32; 2]> = points.iter().map(|p| (Vector2::from(*p) - centroid).into()).collect();
+                let rigid_body = RigidBody {
+                    position: centroid.into(),
+                    velocity: *velocity,
+                    angular_velocity: *angular_velocity,
+                    inverse_mass: 1.0 / mass,
+                    inverse_inertia: 1.0 / (density * second_moment),
+                    kind: BODY_POLYGON,
+                    first_point: self.points.len() as u32,
+                    num_points: local.len() as u32,
+                    radius: local.iter().map(|p| Vector2::from(*p).magnitude()).fold(0.0, f32::max),
+                    ..RigidBody::default()
+                };
+                self.points.extend(local);
+                (rigid_body, *density, *pinned)
+            }
+        };
+        if density <= 0.0 || !rigid_body.inverse_mass.is_finite() || !rigid_body.inverse_inertia.is_finite() {
+            log::info!("Skipping a body without any mass");
+            return;
+        }
+        if pinned {
+            rigid_body.velocity = [0.0, 0.0];
+            rigid_body.inverse_mass = 0.0;
+        }
+        self.bodies.push(rigid_body);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bodies.len()
+    }
+
+    // samples the outlines again if the parameters changed since the last time, returns whether
+    // it did so the gpu copy can follow
+    pub fn update_samples(&mut self, params: &SimParams) -> bool {
+        let sampling = Sampling {
+            smoothing_radius: params.smoothing_radius,
+            kernel: params.kernel,
+            target_density: params.target_density,
+        };
+        if self.sampling == Some(sampling) {
+            return false;
+        }
+        self.sample(params);
+        self.sampling = Some(sampling);
+        true
+    }
+
+    // a single layer of particles along every outline. psi_b = rho_0 / sum_k W_bk over the
+    // particles of the same body, so a body adds the same density as a wall however it was sampled
+    fn sample(&mut self, params: &SimParams) {
+        let perimeter: f32 = self.bodies.iter().map(|body| self.perimeter(body)).sum();
+        let mut spacing = params.smoothing_radius * BOUNDARY_SPACING;
+        if perimeter / spacing > MAX_BODY_SAMPLES as f32 {
+            spacing = perimeter / MAX_BODY_SAMPLES as f32;
+            log::info!("Bodies need more than {} boundary particles, spacing them {:.3} apart", MAX_BODY_SAMPLES, spacing);
+        }
+        // the fluid stays about half a rest spacing off a layer of boundary particles, the side
+        // of the square a particle fills at the rest density. the layer sits that far inside the
+        // outline so the fluid meets the body there, it would displace a skin of fluid around
+        // the body as well otherwise
+        let inset = (MASS / params.target_density).sqrt() / 2.0;
+
+        self.samples.clear();
+        self.sample_ranges.clear();
+        for body in &self.bodies {
+            let mut positions = Vec::new();
+            if body.kind == BODY_CIRCLE {
+                let radius = (body.radius - inset).max(body.radius / 2.0);
+                let count = ((2.0 * std::f32::consts::PI * radius / spacing).ceil() as usize).max(3);
+                for k in 0..count {
+                    let angle = 2.0 * std::f32::consts::PI * k as f32 / count as f32;
+                    positions.push(Vector2::new(angle.cos(), angle.sin()) * radius);
+                }
+            } else {
+                let first = body.first_point as usize;
+                let corners = inset_polygon(&self.points[first..first + body.num_points as usize], inset);
+                for (i, a) in corners.iter().enumerate() {
+                    let b = corners[(i + 1) % corners.len()];
+                    let count = (((b - a).magnitude() / spacing).ceil() as usize).max(1);
+                    for k in 0..count {
+                        positions.push(a + (b - a) * (k as f32 / count as f32));
+                    }
+                }
+            }
+            let room = MAX_BODY_SAMPLES - self.samples.len();
+            positions.truncate(room);
+
+            self.sample_ranges.push([self.samples.len() as u32, positions.len() as u32]);
+            for p in &positions {
+                let volume: f32 = positions
+                    .iter()
+                    .map(|q| (p - q).magnitude())
+                    .filter(|dist| *dist <= params.smoothing_radius)
+                    .map(|dist| params.kernel().value(params.smoothing_radius, dist))
+                    .sum();
+                self.samples.push(BodySample { position: (*p).into(), psi: params.target_density / volume, _padding: 0.0 });
+            }
+        }
+    }
+
+    fn perimeter(&self, body: &RigidBody) -> f32 {
+        if body.kind == BODY_CIRCLE {
+            return 2.0 * std::f32::consts::PI * body.radius;
+        }
+        let first = body.first_point as usize;
+        let corners = &self.points[first..first + body.num_points as usize];
+        corners
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (Vector2::from(corners[(i + 1) % corners.len()]) - Vector2::from(*a)).magnitude())
+            .sum()
+    }
+
+    // calls `f(body, psi, offset_to_sample, distance, arm)` for every boundary particle of a body
+    // inside `smoothing_radius` of `position`. `arm` is the particle's offset from the body's
+    // position, both in world orientation. same as the body loop of fast_calculate_boundary
+    pub fn for_each_sample<F: FnMut(usize, f32, Vector3<f32>, f32, Vector2<f32>)>(&self, position: Vector3<f32>, smoothing_radius: f32, bounds: Bounds, mut f: F) {
+        let sqr_radius = smoothing_radius * smoothing_radius;
+        for (index, body) in self.bodies.iter().enumerate() {
+            let offset_to_body = bounds.nearest_image((Vector2::from(body.position) - position.truncate()).extend(0.0)).truncate();
+            let reach = body.radius + smoothing_radius;
+            if offset_to_body.magnitude2() > reach * reach {
+                continue;
+            }
+            let [first, count] = self.sample_ranges[index];
+            for sample in &self.samples[first as usize..(first + count) as usize] {
+                let arm = rotate(Vector2::from(sample.position), body.angle);
+                let offset = offset_to_body + arm;
+                let sqr_dst = offset.magnitude2();
+                if sqr_dst > sqr_radius {
+                    continue;
+                }
+                f(index, sample.psi, offset.extend(0.0), sqr_dst.sqrt(), arm);
+            }
+        }
+    }
+
+    // how fast the surface of body `index` moves at `arm` from its position
+    pub fn surface_velocity(&self, index: usize, arm: Vector2<f32>) -> Vector2<f32> {
+        let body = &self.bodies[index];
+        Vector2::from(body.velocity) + perpendicular(arm) * body.angular_velocity
+    }
+
+    // signed distance to the surface of a body from a point in its own frame
+    fn local_distance(&self, body: &RigidBody, p: Vector2<f32>) -> f32 {
+        if body.kind == BODY_CIRCLE {
+            return p.magnitude() - body.radius;
+        }
+        let first = body.first_point as usize;
+        polygon_distance(&self.points[first..first + body.num_points as usize], p)
+    }
+
+    fn local_normal(&self, body: &RigidBody, p: Vector2<f32>) -> Vector2<f32> {
+        let dx = Vector2::new(NORMAL_EPSILON, 0.0);
+        let dy = Vector2::new(0.0, NORMAL_EPSILON);
+        let gradient = Vector2::new(
+            self.local_distance(body, p + dx) - self.local_distance(body, p - dx),
+            self.local_distance(body, p + dy) - self.local_distance(body, p - dy),
+        );
+        if gradient.magnitude2() < 1e-12 {
+            return Vector2::new(0.0, 1.0);
+        }
+        gradient.normalize()
+    }
+
+    // pushes a fluid particle of `radius` out of body `index` and takes away the part of its
+    // velocity going into the body's surface. the boundary particles keep the fluid off, this is
+    // for whatever still gets through. inelastic, the fluid doesn't bounce off floating things.
+    // returns what the body gets back, same as body_contact in the shader
+    pub fn contact(&self, index: usize, pos: &mut Vector3<f32>, vel: &mut Vector3<f32>, radius: f32, bounds: Bounds) -> BodyImpulse {
+        let body = &self.bodies[index];
+        let offset = bounds.nearest_image((pos.truncate() - Vector2::from(body.position)).extend(0.0)).truncate();
+        let reach = body.radius + radius;
+        if offset.magnitude2() > reach * reach {
+            return BodyImpulse::default();
+        }
+        let local = rotate(offset, -body.angle);
+        let penetration = radius - self.local_distance(body, local);
+        if penetration <= 0.0 {
+            return BodyImpulse::default();
+        }
+        let normal = rotate(self.local_normal(body, local), body.angle);
+        *pos += (normal * penetration).extend(0.0);
+
+        let arm = offset + normal * penetration;
+        let surface_velocity = Vector2::from(body.velocity) + perpendicular(arm) * body.angular_velocity;
+        let normal_speed = (vel.truncate() - surface_velocity).dot(normal);
+        if normal_speed >= 0.0 {
+            return BodyImpulse::default();
+        }
+        let arm_cross_normal = cross(arm, normal);
+        let effective_mass = 1.0 / MASS + body.inverse_mass + body.inverse_inertia * arm_cross_normal * arm_cross_normal;
+        let j = -normal_speed / effective_mass;
+        *vel += (normal * (j / MASS)).extend(0.0);
+        BodyImpulse { impulse: -normal * j, torque: -j * arm_cross_normal }
+    }
+
+    // gravity and what the fluid pushed into them, then the container walls. same as
+    // integrate_bodies in the shader
+    pub fn integrate(&mut self, impulses: &[BodyImpulse], params: &SimParams, bounds: Bounds, delta_time: f32) {
+        for (index, received) in impulses.iter().enumerate().take(self.bodies.len()) {
+            let mut body = self.bodies[index];
+            let mut velocity = Vector2::from(body.velocity);
+            if body.inverse_mass > 0.0 {
+                velocity += received.impulse * body.inverse_mass;
+                velocity.y -= params.gravity * delta_time;
+            }
+            body.angular_velocity += received.torque * body.inverse_inertia;
+            body.velocity = velocity.into();
+            body.position = (Vector2::from(body.position) + velocity * delta_time).into();
+            body.angle += body.angular_velocity * delta_time;
+
+            for corner in 0..self.num_corners(&body) {
+                let arm = self.corner(&body, corner);
+                collide_with_walls(&mut body, arm, bounds, params.boundary_restitution);
+            }
+            body.position = bounds.wrap(Vector2::from(body.position)).into();
+            self.bodies[index] = body;
+        }
+    }
+
+    // the points of a body that are checked against the walls, the four extremes of a circle
+    fn num_corners(&self, body: &RigidBody) -> u32 {
+        if body.kind == BODY_CIRCLE { 4 } else { body.num_points }
+    }
+
+    // offset of a corner from the body's position, in world orientation
+    fn corner(&self, body: &RigidBody, corner: u32) -> Vector2<f32> {
+        if body.kind == BODY_CIRCLE {
+            return CIRCLE_CORNERS[corner as usize] * body.radius;
+        }
+        rotate(Vector2::from(self.points[(body.first_point + corner) as usize]), body.angle)
+    }
+}
+
+const CIRCLE_CORNERS: [Vector2<f32>; 4] = [
+    Vector2 { x: 1.0, y: 0.0 },
+    Vector2 { x: -1.0, y: 0.0 },
+    Vector2 { x: 0.0, y: 1.0 },
+    Vector2 { x: 0.0, y: -1.0 },
+];
+
+// moves a corner that left the container back in and bounces the body off the wall with
+// `restitution`, like check_boundaries does for a particle. in the frame of the container and
+// relative to the velocity of its wall. only reflective axes have walls
+fn collide_with_walls(body: &mut RigidBody, arm: Vector2<f32>, bounds: Bounds, restitution: f32) {
+    let half_size = bounds.size / 2.0;
+    let point = Vector2::from(body.position) + arm;
+    let local = bounds.to_local(point);
+    let walls = [
+        (0, -half_size.x - local.x, Vector2::new(1.0, 0.0)),
+        (0, local.x - half_size.x, Vector2::new(-1.0, 0.0)),
+        (1, -half_size.y - local.y, Vector2::new(0.0, 1.0)),
+        (1, local.y - half_size.y, Vector2::new(0.0, -1.0)),
+    ];
+    let wall_velocity = bounds.velocity_at(point);
+    for (axis, penetration, local_normal) in walls {
+        if penetration <= 0.0 || bounds.modes[axis] != BoundaryMode::Reflective {
+            continue;
+        }
+        let normal = bounds.rotate(local_normal);
+        if body.inverse_mass > 0.0 {
+            body.position = (Vector2::from(body.position) + normal * penetration).into();
+        }
+        let point_velocity = Vector2::from(body.velocity) + perpendicular(arm) * body.angular_velocity - wall_velocity;
+        let normal_speed = point_velocity.dot(normal);
+        if normal_speed >= 0.0 {
+            continue;
+        }
+        let arm_cross_normal = cross(arm, normal);
+        let effective_mass = body.inverse_mass + body.inverse_inertia * arm_cross_normal * arm_cross_normal;
+        let j = -(1.0 + restitution) * normal_speed / effective_mass.max(1e-12);
+        body.velocity = (Vector2::from(body.velocity) + normal * (j * body.inverse_mass)).into();
+        body.angular_velocity += j * arm_cross_normal * body.inverse_inertia;
+    }
+}
+
+// the corners of a polygon moved `inset` into it, where the inward shifted lines of its two
+// edges meet. either winding
+fn inset_polygon(points: &[[f32; 2]], inset: f32) -> Vec<Vector2<f32>> {
+    let signed_area: f32 = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| cross(Vector2::from(*p), Vector2::from(points[(i + 1) % points.len()])) / 2.0)
+        .sum();
+    let winding = signed_area.signum();
+    let inward = |a: Vector2<f32>, b: Vector2<f32>| perpendicular((b - a).normalize()) * winding;
+    (0..points.len())
+        .map(|i| {
+            let previous = Vector2::from(points[(i + points.len() - 1) % points.len()]);
+            let corner = Vector2::from(points[i]);
+            let next = Vector2::from(points[(i + 1) % points.len()]);
+            let (a, b) = (inward(previous, corner), inward(corner, next));
+            // a corner folded back on itself would send it off to infinity
+            corner + (a + b) * (inset / (1.0 + a.dot(b)).max(0.1))
+        })
+        .collect()
+}
+
+// centroid, area and second moment of area around the centroid of a simple polygon
+fn polygon_mass_properties(points: &[[f32; 2]]) -> (Vector2<f32>, f32, f32) {
+    let mut signed_area = 0.0;
+    let mut centroid = Vector2::new(0.0, 0.0);
+    for (i, p) in points.iter().enumerate() {
+        let a = Vector2::from(*p);
+        let b = Vector2::from(points[(i + 1) % points.len()]);
+        let c = cross(a, b);
+        signed_area += c / 2.0;
+        centroid += (a + b) * c;
+    }
+    centroid /= 6.0 * signed_area;
+
+    let mut second_moment = 0.0;
+    for (i, p) in points.iter().enumerate() {
+        let a = Vector2::from(*p) - centroid;
+        let b = Vector2::from(points[(i + 1) % points.len()]) - centroid;
+        second_moment += cross(a, b) * (a.dot(a) + a.dot(b) + b.dot(b)) / 12.0;
+    }
+    (centroid, signed_area.abs(), second_moment.abs())
+}
+
+fn perpendicular(v: Vector2<f32>) -> Vector2<f32> {
+    Vector2::new(-v.y, v.x)
+}
+
+pub(super) fn cross(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+// the body outlines, drawn straight from the body buffer so they follow the gpu backend
+// without a read back. every body gets MAX_BODY_POINTS line segments, a polygon with fewer
+// corners collapses the rest and a circle spends the last one on a spoke that shows it turn
+pub struct BodyOutline {
+    pub bind_group: wgpu::BindGroup,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl BodyOutline {
+    pub fn new(body_buffer: &wgpu::Buffer, body_point_buffer: &wgpu::Buffer, device: &wgpu::Device, pipeline_manager: &PipelineManager, camera_group_layout: &wgpu::BindGroupLayout) -> Self {
+        let storage = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage(0), storage(1)],
+            label: Some("body_bind_group_layout"),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: body_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: body_point_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("body_bind_group"),
+        });
+
+        let vert_shader = create_shader_module(device, "Vert Body Shader", include_str!("../shader/body/body.vert"), naga::ShaderStage::Vertex);
+        let frag_shader = create_shader_module(device, "Frag Body Shader", include_str!("../shader/body/body.frag"), naga::ShaderStage::Fragment);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Body Pipeline Layout"),
+            bind_group_layouts: &[camera_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = pipeline_manager.create_render_pipeline(
+            "Body Pipeline",
+            &pipeline_layout,
+            &vert_shader,
+            &frag_shader,
+            &[],
+            Some(wgpu::BlendState::REPLACE),
+            wgpu::PrimitiveTopology::LineList,
+            None,
+        );
+
+        Self { bind_group, pipeline }
+    }
+
+    pub fn num_vertices(num_bodies: usize) -> u32 {
+        (num_bodies * MAX_BODY_POINTS * 2) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit square of density 2 weighs 2 and turns like m (w^2 + h^2) / 12 around its middle
+    #[test]
+    fn polygon_body_mass_properties() {
+        let bodies = Bodies::new(&[Body::Polygon {
+            points: vec![[2.0, 1.0], [3.0, 1.0], [3.0, 2.0], [2.0, 2.0]],
+            density: 2.0,
+            velocity: [0.0, 0.0],
+            angular_velocity: 0.0,
+            pinned: false,
+        }]);
+        let body = bodies.bodies[0];
+        assert!((Vector2::from(body.position) - Vector2::new(2.5, 1.5)).magnitude() < 1e-5);
+        assert!((1.0 / body.inverse_mass - 2.0).abs() < 1e-5);
+        assert!((1.0 / body.inverse_inertia - 2.0 * 2.0 / 12.0).abs() < 1e-5);
+        assert!((body.radius - 0.5f32.sqrt()).abs() < 1e-5);
+    }
+}
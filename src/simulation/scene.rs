@@ -0,0 +1,232 @@
+use std::path::Path;
+use cgmath::Vector2;
+use serde::Deserialize;
+use super::boundary::Walls;
+use super::container::{BoundaryMode, ContainerMotion};
+use super::obstacle::{Obstacle, SdfConfig};
+use super::params::SimParams;
+use super::rigid_body::Body;
+use super::time_step::TimeStepParams;
+use super::solver::{Solver, SolverParams};
+
+// initial conditions read from a toml file, see scenes/ for examples.
+// anything left out falls back to the same setup the program always started with
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scene {
+    pub solver: Solver,
+    pub container: Container,
+    pub grid: GridConfig,
+    pub params: SimParams,
+    pub time_step: TimeStepParams,
+    pub solver_params: SolverParams,
+    pub blocks: Vec<Block>,
+    pub obstacles: Vec<Obstacle>,
+    pub sdf: SdfConfig,
+    pub bodies: Vec<Body>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Container {
+    pub size: [f32; 2],
+    pub walls: Walls,
+    // across x and across y, `boundary = ["periodic", "reflective"]` for a channel
+    pub boundary: [BoundaryMode; 2],
+    pub motion: ContainerMotion,
+}
+
+// number of spatial hash buckets, defaults to four per particle slot
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GridConfig {
+    pub table_size: Option<u32>,
+}
+
+// one group of particles, `spacing` defaults to a particle diameter
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Block {
+    Rectangle {
+        min: [f32; 2],
+        max: [f32; 2],
+        spacing: Option<f32>,
+        #[serde(default)]
+        velocity: [f32; 2],
+    },
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+        spacing: Option<f32>,
+        #[serde(default)]
+        velocity: [f32; 2],
+    },
+    // `count` particles dropped at random, none closer than `spacing` to each other
+    Random {
+        min: [f32; 2],
+        max: [f32; 2],
+        count: u32,
+        spacing: Option<f32>,
+        #[serde(default)]
+        velocity: [f32; 2],
+    },
+    // the square spiral the simulation used to be seeded with
+    Spiral {
+        #[serde(default)]
+        center: [f32; 2],
+        count: u32,
+        spacing: Option<f32>,
+        #[serde(default)]
+        velocity: [f32; 2],
+    },
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            solver: Solver::default(),
+            container: Container::default(),
+            grid: GridConfig::default(),
+            params: SimParams::default(),
+            time_step: TimeStepParams::default(),
+            solver_params: SolverParams::default(),
+            blocks: vec![Block::Spiral {
+                center: [0.0, 0.0],
+                count: 2u32.pow(12),
+                spacing: None,
+                velocity: [0.0, 0.0],
+            }],
+            obstacles: Vec::new(),
+            sdf: SdfConfig::default(),
+            bodies: Vec::new(),
+        }
+    }
+}
+
+impl Default for Container {
+    fn default() -> Self {
+        Self { size: [30.0, 20.0], walls: Walls::default(), boundary: [BoundaryMode::default(); 2], motion: ContainerMotion::default() }
+    }
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read scene '{}': {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("invalid scene '{}': {}", path.display(), e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+}
+
+impl Block {
+    pub fn velocity(&self) -> Vector2<f32> {
+        match self {
+            Block::Rectangle { velocity, .. }
+            | Block::Circle { velocity, .. }
+            | Block::Random { velocity, .. }
+            | Block::Spiral { velocity, .. } => Vector2::from(*velocity),
+        }
+    }
+
+    pub fn positions(&self, default_spacing: f32) -> Vec<Vector2<f32>> {
+        match *self {
+            Block::Rectangle { min, max, spacing, .. } => {
+                rectangle(min.into(), max.into(), spacing.unwrap_or(default_spacing))
+            }
+            Block::Circle { center, radius, spacing, .. } => {
+                let center = Vector2::from(center);
+                let offset = Vector2 { x: radius, y: radius };
+                rectangle(center - offset, center + offset, spacing.unwrap_or(default_spacing))
+                    .into_iter()
+                    .filter(|p| {
+                        let d = p - center;
+                        d.x * d.x + d.y * d.y <= radius * radius
+                    })
+                    .collect()
+            }
+            Block::Random { min, max, count, spacing, .. } => {
+                random(min.into(), max.into(), count, spacing.unwrap_or(default_spacing))
+            }
+            Block::Spiral { center, count, spacing, .. } => {
+                spiral(center.into(), count, spacing.unwrap_or(default_spacing))
+            }
+        }
+    }
+}
+
+fn rectangle(min: Vector2<f32>, max: Vector2<f32>, spacing: f32) -> Vec<Vector2<f32>> {
+    let mut positions = Vec::new();
+    if spacing <= 0.0 {
+        return positions;
+    }
+    let columns = ((max.x - min.x) / spacing).floor() as i32 + 1;
+    let rows = ((max.y - min.y) / spacing).floor() as i32 + 1;
+    for row in 0..rows.max(0) {
+        for column in 0..columns.max(0) {
+            positions.push(Vector2 {
+                x: min.x + column as f32 * spacing,
+                y: min.y + row as f32 * spacing,
+            });
+        }
+    }
+    positions
+}
+
+// dart throwing, gives up after a fixed number of misses so a too dense block still terminates
+fn random(min: Vector2<f32>, max: Vector2<f32>, count: u32, spacing: f32) -> Vec<Vector2<f32>> {
+    let mut positions: Vec<Vector2<f32>> = Vec::with_capacity(count as usize);
+    let mut attempts = 0;
+    while positions.len() < count as usize && attempts < count * 30 {
+        attempts += 1;
+        let candidate = Vector2 {
+            x: min.x + rand::random::<f32>() * (max.x - min.x),
+            y: min.y + rand::random::<f32>() * (max.y - min.y),
+        };
+        let free = positions.iter().all(|p| {
+            let d = p - candidate;
+            d.x * d.x + d.y * d.y >= spacing * spacing
+        });
+        if free {
+            positions.push(candidate);
+        }
+    }
+    if positions.len() < count as usize {
+        log::info!("Random block only fit {} of {} particles", positions.len(), count);
+    }
+    positions
+}
+
+// square spiral out from `center`, somwaht stupid ngl
+pub fn spiral(center: Vector2<f32>, count: u32, spacing: f32) -> Vec<Vector2<f32>> {
+    let mut positions = Vec::with_capacity(count as usize);
+
+    let mut pos = center;
+    let mut delta_pos = Vector2 { x: 0.0, y: spacing };
+    let rotation_matrix = cgmath::Matrix2::new(0.0, -1.0, 1.0, 0.0);
+
+    let mut counter = 1;
+    let mut segment_length = 1;
+    let mut segment_counter = 1;
+
+    for _ in 0..count {
+        positions.push(pos);
+
+        if segment_counter == 0 {
+            segment_counter = segment_length;
+            delta_pos = rotation_matrix * delta_pos;
+            counter -= 1;
+        }
+
+        if counter == 0 {
+            counter = 2;
+            segment_length += 1;
+        }
+
+        segment_counter -= 1;
+        pos += delta_pos;
+    }
+    positions
+}
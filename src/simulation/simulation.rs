@@ -1,31 +1,87 @@
-use rand::Rng;
-use wgpu::core::device;
-use std::{num::ParseIntError, time::Duration, vec};
-use cgmath::{Vector2, prelude::InnerSpace, Vector3};
+use cgmath::{Vector2, Vector3};
 use bytemuck::{Pod, Zeroable};
-use rayon::prelude::*;
+use wgpu::util::DeviceExt;
+use super::params::SimParams;
+use super::time_step::TimeStepParams;
+use super::solver::{Solver, SolverData, SolverParams};
+use super::scene::{self, Scene};
+use super::snapshot::{SavedContainer, SavedSolver, Snapshot};
+use std::path::Path;
+use super::grid::{Grid, HashCell, HashStats};
+use super::boundary::{Boundary, Walls};
+use super::container::{BoundaryMode, ContainerMotion};
+use super::obstacle::{Obstacles, ObstacleInfo, ObstacleShape, MAX_OBSTACLE_POINTS, MAX_OBSTACLE_SHAPES, MAX_SDF_CELLS};
+use super::rigid_body::{Bodies, BodySample, RigidBody, MAX_BODIES, MAX_BODY_POINTS, MAX_BODY_SAMPLES};
+use super::cpu_solver::Bounds;
+use crate::utils::readback::read_buffer;
+
+// which solver advances the particles, picked once at startup
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Backend {
+    Gpu,
+    Cpu,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
 
-const GRAVITY: f32 = 0.1;
-const COLLISION_DAMPING: f32 = 0.8;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "gpu" => Ok(Backend::Gpu),
+            "cpu" => Ok(Backend::Cpu),
+            other => Err(format!("unknown backend '{}', expected 'gpu' or 'cpu'", other)),
+        }
+    }
+}
 
 pub struct WaterSimulation {
     pub particles: Vec<ParticleLl>,
     pub positions : Vec<PositionLl>,
     pub velocities: Vec<VelocityLl>,
     pub densities: Vec<DensityLl>,
-    pub particle_info: Vec<Particle>,
     pub num_particles: u32,
     pub num_particles_buffer: wgpu::Buffer,
     pub max_particles: usize,
+    pub table_size: usize,
     pub bound_size: [f32; 2],
+    pub walls: Walls,
+    pub boundary_modes: [BoundaryMode; 2],
+    pub container_motion: ContainerMotion,
+    pub container_motion_buffer: wgpu::Buffer,
+    pub boundary: Boundary,
+    pub obstacles: Obstacles,
+    pub bodies: Bodies,
     pub radius: RadiusLl,
-    pub smoothing_radius: f32,
-    pub target_density: f32,
-    pub pressure_multiplier: f32,
+    pub params: SimParams,
+    pub params_buffer: wgpu::Buffer,
+    pub time_step_params: TimeStepParams,
+    pub time_step_params_buffer: wgpu::Buffer,
+    pub solver: Solver,
+    pub solver_params: SolverParams,
+    pub solver_params_buffer: wgpu::Buffer,
+
+    pub particle_buffer: wgpu::Buffer,
+    pub position_buffer: wgpu::Buffer,
+    pub velocity_buffer: wgpu::Buffer,
+    pub density_buffer: wgpu::Buffer,
+    pub predicted_position_buffer: wgpu::Buffer,
+    pub solver_data_buffer: wgpu::Buffer,
+    pub obstacle_shape_buffer: wgpu::Buffer,
+    pub obstacle_point_buffer: wgpu::Buffer,
+    pub obstacle_field_buffer: wgpu::Buffer,
+    pub obstacle_info_buffer: wgpu::Buffer,
+    pub body_buffer: wgpu::Buffer,
+    pub body_point_buffer: wgpu::Buffer,
+    pub body_impulse_buffer: wgpu::Buffer,
+    pub num_bodies_buffer: wgpu::Buffer,
+    pub body_sample_buffer: wgpu::Buffer,
+    pub body_sample_range_buffer: wgpu::Buffer,
 }
 
 impl WaterSimulation {
     pub fn new(device: &wgpu::Device) -> Self {
+        let max_particles = 2u32.pow(12) as usize;
+
         let num_particles_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("num_particles_buffer"),
             size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
@@ -33,289 +89,401 @@ impl WaterSimulation {
             mapped_at_creation: false,
         });
 
+        let params = SimParams::default();
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("sim_params_buffer"),
+            contents: bytemuck::cast_slice(&[params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let time_step_params = TimeStepParams::default();
+        let time_step_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("time_step_params_buffer"),
+            contents: bytemuck::cast_slice(&[time_step_params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let solver_params = SolverParams::default();
+        let solver_params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("solver_params_buffer"),
+            contents: bytemuck::cast_slice(&[solver_params]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let particle_buffer = Self::create_particle_buffer::<ParticleLl>(device, "Particle Buffer", max_particles);
+        let position_buffer = Self::create_particle_buffer::<PositionLl>(device, "Position Buffer", max_particles);
+        let velocity_buffer = Self::create_particle_buffer::<VelocityLl>(device, "Velocity Buffer", max_particles);
+        let density_buffer = Self::create_particle_buffer::<DensityLl>(device, "Density Buffer", max_particles);
+        let predicted_position_buffer = Self::create_particle_buffer::<PositionLl>(device, "Predicted Position Buffer", max_particles);
+        let solver_data_buffer = Self::create_particle_buffer::<SolverData>(device, "Solver Data Buffer", max_particles);
+
+        // sized for the largest scene, load_scene fills in the front
+        let obstacle_shape_buffer = Self::create_particle_buffer::<ObstacleShape>(device, "Obstacle Shape Buffer", MAX_OBSTACLE_SHAPES);
+        let obstacle_point_buffer = Self::create_particle_buffer::<[f32; 2]>(device, "Obstacle Point Buffer", MAX_OBSTACLE_POINTS);
+        let obstacle_field_buffer = Self::create_particle_buffer::<f32>(device, "Obstacle Field Buffer", MAX_SDF_CELLS);
+        let obstacle_info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("obstacle_info_buffer"),
+            contents: bytemuck::cast_slice(&[ObstacleInfo::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let container_motion_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("container_motion_buffer"),
+            contents: bytemuck::cast_slice(&[ContainerMotion::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let body_buffer = Self::create_particle_buffer::<RigidBody>(device, "Body Buffer", MAX_BODIES);
+        let body_point_buffer = Self::create_particle_buffer::<[f32; 2]>(device, "Body Point Buffer", MAX_BODIES * MAX_BODY_POINTS);
+        // linear impulse and torque the fluid pushed into every body, as fixed point
+        let body_impulse_buffer = Self::create_particle_buffer::<[i32; 4]>(device, "Body Impulse Buffer", MAX_BODIES);
+        let num_bodies_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("num_bodies_buffer"),
+            contents: bytemuck::cast_slice(&[0u32]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let body_sample_buffer = Self::create_particle_buffer::<BodySample>(device, "Body Sample Buffer", MAX_BODY_SAMPLES);
+        let body_sample_range_buffer = Self::create_particle_buffer::<[u32; 2]>(device, "Body Sample Range Buffer", MAX_BODIES);
+
         Self {
             particles: Vec::new(),
-            particle_info: Vec::new(),
             positions: Vec::new(),
             velocities: Vec::new(),
             densities: Vec::new(),
             num_particles: 0,
             num_particles_buffer,
-            max_particles: 2u32.pow(12) as usize,
+            max_particles,
+            table_size: 4 * max_particles,
             bound_size: [30.0, 20.0], //x, y
+            walls: Walls::default(),
+            boundary_modes: [BoundaryMode::default(); 2],
+            container_motion: ContainerMotion::default(),
+            container_motion_buffer,
+            boundary: Boundary::default(),
+            obstacles: Obstacles::default(),
+            bodies: Bodies::default(),
             radius: RadiusLl::new(0.08),
-            smoothing_radius: 0.1,
-            target_density: 4.0,
-            pressure_multiplier: 0.01,
+            params,
+            params_buffer,
+            time_step_params,
+            time_step_params_buffer,
+            solver: Solver::default(),
+            solver_params,
+            solver_params_buffer,
+
+            particle_buffer,
+            position_buffer,
+            velocity_buffer,
+            density_buffer,
+            predicted_position_buffer,
+            solver_data_buffer,
+            obstacle_shape_buffer,
+            obstacle_point_buffer,
+            obstacle_field_buffer,
+            obstacle_info_buffer,
+            body_buffer,
+            body_point_buffer,
+            body_impulse_buffer,
+            num_bodies_buffer,
+            body_sample_buffer,
+            body_sample_range_buffer,
         }
     }
 
-    // pub fn update(&mut self, delta_time: Duration) {
-    //     self.update_density();
-
-
-    //     for particle_index in 0..self.num_particles {
-    //         self.particle_info[particle_index as usize].apply_gravity(delta_time);
-    //         let pressure_force = self.calclulate_pressure_force(particle_index, delta_time);
-    //         let pressure_acceleration = pressure_force / self.particle_info[particle_index as usize].density;
-    //         self.particle_info[particle_index as usize].velocity += pressure_acceleration * 0.001;
-    //     }
-
-    //     for particle in self.particle_info.iter_mut() {
-    //         particle.position += particle.velocity;
-    //     }
-
-    //     for (i, particle) in self.particles.iter_mut().enumerate() {
-    //         particle.position = self.particle_info[i].position;
-    //         println!("Particle position: {:?}", particle.position);
-    //     }
-
-    //     self.collision_detection();
-
-    // }
-
-    // pub fn update_in_parrallel(&mut self, delta_time: Duration) {
-    //     self.update_density();
-
-    //     // Calculate pressure forces in parallel
-    //     let pressure_forces: Vec<_> = (0..self.num_particles)
-    //         .into_par_iter()
-    //         .map(|particle_index| {
-    //             //self.particle_info[particle_index as usize].apply_gravity(delta_time);
-    //             let pressure_force = self.calclulate_pressure_force(particle_index, delta_time);
-    //             let pressure_acceleration = pressure_force / self.particle_info[particle_index as usize].density;
-    //             pressure_acceleration * 0.01 /*+ Vector3::new(0.0, -GRAVITY * delta_time.as_secs_f32(), 0.0)*/
-    //         })
-    //         .collect();
-
-    //     // Apply pressure forces in parallel
-    //     self.particle_info.par_iter_mut().zip(pressure_forces.par_iter())
-    //         .for_each(|(particle, &pressure_acceleration)| {
-    //             particle.velocity += pressure_acceleration;
-    //         });
-
-    //     // Update positions in parallel
-    //     self.particle_info.par_iter_mut().for_each(|particle| {
-    //         particle.position += particle.velocity;
-    //     });
-
-    //     // Update the positions in ParticleLl based on Particle info
-    //     self.particles.par_iter_mut().enumerate().for_each(|(i, particle)| {
-    //         particle.position = self.particle_info[i].position;
-    //     });
-
-    //     self.collision_detection();
-    // }
-
-
-
-    // fn collision_detection(&mut self) {
-        
-    //     let half_bound_sizes = [
-    //         (self.bound_size[0] / 2.0) - self.radius.radius, 
-    //         (self.bound_size[1] / 2.0) - self.radius.radius,
-    //     ];
-
-    //     for particle in self.particle_info.iter_mut() {
-    //         if particle.position.x.abs() > half_bound_sizes[0] {
-    //             particle.position.x = half_bound_sizes[0] * particle.position.x.signum();
-    //             particle.velocity.x *= -1.0;
-    //         }
-
-    //         if particle.position.y.abs() > half_bound_sizes[1] {
-    //             particle.position.y = half_bound_sizes[1] * particle.position.y.signum();
-    //             particle.velocity.y *= -1.0;
-    //         }
-    //     }
-
-    // }
+    fn create_particle_buffer<T>(device: &wgpu::Device, label: &str, max_particles: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (max_particles * std::mem::size_of::<T>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
 
-    pub fn add_multiple_random_particles(&mut self, num_new: u32, queue: &wgpu::Queue, particle_buffer: &wgpu::Buffer, position_buffer: &wgpu::Buffer, velocity_buffer: &wgpu::Buffer, density_buffer: &wgpu::Buffer) {
+    pub fn add_multiple_random_particles(&mut self, num_new: u32, queue: &wgpu::Queue) {
         let mut new_num = num_new;
-        if self.num_particles + num_new as u32 > self.max_particles as u32 {
+        if self.num_particles + num_new > self.max_particles as u32 {
             log::info!("Returning max particles instead of adding more");
             new_num = self.max_particles as u32 - self.num_particles
         }
 
-        let mut new_particles = Vec::new();
         let mut new_positions = Vec::new();
-        let mut new_velocities = Vec::new();
-        let mut new_densities = Vec::new();
 
         for _ in 0..new_num {
             let x = rand::random::<f32>() * self.bound_size[0] - self.bound_size[0] / 2.0;
             let y = rand::random::<f32>() * self.bound_size[1] - self.bound_size[1] / 2.0;
-            new_particles.push(ParticleLl::new(x, y));
-            new_positions.push(PositionLl::new(x, y));
-            new_velocities.push(VelocityLl::new());
-            new_densities.push(DensityLl::new());
-
-            self.particle_info.push(Particle::new(x, y, 1.0));
-            self.particles.push(ParticleLl::new(x, y));
+            new_positions.push(Vector2 { x, y });
         }
 
-        let offset = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<ParticleLl>() as wgpu::BufferAddress;
-        let offset_position = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<PositionLl>() as wgpu::BufferAddress;
-        let offset_velocity = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<VelocityLl>() as wgpu::BufferAddress;
-        let offset_density = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<DensityLl>() as wgpu::BufferAddress;
-
-        self.num_particles += new_num;
-        let new_particle_data = bytemuck::cast_slice(&new_particles);
-        let new_position_data = bytemuck::cast_slice(&new_positions);
-        let new_velocity_data = bytemuck::cast_slice(&new_velocities);
-        let new_density_data = bytemuck::cast_slice(&new_densities);
-
-        queue.write_buffer(particle_buffer, offset, new_particle_data);
-        queue.write_buffer(position_buffer, offset_position, new_position_data);
-        queue.write_buffer(velocity_buffer, offset_velocity, new_velocity_data);
-        queue.write_buffer(density_buffer, offset_density, new_density_data);        
+        self.push_particles(&new_positions, Vector2 { x: 0.0, y: 0.0 }, queue);
     }
     
     //adds in a square spiral pattern, somwaht stupid ngl
-    pub fn add_multiple_uniform_particles(&mut self, num_new: u32, queue: &wgpu::Queue, particle_buffer: &wgpu::Buffer, position_buffer: &wgpu::Buffer, velocity_buffer: &wgpu::Buffer, density_buffer: &wgpu::Buffer) {
+    pub fn add_multiple_uniform_particles(&mut self, num_new: u32, queue: &wgpu::Queue) {
         let mut new_num = num_new;
-        if self.num_particles + num_new as u32 > self.max_particles as u32 {
+        if self.num_particles + num_new > self.max_particles as u32 {
             log::info!("Returning max particles instead of adding more");
             new_num = self.max_particles as u32 - self.num_particles;
         }
 
-        let mut new_particles = Vec::new();
-
-        let mut new_positions = Vec::new();
-        let mut new_velocities = Vec::new();
-        let mut new_densities = Vec::new();
-
-        let mut pos = Vector2{x: 0.0, y:0.0};
-        let mut delta_pos = Vector2{x: 0.0, y: 2.0*self.radius.radius};
-        let rotation_matrix = cgmath::Matrix2::new(0.0, -1.0, 1.0, 0.0);
-
-
-        let mut counter = 1;
-        let mut segment_length = 1;
-        let mut segment_counter = 1;
-
-        for _ in 0..new_num {
-            new_particles.push(ParticleLl::new(pos.x, pos.y));
-            new_positions.push(PositionLl::new(pos.x, pos.y));
-            new_velocities.push(VelocityLl::new());
-            new_densities.push(DensityLl::new());
-
+        let new_positions = scene::spiral(Vector2 { x: 0.0, y: 0.0 }, new_num, 2.0 * self.radius.radius);
+        log::info!("Adding {} particles", new_positions.len());
 
+        self.push_particles(&new_positions, Vector2 { x: 0.0, y: 0.0 }, queue);
+    }
 
+    // throws away the current particles and sets up the container, parameters and blocks of `scene`
+    pub fn load_scene(&mut self, scene: &Scene, queue: &wgpu::Queue) {
+        self.particles.clear();
+        self.positions.clear();
+        self.velocities.clear();
+        self.densities.clear();
+        self.num_particles = 0;
+
+        self.bound_size = scene.container.size;
+        self.walls = scene.container.walls;
+        self.boundary_modes = scene.container.boundary;
+        self.container_motion = scene.container.motion;
+        queue.write_buffer(&self.container_motion_buffer, 0, bytemuck::cast_slice(&[self.container_motion]));
+        self.table_size = scene.grid.table_size.unwrap_or(4 * self.max_particles as u32).max(1) as usize;
+        self.params = scene.params;
+        self.time_step_params = scene.time_step;
+        self.solver = scene.solver;
+        self.solver_params = scene.solver_params;
+
+        for block in &scene.blocks {
+            let mut new_positions = block.positions(2.0 * self.radius.radius);
+            let room = self.max_particles - self.num_particles as usize;
+            if new_positions.len() > room {
+                log::info!("Scene has more than {} particles, dropping {}", self.max_particles, new_positions.len() - room);
+                new_positions.truncate(room);
+            }
+            self.push_particles(&new_positions, block.velocity(), queue);
+        }
+        self.obstacles = Obstacles::new(&scene.obstacles, &scene.sdf, self.container_motion.reach(self.bound_size));
+        self.upload_obstacles(queue);
+        self.bodies = Bodies::new(&scene.bodies);
+        if !self.bodies.points.is_empty() {
+            queue.write_buffer(&self.body_point_buffer, 0, bytemuck::cast_slice(&self.bodies.points));
+        }
+        queue.write_buffer(&self.body_impulse_buffer, 0, bytemuck::cast_slice(&vec![[0i32; 4]; MAX_BODIES]));
+        queue.write_buffer(&self.num_bodies_buffer, 0, bytemuck::cast_slice(&[self.bodies.len() as u32]));
+        self.upload_bodies(queue);
+        self.update_body_samples(queue);
+        self.update_boundary();
+        log::info!("Loaded scene with {} particles", self.num_particles);
+    }
 
-            self.particle_info.push(Particle::new(pos.x, pos.y, 1.0));
-            self.particles.push(ParticleLl::new(pos.x, pos.y));
+    // samples the boundary particles again when the container, the smoothing radius or anything
+    // else they depend on changed. returns whether they did, the grid has to be told then
+    pub fn update_boundary(&mut self) -> bool {
+        self.boundary.update(self.walls, self.bound_size, self.boundary_modes, self.radius.radius, &self.params, self.table_size)
+    }
 
-            if segment_counter == 0 {
-                segment_counter = segment_length;
-                delta_pos = rotation_matrix * delta_pos;
-                counter -= 1;
-            }
+    // samples the outlines of the bodies again when the smoothing radius, the kernel or the
+    // rest density changed, and uploads them
+    pub fn update_body_samples(&mut self, queue: &wgpu::Queue) {
+        if !self.bodies.update_samples(&self.params) || self.bodies.is_empty() {
+            return;
+        }
+        queue.write_buffer(&self.body_sample_buffer, 0, bytemuck::cast_slice(&self.bodies.samples));
+        queue.write_buffer(&self.body_sample_range_buffer, 0, bytemuck::cast_slice(&self.bodies.sample_ranges));
+    }
 
-            if counter == 0 {
-                counter = 2;
-                segment_length += 1;
-            }
+    // the flattened obstacles to the gpu, the baked grid only when the collision stage reads it
+    fn upload_obstacles(&self, queue: &wgpu::Queue) {
+        let obstacles = &self.obstacles;
+        if !obstacles.shapes.is_empty() {
+            queue.write_buffer(&self.obstacle_shape_buffer, 0, bytemuck::cast_slice(&obstacles.shapes));
+        }
+        if !obstacles.points.is_empty() {
+            queue.write_buffer(&self.obstacle_point_buffer, 0, bytemuck::cast_slice(&obstacles.points));
+        }
+        if obstacles.info.baked != 0 {
+            queue.write_buffer(&self.obstacle_field_buffer, 0, bytemuck::cast_slice(&obstacles.field));
+        }
+        queue.write_buffer(&self.obstacle_info_buffer, 0, bytemuck::cast_slice(&[obstacles.info]));
+    }
 
-            segment_counter -= 1;
-            pos += delta_pos;
+    // the moving part of the bodies, every step on the cpu backend since they are drawn from
+    // the gpu copy
+    fn upload_bodies(&self, queue: &wgpu::Queue) {
+        if !self.bodies.is_empty() {
+            queue.write_buffer(&self.body_buffer, 0, bytemuck::cast_slice(&self.bodies.bodies));
         }
-        println!("particles: {:?}", new_particles.len());
+    }
+
+    // appends particles to the cpu mirror and uploads them behind the live ones
+    fn push_particles(&mut self, new_positions: &[Vector2<f32>], velocity: Vector2<f32>, queue: &wgpu::Queue) {
+        let new_particles: Vec<ParticleLl> = new_positions.iter().map(|p| ParticleLl::new(p.x, p.y)).collect();
+        let new_positions: Vec<PositionLl> = new_positions.iter().map(|p| PositionLl::new(p.x, p.y)).collect();
+        let new_velocities = vec![VelocityLl::with_velocity(velocity.x, velocity.y); new_positions.len()];
+        let new_densities = vec![DensityLl::new(); new_positions.len()];
 
         let offset = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<ParticleLl>() as wgpu::BufferAddress;
         let offset_position = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<PositionLl>() as wgpu::BufferAddress;
         let offset_velocity = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<VelocityLl>() as wgpu::BufferAddress;
         let offset_density = self.num_particles as wgpu::BufferAddress * std::mem::size_of::<DensityLl>() as wgpu::BufferAddress;
-        
-        self.num_particles += new_num;
-        let new_particle_data = bytemuck::cast_slice(&new_particles);
-        let new_position_data = bytemuck::cast_slice(&new_positions);
-        let new_velocity_data = bytemuck::cast_slice(&new_velocities);
-        let new_density_data = bytemuck::cast_slice(&new_densities);
-
-        queue.write_buffer(particle_buffer, offset, new_particle_data);
-        queue.write_buffer(position_buffer, offset_position, new_position_data);
-        queue.write_buffer(velocity_buffer, offset_velocity, new_velocity_data);
-        queue.write_buffer(density_buffer, offset_density, new_density_data);
-    }
 
-    // pub fn calculate_density(&self, sample_point: Vector3<f32> ) -> f32 {
-    //     let mut density = 0.0;
-
-    //     for particle in self.particle_info.iter() {
-    //         let distance = (sample_point - particle.position).magnitude();
-    //         density += self.smoothing_kernel(self.smoothing_radius, distance);
-    //     }
-
-    //     density
-    // }
-
-    // //poly6 kernel
-    // fn smoothing_kernel(&self, radius: f32, distance: f32) -> f32 {
-    //     //let volume = 315.0 / (64.0 * std::f32::consts::PI * radius.powi(9));
-    //     let volume = std::f32::consts::PI * radius.powi(4) / 6.0;
-    //     let value = 0.0f32.max(radius - distance).powi(2);
-
-    //     value / volume
-    // }
-
-    // fn smoothing_kernel_derivative(&self, radius: f32, distance: f32) -> f32 {
-
-    //     if distance > radius { return 0.0; }
-        
-    //     let a = - 12.0 / (std::f32::consts::PI * radius.powi(4));
-    //     let b =  radius - distance;
-    //     a * b
-    // }
-
-    // pub fn update_density(&mut self) {
-    //     for particle_index in 0..self.num_particles {
-    //         let density = self.calculate_density(self.particle_info[particle_index as usize].position);
-    //         self.particle_info[particle_index as usize].density = density;
-    //     }
-    // }
-    
+        self.num_particles += new_positions.len() as u32;
+
+        queue.write_buffer(&self.particle_buffer, offset, bytemuck::cast_slice(&new_particles));
+        queue.write_buffer(&self.position_buffer, offset_position, bytemuck::cast_slice(&new_positions));
+        queue.write_buffer(&self.velocity_buffer, offset_velocity, bytemuck::cast_slice(&new_velocities));
+        queue.write_buffer(&self.density_buffer, offset_density, bytemuck::cast_slice(&new_densities));
 
-    // fn calclulate_pressure_force(&self, sample_point_index: u32, delta_time: Duration) -> Vector3<f32> {
+        self.particles.extend(new_particles);
+        self.positions.extend(new_positions);
+        self.velocities.extend(new_velocities);
+        self.densities.extend(new_densities);
+    }
 
-    //     let mut pressure_force = Vector3::new(0.0, 0.0, 0.0);
-    //     let sample_point = self.particle_info[sample_point_index as usize].position;
+    pub async fn read_positions(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<PositionLl> {
+        read_buffer(device, queue, &self.position_buffer, 0..self.num_particles as usize).await
+    }
 
-    //     for particle_index in 0..self.num_particles {
-    //         if sample_point_index == particle_index { continue; }
+    pub async fn read_velocities(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<VelocityLl> {
+        read_buffer(device, queue, &self.velocity_buffer, 0..self.num_particles as usize).await
+    }
+
+    pub async fn read_densities(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<DensityLl> {
+        read_buffer(device, queue, &self.density_buffer, 0..self.num_particles as usize).await
+    }
 
-    //         let particle = &self.particle_info[particle_index as usize];
-    //         let distance = (particle.position - sample_point).magnitude();
+    // the sorted (cell, particle) pairs of the last step, the grid lives next to the pipelines
+    pub async fn read_spatial_hash(&self, device: &wgpu::Device, queue: &wgpu::Queue, grid: &Grid) -> Vec<HashCell> {
+        read_buffer(device, queue, &grid.spatial_lookup_buffer, 0..self.num_particles as usize).await
+    }
 
-    //         let direction;
-    //         if distance == 0.0 {
-    //             let x = rand::random::<f32>()* 2.0 - 1.0;
-    //             let y = rand::random::<f32>()* 2.0 - 1.0;
-    //             direction = Vector3::new(x, y, 0.0).normalize();
-    //         } else {
-    //             direction = (particle.position - sample_point) / distance;
-    //         }
+    // collision statistics of the last step's spatial hash, `bounds` is where the container was
+    // when the compute pass hashed, the cell of a particle is measured in its frame
+    pub async fn read_hash_stats(&self, device: &wgpu::Device, queue: &wgpu::Queue, grid: &Grid, bounds: Bounds) -> HashStats {
+        let sorted = self.read_spatial_hash(device, queue, grid).await;
+        let predicted: Vec<PositionLl> = read_buffer(device, queue, &self.predicted_position_buffer, 0..self.num_particles as usize).await;
+        let half_boundries = bounds.size / 2.0 - Vector2::new(self.radius.radius, self.radius.radius);
+        let cells: Vec<Vector2<i32>> = predicted
+            .iter()
+            .map(|p| bounds.cell(bounds.to_local(p.position.truncate()), half_boundries, self.params.cell_size()))
+            .collect();
+        HashStats::new(&sorted, &cells, grid.table_size)
+    }
 
-    //         let slope = self.smoothing_kernel_derivative(self.smoothing_radius, distance);
-    //         let density = particle.density;
-    //         let other_density = self.particle_info[sample_point_index as usize].density;
-    //         let symmetric_pressure = self.calculate_symmetric_pressure(other_density, density);
+    // pulls the gpu state back into the cpu vectors, needed before looking at them on the gpu backend
+    pub fn sync_from_gpu(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let (positions, velocities, densities) = futures::executor::block_on(async {
+            (
+                self.read_positions(device, queue).await,
+                self.read_velocities(device, queue).await,
+                self.read_densities(device, queue).await,
+            )
+        });
+        self.positions = positions;
+        self.velocities = velocities;
+        self.densities = densities;
+        if !self.bodies.is_empty() {
+            self.bodies.bodies = futures::executor::block_on(read_buffer(device, queue, &self.body_buffer, 0..self.bodies.len()));
+        }
+    }
 
-    //         pressure_force += symmetric_pressure * direction * slope * particle.mass / density;
-    //     }
-    //     pressure_force
-    // }
+    // writes the cpu side state, sync first when the gpu owns the simulation. `time` is the
+    // simulated time and `center` where the container rests, neither is kept in here
+    pub fn save_snapshot(&self, path: &Path, time: f32, center: Vector2<f32>) -> Result<(), String> {
+        let n = self.num_particles as usize;
+        Snapshot {
+            bound_size: self.bound_size,
+            params: self.params,
+            positions: self.positions[..n].to_vec(),
+            velocities: self.velocities[..n].to_vec(),
+            densities: self.densities[..n].to_vec(),
+            bodies: Some(self.bodies.bodies.clone()),
+            container: Some(SavedContainer {
+                time,
+                center: center.into(),
+                motion: self.container_motion,
+            }),
+            modes: Some(self.boundary_modes.map(|mode| mode as u32)),
+            solver: Some(SavedSolver {
+                solver: self.solver as u32,
+                table_size: self.table_size as u32,
+                solver_params: self.solver_params,
+                time_step_params: self.time_step_params,
+            }),
+        }.save(path)
+    }
 
-    // fn calculate_symmetric_pressure(&self, density_a: f32, density_b: f32) -> f32 {
-    //     let pressure_a = self.convert_density_to_pressure(density_a);
-    //     let pressure_b = self.convert_density_to_pressure(density_b);
-    //     (pressure_a + pressure_b) / 2.0
-    // }
+    // replaces the particles, container size and motion, parameters, solver settings and the state
+    // of the bodies, and uploads them to the gpu. the bodies and the hash table come from the scene,
+    // so the snapshot has to have been taken with the same ones. the time and resting place of the
+    // container go back to the caller, none from a file older than those
+    pub fn load_snapshot(&mut self, path: &Path, queue: &wgpu::Queue) -> Result<Option<SavedContainer>, String> {
+        let snapshot = Snapshot::load(path)?;
+        if snapshot.positions.len() > self.max_particles {
+            return Err(format!("snapshot has {} particles, at most {} are supported", snapshot.positions.len(), self.max_particles));
+        }
+        match &snapshot.bodies {
+            None if !self.bodies.is_empty() => {
+                return Err(format!("snapshot is from before rigid bodies were saved, the scene has {} of them", self.bodies.len()));
+            }
+            Some(bodies) if bodies.len() != self.bodies.len() => {
+                return Err(format!("snapshot has {} bodies, the scene has {}", bodies.len(), self.bodies.len()));
+            }
+            _ => {}
+        }
+        if snapshot.container.is_none() && self.container_motion.is_moving() {
+            return Err("snapshot is from before the container motion was saved, the scene moves the container".to_string());
+        }
+        // the walls are built from the scene, fluid saved between other ones would flow differently
+        let modes = snapshot.modes.map_or([BoundaryMode::Reflective; 2], |modes| modes.map(BoundaryMode::from_index));
+        if modes != self.boundary_modes {
+            return Err(format!("snapshot has {:?} boundaries, the scene has {:?}", modes, self.boundary_modes));
+        }
+        // the grid buffers are sized for the scene's table, another one would bin differently
+        if let Some(solver) = snapshot.solver {
+            if solver.table_size as usize != self.table_size {
+                return Err(format!("snapshot has a hash table of {} buckets, the scene has {}", solver.table_size, self.table_size));
+            }
+        }
+        let reach = self.container_motion.reach(self.bound_size);
+
+        self.num_particles = snapshot.positions.len() as u32;
+        self.bound_size = snapshot.bound_size;
+        self.params = snapshot.params;
+        self.particles = snapshot.positions.iter().map(|p| ParticleLl::new(p.position.x, p.position.y)).collect();
+        self.positions = snapshot.positions;
+        self.velocities = snapshot.velocities;
+        self.densities = snapshot.densities;
+        // only where the bodies are and how they move, shapes and masses stay the scene's
+        for (body, saved) in self.bodies.bodies.iter_mut().zip(snapshot.bodies.unwrap_or_default()) {
+            body.position = saved.position;
+            body.velocity = saved.velocity;
+            body.angle = saved.angle;
+            body.angular_velocity = saved.angular_velocity;
+        }
+        if let Some(container) = snapshot.container {
+            self.container_motion = container.motion;
+            queue.write_buffer(&self.container_motion_buffer, 0, bytemuck::cast_slice(&[self.container_motion]));
+        }
+        if let Some(solver) = snapshot.solver {
+            self.solver = Solver::from_index(solver.solver);
+            self.solver_params = solver.solver_params;
+            self.time_step_params = solver.time_step_params;
+        }
+        // the baked field has to cover wherever the container can go now
+        if self.container_motion.reach(self.bound_size) != reach {
+            self.obstacles.resize(self.container_motion.reach(self.bound_size));
+            self.upload_obstacles(queue);
+        }
 
-    // fn convert_density_to_pressure(&self, density: f32) -> f32 {
-    //     self.pressure_multiplier * (density - self.target_density)
-    // }
+        queue.write_buffer(&self.particle_buffer, 0, bytemuck::cast_slice(&self.particles));
+        queue.write_buffer(&self.predicted_position_buffer, 0, bytemuck::cast_slice(&self.positions));
+        self.upload_particles(queue);
+        Ok(snapshot.container)
+    }
 
+    // pushes the cpu side state to the gpu, used when the cpu backend owns the simulation
+    pub fn upload_particles(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.position_buffer, 0, bytemuck::cast_slice(&self.positions));
+        queue.write_buffer(&self.velocity_buffer, 0, bytemuck::cast_slice(&self.velocities));
+        queue.write_buffer(&self.density_buffer, 0, bytemuck::cast_slice(&self.densities));
+        self.upload_bodies(queue);
+    }
 }
 
 // random info i want to use in shaders
@@ -349,30 +517,6 @@ impl ParticleLl {
     }
 }
 
-pub struct Particle {
-    pub position: Vector3<f32>,
-    pub velocity: Vector3<f32>,
-    pub mass: f32, //every particle has the same mass of 1.0
-    pub density: f32,
-}
-
-impl Particle {
-    pub fn new(x: f32, y: f32, mass: f32) -> Self {
-        Self {
-            position: Vector3 { x, y, z: 0.0 },
-            velocity: Vector3 { x: 0.0, y: 0.0, z: 0.0 },
-            mass,
-            density: 0.1,
-        }
-    }
-
-    pub fn apply_gravity(&mut self, delta_time: Duration) {
-        return;
-        let delta_seconds = delta_time.as_secs_f32();
-        self.velocity.y -= GRAVITY * delta_seconds;
-    }
-}
-
 #[derive(Debug, Copy, Clone)]
 pub struct PositionLl {
     pub position: Vector3<f32>, //12 bytes
@@ -408,6 +552,13 @@ impl VelocityLl {
             _padding: 0.0,
         }
     }
+
+    pub fn with_velocity(x: f32, y: f32) -> Self {
+        Self {
+            position: Vector3 { x, y, z: 0.0 },
+            _padding: 0.0,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -0,0 +1,180 @@
+use std::path::Path;
+use wgpu::util::DeviceExt;
+use cgmath::SquareMatrix;
+use super::bounding_box::BoundingBox;
+use super::compute::SimulationCompute;
+use super::cpu_solver::{Bounds, CpuSolver};
+use super::grid::HashStats;
+use super::time_step::TimeStep;
+use super::solver::SolverStats;
+use super::simulation::{Backend, WaterSimulation};
+use super::scene::Scene;
+use crate::state::camera::camera::MatrixUniform;
+
+// the simulation without a window or surface, for long runs on machines without a display
+pub struct Simulator {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub backend: Backend,
+    pub water_simulation: WaterSimulation,
+    pub compute: SimulationCompute,
+    pub cpu_solver: CpuSolver,
+    pub bounds: Bounds,
+    pub steps: u64,
+
+    bounding_box_buffer: wgpu::Buffer,
+    // only kept alive for the bind groups in `compute`
+    _radius_buffer: wgpu::Buffer,
+    _proj_view_inv_buffer: wgpu::Buffer,
+}
+
+impl Simulator {
+    // `force_fallback_adapter` asks wgpu for a software adapter, for machines without a gpu
+    pub async fn new(backend: Backend, scene: &Scene, force_fallback_adapter: bool) -> Self {
+        let wgpu_instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+
+        let adapter = wgpu_instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            }
+        ).await.expect("adapter request");
+        log::info!("Headless adapter: {:?}", adapter.get_info());
+
+        let (device, queue) = SimulationCompute::request_device(&adapter, "headless device").await;
+
+        let mut water_simulation = WaterSimulation::new(&device);
+        water_simulation.load_scene(scene, &queue);
+
+        let bounds = Bounds {
+            modes: water_simulation.boundary_modes,
+            ..Bounds::at_rest(cgmath::vec2(0.0, 0.0), cgmath::vec2(water_simulation.bound_size[0], water_simulation.bound_size[1]))
+        };
+
+        let radius_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Info Buffer"),
+            contents: bytemuck::cast_slice(&[water_simulation.radius]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bounding_box_buffer = BoundingBox::create_buffer(&device, bounds);
+
+        // no camera, so the mouse interaction never lands anywhere meaningful
+        let proj_view_inv = MatrixUniform { matrix: cgmath::Matrix4::<f32>::identity().into() };
+        let proj_view_inv_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("View Buffer Inverse"),
+            contents: bytemuck::cast_slice(&[proj_view_inv]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let compute = SimulationCompute::new(
+            &device,
+            &queue,
+            &water_simulation,
+            &radius_buffer,
+            &bounding_box_buffer,
+            &proj_view_inv_buffer,
+        );
+        let cpu_solver = CpuSolver::new(water_simulation.max_particles);
+
+        Self {
+            device,
+            queue,
+            backend,
+            water_simulation,
+            compute,
+            cpu_solver,
+            bounds,
+            steps: 0,
+
+            bounding_box_buffer,
+            _radius_buffer: radius_buffer,
+            _proj_view_inv_buffer: proj_view_inv_buffer,
+        }
+    }
+
+    pub fn step(&mut self) {
+        if self.water_simulation.update_boundary() {
+            self.compute.grid.write_boundary(&self.queue, &self.water_simulation.boundary);
+        }
+        self.water_simulation.update_body_samples(&self.queue);
+        match self.backend {
+            Backend::Gpu => {
+                self.queue.write_buffer(&self.water_simulation.num_particles_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.num_particles]));
+                self.queue.write_buffer(&self.water_simulation.params_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.params]));
+                self.queue.write_buffer(&self.water_simulation.time_step_params_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.time_step_params]));
+                self.queue.write_buffer(&self.water_simulation.solver_params_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.solver_params]));
+                self.compute.step(&self.device, &self.queue, &self.water_simulation);
+            }
+            Backend::Cpu => {
+                self.cpu_solver.step(&mut self.water_simulation, self.bounds, None);
+            }
+        }
+        self.steps += 1;
+    }
+
+    // delta time of the last step and the simulated seconds so far, waits for the gpu
+    pub fn time_step(&self) -> TimeStep {
+        match self.backend {
+            Backend::Gpu => futures::executor::block_on(self.compute.read_time_step(&self.device, &self.queue)),
+            Backend::Cpu => self.cpu_solver.time_step(),
+        }
+    }
+
+    pub fn solver_stats(&self) -> SolverStats {
+        match self.backend {
+            Backend::Gpu => futures::executor::block_on(self.compute.read_solver_stats(&self.device, &self.queue)),
+            Backend::Cpu => self.cpu_solver.solver_stats(),
+        }
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time_step().time
+    }
+
+    // advances `steps` steps and blocks until the gpu has caught up
+    pub fn advance(&mut self, steps: u32) {
+        for _ in 0..steps {
+            self.step();
+        }
+        self.device.poll(wgpu::Maintain::Wait);
+    }
+
+    // pulls the gpu state back into the cpu vectors of `water_simulation`,
+    // a no-op on the cpu backend where those are already the truth
+    pub fn sync(&mut self) {
+        if self.backend == Backend::Cpu {
+            return;
+        }
+        self.water_simulation.sync_from_gpu(&self.device, &self.queue);
+    }
+
+    pub fn hash_stats(&self) -> HashStats {
+        match self.backend {
+            Backend::Gpu => {
+                // `bounds` is where the container rests, the last step hashed where the motion had taken it
+                let bounds = self.water_simulation.container_motion.apply(self.bounds, self.time());
+                futures::executor::block_on(self.water_simulation.read_hash_stats(&self.device, &self.queue, &self.compute.grid, bounds))
+            }
+            Backend::Cpu => self.cpu_solver.hash_stats(&self.water_simulation),
+        }
+    }
+
+    pub fn save_snapshot(&mut self, path: &Path) -> Result<(), String> {
+        self.sync();
+        self.water_simulation.save_snapshot(path, self.time(), self.bounds.center)
+    }
+
+    pub fn load_snapshot(&mut self, path: &Path) -> Result<(), String> {
+        let container = self.water_simulation.load_snapshot(path, &self.queue)?;
+        self.bounds.size = cgmath::vec2(self.water_simulation.bound_size[0], self.water_simulation.bound_size[1]);
+        if let Some(container) = container {
+            self.bounds.center = container.center.into();
+            self.cpu_solver.set_time(container.time);
+            self.compute.write_time(&self.queue, container.time);
+        }
+        BoundingBox::write_buffer(&self.queue, &self.bounding_box_buffer, self.bounds);
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use std::path::Path;
+use bytemuck::{Pod, Zeroable};
+use super::container::ContainerMotion;
+use super::params::SimParams;
+use super::rigid_body::RigidBody;
+use super::simulation::{DensityLl, PositionLl, VelocityLl};
+use super::solver::SolverParams;
+use super::time_step::TimeStepParams;
+
+// binary dump of everything needed to resume a run.
+// layout, all little endian:
+//   magic "FSNP" | version u32 | num_particles u32 | bound_size 2 x f32 | num_bodies u32
+//   | SimParams | SavedContainer | boundary modes 2 x u32 | SavedSolver | positions | velocities
+//   | densities | bodies | fnv-1a 64 checksum of everything before it
+// older versions lack parts of it:
+//   2: no num_bodies, bodies, container or modes, loads into scenes without bodies
+//   3: no container or modes, loads into scenes whose container stands still
+//   4: no modes, loads into scenes with reflective walls all around
+//   5: no solver, keeps the one of the scene it is loaded into
+const MAGIC: &[u8; 4] = b"FSNP";
+const VERSION: u32 = 6;
+const OLDEST_VERSION: u32 = 2;
+const HEADER_SIZE: usize = 4 + 4 + 4 + 8;
+const CHECKSUM_SIZE: usize = 8;
+
+pub struct Snapshot {
+    pub bound_size: [f32; 2],
+    pub params: SimParams,
+    pub positions: Vec<PositionLl>,
+    pub velocities: Vec<VelocityLl>,
+    pub densities: Vec<DensityLl>,
+    // none from a version 2 file, those were written before there were bodies
+    pub bodies: Option<Vec<RigidBody>>,
+    // none before version 4, the container could not move back then
+    pub container: Option<SavedContainer>,
+    // a `BoundaryMode` as u32 for each axis, none before version 5
+    pub modes: Option<[u32; 2]>,
+    // none before version 6
+    pub solver: Option<SavedSolver>,
+}
+
+// the simulated time and where the container rests, so its scripted motion picks up at the same
+// phase the fluid was saved in
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SavedContainer {
+    pub time: f32,
+    pub center: [f32; 2],
+    pub motion: ContainerMotion,
+}
+
+unsafe impl Pod for SavedContainer {}
+unsafe impl Zeroable for SavedContainer {}
+
+// how the run was stepped, a resumed run would behave differently with other settings
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SavedSolver {
+    // a `Solver` as u32
+    pub solver: u32,
+    pub table_size: u32,
+    pub solver_params: SolverParams,
+    pub time_step_params: TimeStepParams,
+}
+
+unsafe impl Pod for SavedSolver {}
+unsafe impl Zeroable for SavedSolver {}
+
+impl Snapshot {
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_bytes())
+            .map_err(|e| format!("could not write snapshot '{}': {}", path.display(), e))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("could not read snapshot '{}': {}", path.display(), e))?;
+        Self::from_bytes(&bytes).map_err(|e| format!("invalid snapshot '{}': {}", path.display(), e))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.positions.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.bound_size));
+        let bodies = self.bodies.as_deref().unwrap_or(&[]);
+        bytes.extend_from_slice(&(bodies.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(bytemuck::bytes_of(&self.params));
+        bytes.extend_from_slice(bytemuck::bytes_of(&self.container.unwrap_or_default()));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.modes.unwrap_or_default()));
+        bytes.extend_from_slice(bytemuck::bytes_of(&self.solver.unwrap_or_default()));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.positions));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.velocities));
+        bytes.extend_from_slice(bytemuck::cast_slice(&self.densities));
+        bytes.extend_from_slice(bytemuck::cast_slice(bodies));
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE + CHECKSUM_SIZE || &bytes[0..4] != MAGIC {
+            return Err("not a snapshot file".to_string());
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        if fnv1a(body) != u64::from_le_bytes(checksum.try_into().unwrap()) {
+            return Err("checksum mismatch, the file is corrupt".to_string());
+        }
+
+        let version = u32::from_le_bytes(body[4..8].try_into().unwrap());
+        if !(OLDEST_VERSION..=VERSION).contains(&version) {
+            return Err(format!("unsupported version {}, expected {} to {}", version, OLDEST_VERSION, VERSION));
+        }
+
+        let num_particles = u32::from_le_bytes(body[8..12].try_into().unwrap()) as usize;
+        let bound_size = [
+            f32::from_le_bytes(body[12..16].try_into().unwrap()),
+            f32::from_le_bytes(body[16..20].try_into().unwrap()),
+        ];
+
+        let mut reader = Reader { bytes: &body[HEADER_SIZE..] };
+        let num_bodies = if version >= 3 { Some(reader.read::<u32>(1)?[0] as usize) } else { None };
+        let params = reader.read::<SimParams>(1)?[0];
+        let container = if version >= 4 { Some(reader.read::<SavedContainer>(1)?[0]) } else { None };
+        let modes = if version >= 5 { Some(reader.read::<[u32; 2]>(1)?[0]) } else { None };
+        let solver = if version >= 6 { Some(reader.read::<SavedSolver>(1)?[0]) } else { None };
+        let positions = reader.read::<PositionLl>(num_particles)?;
+        let velocities = reader.read::<VelocityLl>(num_particles)?;
+        let densities = reader.read::<DensityLl>(num_particles)?;
+        let bodies = num_bodies.map(|count| reader.read::<RigidBody>(count)).transpose()?;
+        if !reader.bytes.is_empty() {
+            return Err(format!("{} trailing bytes", reader.bytes.len()));
+        }
+
+        Ok(Self { bound_size, params, positions, velocities, densities, bodies, container, modes, solver })
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn read<T: bytemuck::Pod>(&mut self, count: usize) -> Result<Vec<T>, String> {
+        let size = count * std::mem::size_of::<T>();
+        if self.bytes.len() < size {
+            return Err("file ends early".to_string());
+        }
+        let (data, rest) = self.bytes.split_at(size);
+        self.bytes = rest;
+        // the slice is not necessarily aligned for T, so copy it out
+        let mut out = vec![T::zeroed(); count];
+        bytemuck::cast_slice_mut::<T, u8>(&mut out).copy_from_slice(data);
+        Ok(out)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(bodies: Option<Vec<RigidBody>>) -> Snapshot {
+        Snapshot {
+            bound_size: [30.0, 20.0],
+            params: SimParams::default(),
+            positions: vec![PositionLl::new(1.0, 2.0), PositionLl::new(-3.0, 4.0)],
+            velocities: vec![VelocityLl::with_velocity(0.5, -0.5); 2],
+            densities: vec![DensityLl::new(); 2],
+            bodies,
+            container: None,
+            modes: None,
+            solver: None,
+        }
+    }
+
+    // the bytes an older version wrote, it only had the parts that are some
+    fn old_bytes(version: u32, old: &Snapshot) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&(old.positions.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(bytemuck::cast_slice(&old.bound_size));
+        if let Some(bodies) = &old.bodies {
+            bytes.extend_from_slice(&(bodies.len() as u32).to_le_bytes());
+        }
+        bytes.extend_from_slice(bytemuck::bytes_of(&old.params));
+        if let Some(container) = &old.container {
+            bytes.extend_from_slice(bytemuck::bytes_of(container));
+        }
+        if let Some(modes) = &old.modes {
+            bytes.extend_from_slice(bytemuck::cast_slice(modes));
+        }
+        bytes.extend_from_slice(bytemuck::cast_slice(&old.positions));
+        bytes.extend_from_slice(bytemuck::cast_slice(&old.velocities));
+        bytes.extend_from_slice(bytemuck::cast_slice(&old.densities));
+        if let Some(bodies) = &old.bodies {
+            bytes.extend_from_slice(bytemuck::cast_slice(bodies));
+        }
+        let checksum = fnv1a(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn bodies_container_modes_and_solver_survive_the_round_trip() {
+        let body = RigidBody { position: [2.0, -1.0], velocity: [0.5, 3.0], angle: 0.3, angular_velocity: -1.0, ..RigidBody::default() };
+        let mut motion = ContainerMotion::default();
+        motion.frequency = 0.6;
+        let container = SavedContainer { time: 2.5, center: [1.0, 0.0], motion };
+        let solver = SavedSolver { solver: 2, table_size: 512, solver_params: SolverParams { max_iterations: 9, ..SolverParams::default() }, ..SavedSolver::default() };
+        let written = Snapshot { container: Some(container), modes: Some([1, 0]), solver: Some(solver), ..snapshot(Some(vec![body])) };
+        let loaded = Snapshot::from_bytes(&written.to_bytes()).unwrap();
+        let saved = loaded.container.expect("container missing");
+        assert_eq!((saved.time, saved.center, saved.motion), (2.5, [1.0, 0.0], container.motion));
+        assert_eq!(loaded.modes, Some([1, 0]));
+        let saved = loaded.solver.expect("solver missing");
+        assert_eq!((saved.solver, saved.table_size, saved.solver_params.max_iterations), (2, 512, 9));
+        let bodies = loaded.bodies.expect("bodies missing");
+        assert_eq!(bodies.len(), 1);
+        assert_eq!((bodies[0].position, bodies[0].velocity, bodies[0].angle, bodies[0].angular_velocity), ([2.0, -1.0], [0.5, 3.0], 0.3, -1.0));
+    }
+
+    #[test]
+    fn older_versions_load_without_the_parts_they_lack() {
+        let body = RigidBody { position: [2.0, -1.0], ..RigidBody::default() };
+        let container = SavedContainer { time: 1.5, ..SavedContainer::default() };
+
+        let loaded = Snapshot::from_bytes(&old_bytes(2, &snapshot(None))).unwrap();
+        assert!(loaded.bodies.is_none() && loaded.container.is_none() && loaded.modes.is_none());
+        assert_eq!(loaded.positions[1].position, PositionLl::new(-3.0, 4.0).position);
+
+        let loaded = Snapshot::from_bytes(&old_bytes(3, &snapshot(Some(vec![body])))).unwrap();
+        assert_eq!(loaded.bodies.expect("bodies missing")[0].position, [2.0, -1.0]);
+        assert!(loaded.container.is_none() && loaded.modes.is_none());
+
+        let old = Snapshot { container: Some(container), ..snapshot(Some(vec![body])) };
+        let loaded = Snapshot::from_bytes(&old_bytes(4, &old)).unwrap();
+        assert_eq!(loaded.container.expect("container missing").time, 1.5);
+        assert!(loaded.modes.is_none());
+        assert_eq!(loaded.positions[1].position, PositionLl::new(-3.0, 4.0).position);
+
+        let old = Snapshot { modes: Some([2, 1]), ..old };
+        let loaded = Snapshot::from_bytes(&old_bytes(5, &old)).unwrap();
+        assert_eq!(loaded.modes, Some([2, 1]));
+        assert!(loaded.solver.is_none());
+    }
+}
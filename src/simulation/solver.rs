@@ -0,0 +1,122 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+// which pressure solver the step runs, picked per scene and switchable at runtime
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Solver {
+    // explicit double density relaxation, the original scheme
+    #[default]
+    Sph,
+    // position based fluids, Macklin & Müller 2013
+    Pbf,
+    // divergence-free sph, Bender & Koschier 2015
+    Dfsph,
+    // implicit incompressible sph, Ihmsen et al. 2014
+    Iisph,
+}
+
+impl Solver {
+    pub const ALL: [Solver; 4] = [Solver::Sph, Solver::Pbf, Solver::Dfsph, Solver::Iisph];
+
+    // unknown values fall back to sph
+    pub fn from_index(index: u32) -> Self {
+        Self::ALL.get(index as usize).copied().unwrap_or_default()
+    }
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|s| *s == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    // whether the step runs until an error tolerance and fills in `SolverStats`
+    pub fn solves_to_tolerance(self) -> bool {
+        matches!(self, Solver::Dfsph | Solver::Iisph)
+    }
+}
+
+// knobs of the iterative solvers, mirrors `SolverParams` in simulation.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolverParams {
+    // constraint projections per step, pbf
+    pub iterations: u32,
+    // constraint force mixing, softens the constraint where the gradient is tiny
+    pub relaxation: f32,
+    // tensile instability correction, s_corr = -k (W(r) / W(dq * h))^n
+    pub tensile_k: f32,
+    pub tensile_n: f32,
+    pub tensile_dq: f32,
+    // cap on the iterations of a solve that runs to a tolerance, dfsph and iisph
+    pub max_iterations: u32,
+    // average density error over the rest density a solve stops at
+    pub tolerance: f32,
+    // same for the rate of compression, relative to the rest density per second
+    pub divergence_tolerance: f32,
+    // relaxation of the jacobi pressure update, iisph
+    pub omega: f32,
+    #[serde(skip)]
+    pub _padding: [f32; 3],
+}
+
+unsafe impl Pod for SolverParams {}
+unsafe impl Zeroable for SolverParams {}
+
+impl Default for SolverParams {
+    fn default() -> Self {
+        Self {
+            iterations: 4,
+            relaxation: 10.0,
+            tensile_k: 0.1,
+            tensile_n: 4.0,
+            tensile_dq: 0.2,
+            max_iterations: 32,
+            tolerance: 0.005,
+            divergence_tolerance: 0.1,
+            omega: 0.5,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+// per particle scratch space of the iterative solvers, mirrors `SolverData` in simulation.wgsl.
+// what the slots hold depends on the solver, see the kernels
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SolverData {
+    pub a: [f32; 4],
+    pub b: [f32; 4],
+    pub c: [f32; 4],
+}
+
+unsafe impl Pod for SolverData {}
+unsafe impl Zeroable for SolverData {}
+
+pub const DIVERGENCE_SOLVE: usize = 0;
+pub const DENSITY_SOLVE: usize = 1;
+
+// how the iterative solves of the last step went, mirrors `SolverStats` in simulation.wgsl.
+// `iterations` counts the correction passes, `error` is the average error after the last one
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SolverStats {
+    error_sum: u32,
+    converged: [u32; 2],
+    pub iterations: [u32; 2],
+    pub error: [f32; 2],
+}
+
+unsafe impl Pod for SolverStats {}
+unsafe impl Zeroable for SolverStats {}
+
+impl std::fmt::Display for SolverStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "density error {:.3}% after {} iterations", self.error[DENSITY_SOLVE] * 100.0, self.iterations[DENSITY_SOLVE])?;
+        // only dfsph has a divergence solve
+        if self.iterations[DIVERGENCE_SOLVE] > 0 || self.error[DIVERGENCE_SOLVE] > 0.0 {
+            write!(f, ", divergence error {:.4}/s after {} iterations", self.error[DIVERGENCE_SOLVE], self.iterations[DIVERGENCE_SOLVE])?;
+        }
+        Ok(())
+    }
+}
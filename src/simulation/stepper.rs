@@ -0,0 +1,81 @@
+// turns wall clock time into a whole number of fixed physics substeps, so the simulation runs
+// at the same speed whatever the display refresh rate is. leftover time carries to the next frame
+#[derive(Debug, Clone)]
+pub struct Stepper {
+    // simulated seconds per substep
+    pub substep: f32,
+    // the most substeps a single frame may run, anything beyond that is dropped
+    pub max_substeps: u32,
+    // simulated seconds per wall clock second
+    pub time_scale: f32,
+    accumulator: f32,
+}
+
+// the rate `substeps` is counted against
+pub const NOMINAL_FRAME_TIME: f32 = 1.0 / 60.0;
+
+impl Stepper {
+    // `substeps` fixed steps per nominal 60 Hz frame, never longer than `max_delta_time`
+    pub fn new(substeps: u32, max_delta_time: f32, time_scale: f32) -> Self {
+        let substeps = substeps.max(1);
+        Self {
+            substep: (NOMINAL_FRAME_TIME / substeps as f32).min(max_delta_time),
+            max_substeps: substeps * 4,
+            time_scale,
+            accumulator: 0.0,
+        }
+    }
+
+    // number of substeps to run for a frame that took `elapsed` wall clock seconds
+    pub fn advance(&mut self, elapsed: f32) -> u32 {
+        self.accumulator += elapsed * self.time_scale;
+        // a frame of exactly n substeps can round to just under n, which would run one short now
+        // and one extra later, so a sliver below a whole substep still counts as one
+        let substeps = (self.accumulator / self.substep + 1e-3) as u32;
+        if substeps > self.max_substeps {
+            // too far behind to catch up, e.g. after a stall, so give the time up instead
+            self.accumulator = 0.0;
+            return self.max_substeps;
+        }
+        self.accumulator -= substeps as f32 * self.substep;
+        substeps
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(stepper: &mut Stepper, frames: u32, frame_time: f32) -> Vec<u32> {
+        (0..frames).map(|_| stepper.advance(frame_time)).collect()
+    }
+
+    #[test]
+    fn refresh_rate_does_not_change_the_simulated_time() {
+        let mut at_60 = Stepper::new(2, 1.0, 1.0);
+        let mut at_144 = Stepper::new(2, 1.0, 1.0);
+        let steps_60 = run(&mut at_60, 60 * 3, 1.0 / 60.0);
+        let steps_144 = run(&mut at_144, 144 * 3, 1.0 / 144.0);
+        assert_eq!(steps_60.iter().sum::<u32>(), 2 * 60 * 3);
+        assert_eq!(steps_144.iter().sum::<u32>(), steps_60.iter().sum::<u32>());
+        // a 60 Hz frame is exactly one nominal frame
+        assert!(steps_60.iter().all(|&steps| steps == 2), "{:?}", steps_60);
+
+        let mut slow_motion = Stepper::new(2, 1.0, 0.5);
+        assert_eq!(run(&mut slow_motion, 60 * 3, 1.0 / 60.0).iter().sum::<u32>(), 60 * 3);
+    }
+
+    #[test]
+    fn a_stall_is_capped_and_its_backlog_dropped() {
+        let mut stepper = Stepper::new(2, 1.0, 1.0);
+        assert_eq!(stepper.advance(1.0 / 120.0), 1);
+        assert_eq!(stepper.advance(2.0), stepper.max_substeps);
+        // nothing of the stall is left to catch up on
+        assert_eq!(stepper.advance(1.0 / 60.0), 2);
+        assert_eq!(stepper.advance(0.0), 0);
+    }
+}
@@ -0,0 +1,79 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+// bounds for the adaptive step, mirrors `TimeStepParams` in simulation.wgsl.
+// setting min_delta_time == max_delta_time turns it back into a fixed step
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeStepParams {
+    // fraction of a smoothing radius the fastest particle may travel per step
+    pub cfl_factor: f32,
+    // safety factor on sqrt(h / a_max) for the most accelerated particle
+    pub force_factor: f32,
+    pub min_delta_time: f32,
+    pub max_delta_time: f32,
+}
+
+unsafe impl Pod for TimeStepParams {}
+unsafe impl Zeroable for TimeStepParams {}
+
+impl Default for TimeStepParams {
+    fn default() -> Self {
+        Self {
+            cfl_factor: 0.4,
+            force_factor: 0.25,
+            min_delta_time: 1.0 / 2000.0,
+            max_delta_time: 1.0 / 60.0,
+        }
+    }
+}
+
+impl TimeStepParams {
+    // same as `update_time_step` in the shader. the maxima are from the previous step,
+    // a still scene gets max_delta_time
+    pub fn delta_time(&self, max_speed: f32, max_acceleration: f32, smoothing_radius: f32) -> f32 {
+        let mut delta_time = self.max_delta_time;
+        if max_speed > 0.0 {
+            delta_time = delta_time.min(self.cfl_factor * smoothing_radius / max_speed);
+        }
+        if max_acceleration > 0.0 {
+            delta_time = delta_time.min(self.force_factor * (smoothing_radius / max_acceleration).sqrt());
+        }
+        delta_time.clamp(self.min_delta_time, self.max_delta_time.max(self.min_delta_time))
+    }
+}
+
+// the step state the kernels share, mirrors `TimeStep` in simulation.wgsl. the maxima are
+// stored as float bits in atomics on the gpu, which compare the same way for positive floats
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct TimeStep {
+    pub delta_time: f32,
+    pub time: f32,
+    pub max_speed: f32,
+    pub max_acceleration: f32,
+}
+
+unsafe impl Pod for TimeStep {}
+unsafe impl Zeroable for TimeStep {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_time_follows_the_tightest_bound() {
+        let params = TimeStepParams::default();
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-7;
+
+        assert_eq!(params.delta_time(0.0, 0.0, 1.0), params.max_delta_time);
+        // 0.4 of a smoothing radius at 100 units per second
+        assert!(close(params.delta_time(100.0, 0.0, 1.0), 0.004));
+        // 0.25 sqrt(1 / 10000)
+        assert!(close(params.delta_time(0.0, 10000.0, 1.0), 0.0025));
+        assert!(close(params.delta_time(100.0, 10000.0, 1.0), 0.0025));
+        assert_eq!(params.delta_time(1e6, 0.0, 1.0), params.min_delta_time);
+        assert_eq!(params.delta_time(0.0, 1e12, 1.0), params.min_delta_time);
+    }
+}
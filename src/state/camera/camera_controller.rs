@@ -2,7 +2,7 @@
 use std::time::{Duration, Instant};
 
 use cgmath::Vector2;
-use winit::{event::{WindowEvent::{self, KeyboardInput}, ElementState, KeyEvent}, keyboard::{KeyCode, PhysicalKey}, dpi::PhysicalPosition};
+use winit::{event::{WindowEvent::{self, KeyboardInput}, ElementState, KeyEvent, MouseButton}, keyboard::{KeyCode, PhysicalKey}, dpi::PhysicalPosition};
 use super::camera::{ViewMatrix, CameraMatrix};
 use fluid_simulations::SVec;
 use bytemuck::{Pod, Zeroable};
@@ -28,6 +28,8 @@ pub struct CameraController {
     is_up_pressed: bool,
     is_down_pressed: bool,
     pub is_mouse_pressed: bool,
+    // right button held, the container follows the mouse
+    pub is_container_grabbed: bool,
     previous_mouse_position: Option<PhysicalPosition<f64>>,
     last_frame_time: Instant,
     yaw: f32,
@@ -53,6 +55,7 @@ impl CameraController {
             is_up_pressed: false,
             is_down_pressed: false,
             is_mouse_pressed: false,
+            is_container_grabbed: false,
             previous_mouse_position: None,
             last_frame_time: Instant::now(),
             yaw,
@@ -106,12 +109,16 @@ impl CameraController {
                     _ => false
                 }
             }
-            WindowEvent::MouseInput { state, .. } => {
-                self.is_mouse_pressed = *state == ElementState::Pressed;
+            WindowEvent::MouseInput { state, button, .. } => {
+                let is_pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Right => self.is_container_grabbed = is_pressed,
+                    _ => self.is_mouse_pressed = is_pressed,
+                }
                 true
             }
             WindowEvent::CursorMoved { position, .. } => {
-                if self.is_mouse_pressed {
+                if self.is_mouse_pressed || self.is_container_grabbed {
                     if let Some(prev_pos) = self.previous_mouse_position {
                         self.x_delta += (position.x - prev_pos.x) as f32;
                         self.y_delta += (position.y - prev_pos.y) as f32;
@@ -82,7 +82,67 @@ impl<'a> EventHandler for State<'a> {
                             is_synthetic: false, 
                             ..
                         } => {
-                            self.water_simulation.smoothing_radius += 0.01;
+                            // the hash cells follow the radius, so the neighbourhoods stay consistent
+                            self.water_simulation.params.smoothing_radius += 0.01;
+                            log::info!("smoothing radius {:.2}, cell size {:.2}", self.water_simulation.params.smoothing_radius, self.water_simulation.params.cell_size());
+                        }
+                        WindowEvent::KeyboardInput {
+                            event: winit::event::KeyEvent { 
+                                state: ElementState::Pressed, 
+                                physical_key: Code(KeyCode::KeyM),
+                                ..
+                            }, 
+                            is_synthetic: false, 
+                            ..
+                        } => {
+                            self.water_simulation.solver = self.water_simulation.solver.next();
+                            log::info!("solver {:?}", self.water_simulation.solver);
+                        }
+                        WindowEvent::KeyboardInput {
+                            event: winit::event::KeyEvent { 
+                                state: ElementState::Pressed, 
+                                physical_key: Code(KeyCode::BracketLeft),
+                                ..
+                            }, 
+                            is_synthetic: false, 
+                            ..
+                        } => {
+                            self.stepper.time_scale /= 2.0;
+                            log::info!("time scale {}", self.stepper.time_scale);
+                        }
+                        WindowEvent::KeyboardInput {
+                            event: winit::event::KeyEvent { 
+                                state: ElementState::Pressed, 
+                                physical_key: Code(KeyCode::BracketRight),
+                                ..
+                            }, 
+                            is_synthetic: false, 
+                            ..
+                        } => {
+                            self.stepper.time_scale *= 2.0;
+                            log::info!("time scale {}", self.stepper.time_scale);
+                        }
+                        WindowEvent::KeyboardInput {
+                            event: winit::event::KeyEvent { 
+                                state: ElementState::Pressed, 
+                                physical_key: Code(KeyCode::F5),
+                                ..
+                            }, 
+                            is_synthetic: false, 
+                            ..
+                        } => {
+                            self.save_snapshot();
+                        }
+                        WindowEvent::KeyboardInput {
+                            event: winit::event::KeyEvent { 
+                                state: ElementState::Pressed, 
+                                physical_key: Code(KeyCode::F9),
+                                ..
+                            }, 
+                            is_synthetic: false, 
+                            ..
+                        } => {
+                            self.load_snapshot();
                         }
                         WindowEvent::RedrawRequested => {
                             if !self.surface_configured {
@@ -97,8 +157,9 @@ impl<'a> EventHandler for State<'a> {
                                     if !self.should_update() {
                                         return;
                                     }
-                                    self.timestamp();
+                                    // update measures the frame from the last timestamp
                                     self.update();
+                                    self.timestamp();
                                 }
                             }
                             match self.render() {
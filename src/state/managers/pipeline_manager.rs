@@ -61,22 +61,6 @@ impl<'a> PipelineManager<'a> {
     }
     
 
-    pub fn create_compute_pipeline(
-        &self,
-        label: &str,
-        layout: &wgpu::PipelineLayout,
-        compute_shader: &wgpu::ShaderModule,
-        entery_point: &str,
-    ) -> wgpu::ComputePipeline {
-        self.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor   {
-            label: Some(label),
-            layout: Some(layout),
-            module: compute_shader,
-            entry_point: entery_point,
-            compilation_options: Default::default(),
-        })
-    }
-
     pub fn create_wgsl_pipeline(
         &self,
         layout: &wgpu::PipelineLayout,
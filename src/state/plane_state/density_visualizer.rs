@@ -18,7 +18,7 @@ impl DensityVisualizer {
         camera_bind_group_layout: &BindGroupLayout,
         particle_bind_group_layout: &BindGroupLayout, 
         water_simulation: &WaterSimulation, ) -> Self {
-        let smoothing_buffer = Self::create_smoothing_buffer(device, water_simulation.smoothing_radius);
+        let smoothing_buffer = Self::create_smoothing_buffer(device, water_simulation.params.smoothing_radius);
         
         let density_vert = shader_helper::create_shader_module(&device, "Density Shader vert", include_str!("../../shader/density/density.vert"), naga::ShaderStage::Vertex);
         let density_frag = shader_helper::create_shader_module(&device, "Density Shader frag", include_str!("../../shader/density/density.frag"), naga::ShaderStage::Fragment);
@@ -22,7 +22,7 @@ impl PressureVisualizer {
         
         let shader = wgpu::ShaderModuleDescriptor {
             label: Some("Pressure Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("../../shader/pressure/pressure.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(concat!(include_str!("../../shader/pressure/pressure.wgsl"), include_str!("../../shader/common/kernel.wgsl")).into()),
         };
 
 
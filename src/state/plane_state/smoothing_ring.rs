@@ -17,7 +17,7 @@ impl SmoothingPipeline {
         pipeline_manager: &PipelineManager, 
         camera_bind_group_layout: &BindGroupLayout,
         water_simulation: &WaterSimulation) -> Self {
-        let smoothing_buffer = Self::create_smoothing_buffer(device, water_simulation.smoothing_radius);
+        let smoothing_buffer = Self::create_smoothing_buffer(device, water_simulation.params.smoothing_radius);
 
         let smoothing_vert = shader_helper::create_shader_module(&device, "Smoothing Particle Shader", include_str!("../../shader/smoothing/smoothing.vert"), naga::ShaderStage::Vertex);
         let smoothing_frag = shader_helper::create_shader_module(&device, "Smoothing Particle Shader", include_str!("../../shader/smoothing/smoothing.frag"), naga::ShaderStage::Fragment);
@@ -1,7 +1,8 @@
 use wgpu::util::DeviceExt;
 use std::time::{Duration, Instant};
+use std::path::Path;
 use crate::utils::{console_logger::ConsoleLogger, fps::FpsTracker};
-use crate::simulation::{bounding_box::BoundingBox, simulation::{WaterSimulation, ParticleLl, PositionLl, VelocityLl, DensityLl}};
+use crate::simulation::{bounding_box::BoundingBox, obstacle::ObstacleOutline, rigid_body::BodyOutline, simulation::{WaterSimulation, Backend}, cpu_solver::CpuSolver, compute::SimulationCompute, scene::Scene, export::VtkExporter, stepper::Stepper, solver::SolverStats};
 use super::camera::camera::{ViewMatrix, CameraMatrix};
 use super::events::{ApplicationEvent, Update, EventHandler};
 use super::plane_state::pressure_visualizer;
@@ -12,8 +13,6 @@ use super::managers::{texture_manager::TextureManager, pipeline_manager::Pipelin
 use super::shader_helper;
 use fluid_simulations::{VERTICESIMG, INDICES, VertexImg};
 use super::plane_state::{density_visualizer::DensityVisualizer, smoothing_ring::SmoothingPipeline};
-use crate::simulation::grid::{Grid, Constants};
-use crate::state::camera::camera_controller::MouseDelta;
 use crate::state::camera::camera::MatrixUniform;
 use crate::state::camera::camera::inverse;
 use cgmath::SquareMatrix;
@@ -27,6 +26,7 @@ use winit::{
 
 
 const TARGET_FPS: u32 = 2;
+const SNAPSHOT_PATH: &str = "snapshot.fsnp";
 
 pub struct State<'a> {
     pub window: &'a Window,
@@ -61,43 +61,37 @@ pub struct State<'a> {
     pub proj_buffer: wgpu::Buffer,
     pub proj_view_inv_buffer: wgpu::Buffer,
     pub camera_bind_group: wgpu::BindGroup,
-    pub camera_bind_group_inverse: wgpu::BindGroup,
     pub proj_view_inv: MatrixUniform,
 
     pub camera_controller: CameraController,
 
+    pub backend: Backend,
     pub water_simulation: WaterSimulation,
-    pub particle_buffer: wgpu::Buffer,
-    pub particle_bind_group: wgpu::BindGroup,
+    pub cpu_solver: CpuSolver,
+    pub stepper: Stepper,
+    pub exporter: Option<VtkExporter>,
+    pub simulated_steps: u64,
+    // read back once per frame while the solver runs to a tolerance
+    pub solver_stats: Option<SolverStats>,
+    pub simulation_compute: SimulationCompute,
     pub particle_pipeline: wgpu::RenderPipeline,
     pub radius_bind_group: wgpu::BindGroup,
+    // the container and the particle count, for drawing the periodic images of the particles
+    pub particle_images_bind_group: wgpu::BindGroup,
 
     pub bounding_box: BoundingBox,
+    // where the container sits relative to the mouse while it is dragged around
+    pub container_grab: Option<cgmath::Vector2<f32>>,
+    pub obstacle_outline: ObstacleOutline,
+    pub body_outline: BodyOutline,
 
     pub smoothing_pipeline: SmoothingPipeline,
     pub density_pipeline: DensityVisualizer,
-    pub predict_position_pipeline: wgpu::ComputePipeline,
-    pub calculate_density_pipeline: wgpu::ComputePipeline,
-    pub update_position_pipeline: wgpu::ComputePipeline,
-    pub update_spatial_hash_pipeline: wgpu::ComputePipeline,
-    pub sort_pipeline: wgpu::ComputePipeline,
-    pub indecies_pipeline: wgpu::ComputePipeline,
-    pub reset_indecies_pipeline: wgpu::ComputePipeline,
-    pub viscosity_pipeline: wgpu::ComputePipeline,
-
-    pub settings_bind_group: wgpu::BindGroup,
     pub pressure_visualizer: pressure_visualizer::PressureVisualizer,
-    pub delta_time_buffer: wgpu::Buffer,
-    pub cheat_depth_buffer: wgpu::Buffer,
-
-    pub pressed_buffer: wgpu::Buffer,
-    pub mouse_delta_buffer: wgpu::Buffer,
-
-    pub grid: Grid,
 }
 
 impl <'a> State <'a> {
-    pub async fn new(window: &'a Window) -> Self {
+    pub async fn new(window: &'a Window, backend: Backend, scene: &Scene, stepper: Stepper, exporter: Option<VtkExporter>) -> Self {
         let size = window.inner_size();
 
         let instance_descriptor = wgpu::InstanceDescriptor {
@@ -119,18 +113,7 @@ impl <'a> State <'a> {
             }
         ).await.expect("adapter request");
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("main device"),
-                required_features: wgpu::Features::VERTEX_WRITABLE_STORAGE | 
-                                    wgpu::Features::PUSH_CONSTANTS,
-                required_limits: wgpu::Limits{
-                    max_push_constant_size: 12,
-                    ..wgpu::Limits::default()
-                },
-            },
-            None,
-        ).await.expect("device request");
+        let (device, queue) = SimulationCompute::request_device(&adapter, "main device").await;
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -195,6 +178,12 @@ impl <'a> State <'a> {
 
         let camera_controller = CameraController::new(5.0, 20.0, &view);
         let mut water_simulation = WaterSimulation::new(&device);
+        water_simulation.load_scene(scene, &queue);
+        // every substep advances at most `stepper.substep`, the adaptive step may still go below it
+        // in which case the simulation falls behind wall clock time rather than going unstable
+        let time_step_params = &mut water_simulation.time_step_params;
+        time_step_params.max_delta_time = stepper.substep;
+        time_step_params.min_delta_time = time_step_params.min_delta_time.min(stepper.substep);
 
         let view_buffer = Self::create_init_buffer(
             &device,
@@ -283,21 +272,6 @@ impl <'a> State <'a> {
             label: Some("camera_bind_group_layout"),
         });
 
-        let camera_bind_inverse_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("camera_bind_group_layout"),
-        }); 
 
 
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -315,26 +289,68 @@ impl <'a> State <'a> {
             label: Some("camera_bind_group"),
         });
 
-        let camera_bind_group_inverse = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_inverse_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: proj_view_inv_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("camera_bind_group_inverse"),
+
+        
+        let bounding_box = BoundingBox::new(
+            cgmath::vec2(0.0, 0.0),
+            cgmath::vec2(water_simulation.bound_size[0], water_simulation.bound_size[1]),
+            water_simulation.boundary_modes,
+            &device,
+            &pipeline_manager,
+            &camera_bind_group_layout,
+        );
+
+        let body_outline = BodyOutline::new(
+            &water_simulation.body_buffer,
+            &water_simulation.body_point_buffer,
+            &device,
+            &pipeline_manager,
+            &camera_bind_group_layout,
+        );
+
+        let obstacle_outline = ObstacleOutline::new(
+            &water_simulation.obstacles,
+            &device,
+            &pipeline_manager,
+            &camera_bind_group_layout,
+        );
+
+
+
+
+        let simulation_compute = SimulationCompute::new(
+            &device,
+            &queue,
+            &water_simulation,
+            &radius_buffer,
+            &bounding_box.buffer,
+            &proj_view_inv_buffer,
+        );
+
+
+
+        let vert_texture_shader = shader_helper::create_shader_module(&device, "Vert Texture Shader", include_str!("../shader/texture/texture.vert"), naga::ShaderStage::Vertex);
+        let frag_texture_shader = shader_helper::create_shader_module(&device, "Frag Texture Shader", include_str!("../shader/texture/texture.frag"), naga::ShaderStage::Fragment);
+        let vert_particle_shader = shader_helper::create_shader_module(&device, "Vert Particle Shader", include_str!("../shader/particle/particle.vert"), naga::ShaderStage::Vertex);
+        let frag_particle_shader = shader_helper::create_shader_module(&device, "Frag Particle Shader", include_str!("../shader/particle/particle.frag"), naga::ShaderStage::Fragment);
+
+
+        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[
+                &texture_bind_group_layout,
+                &camera_bind_group_layout,
+            ], 
+            push_constant_ranges: &[],
         });
 
-        let particale_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let particle_images_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX | 
-                                wgpu::ShaderStages::COMPUTE| 
-                                wgpu::ShaderStages::FRAGMENT,
+                    visibility: wgpu::ShaderStages::VERTEX,
                     ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
@@ -342,141 +358,40 @@ impl <'a> State <'a> {
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
+                    visibility: wgpu::ShaderStages::VERTEX,
                     ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
                     count: None,
                 },
             ],
-            label: Some("particle_bind_group_layout"),
+            label: Some("particle_images_bind_group_layout"),
         });
-        
-        let particle_buffer = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Particle Buffer"),
-                size: (water_simulation.max_particles * std::mem::size_of::<ParticleLl>()) as u64,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            }
-        );
-
-        let position_buffer = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Position Buffer"),
-                size: (water_simulation.max_particles * std::mem::size_of::<PositionLl>()) as u64,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            }
-        );
-        
-        let velocity_buffer = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Velocity Buffer"),
-                size: (water_simulation.max_particles * std::mem::size_of::<VelocityLl>()) as u64,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            }
-        );
-
-        let density_buffer = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Density Buffer"),
-                size: (water_simulation.max_particles * std::mem::size_of::<DensityLl>()) as u64,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            }
-        );
-
-        let predicted_position_buffer = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Predicted Position Buffer"),
-                size: (water_simulation.max_particles * std::mem::size_of::<PositionLl>()) as u64,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            }
-        );
-
-        let bounding_box = BoundingBox::new(
-            cgmath::vec2(0.0, 0.0),
-            cgmath::vec2(water_simulation.bound_size[0], water_simulation.bound_size[1]),
-            &device,
-            &pipeline_manager,
-            &camera_bind_group_layout,
-        );
 
-
-
-        water_simulation.add_multiple_uniform_particles(2u32.pow(12), &queue, &particle_buffer, &position_buffer, &velocity_buffer, &density_buffer);
-
-
-        let particle_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &particale_bind_layout,
+        let particle_images_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &particle_images_bind_group_layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: position_buffer.as_entire_binding(),
+                    resource: bounding_box.buffer.as_entire_binding(),
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: velocity_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: density_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: predicted_position_buffer.as_entire_binding(),
+                    resource: water_simulation.num_particles_buffer.as_entire_binding(),
                 },
             ],
-            label: Some("particle_bind_group"),
-        });
-
-        let vert_texture_shader = shader_helper::create_shader_module(&device, "Vert Texture Shader", include_str!("../shader/texture/texture.vert"), naga::ShaderStage::Vertex);
-        let frag_texture_shader = shader_helper::create_shader_module(&device, "Frag Texture Shader", include_str!("../shader/texture/texture.frag"), naga::ShaderStage::Fragment);
-        let vert_particle_shader = shader_helper::create_shader_module(&device, "Vert Particle Shader", include_str!("../shader/particle/particle.vert"), naga::ShaderStage::Vertex);
-        let frag_particle_shader = shader_helper::create_shader_module(&device, "Frag Particle Shader", include_str!("../shader/particle/particle.frag"), naga::ShaderStage::Fragment);
-        let compute_shader = shader_helper::create_shader_module2(&device, "Compute Shader", include_str!("../shader/compute/simulation.wgsl"), naga::ShaderStage::Compute);
-
-
-        let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[
-                &texture_bind_group_layout,
-                &camera_bind_group_layout,
-            ], 
-            push_constant_ranges: &[],
+            label: Some("particle_images_bind_group"),
         });
 
         let particle_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Particle Pipeline Layout"),
             bind_group_layouts: &[
-                &particale_bind_layout,
+                &simulation_compute.particle_bind_layout,
                 &camera_bind_group_layout,
                 &radius_bind_group_layout,
+                &particle_images_bind_group_layout,
             ],
             push_constant_ranges: &[],
         });
@@ -514,267 +429,18 @@ impl <'a> State <'a> {
             &device,
             &pipeline_manager,
             &camera_bind_group_layout,
-            &particale_bind_layout,
+            &simulation_compute.particle_bind_layout,
             &water_simulation,
         );
 
-        let delta_time_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Delta Time Buffer"),
-            contents: bytemuck::cast_slice(&[0.0f32]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let max_particles_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Max Particles Buffer"),
-            contents: bytemuck::cast_slice(&[water_simulation.max_particles as u32]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let pressed_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Pressed Buffer"),
-            contents: bytemuck::cast_slice(&[0u32]),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let mouse_delta_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Mouse Delta Buffer"),
-            contents: bytemuck::cast_slice(&[MouseDelta{previous_position: cgmath::vec2(0.0, 0.0), current_position: cgmath::vec2(0.0, 0.0)}]),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let cheat_depth_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Cheat Depth Buffer"),
-            contents: bytemuck::cast_slice(&[0.33f32]),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-        });
-
-
-        let settings_bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 4,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 5,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 6,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 7,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-            label: Some("settings_bind_layout"),
-        });
-
-        let settings_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &settings_bind_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: radius_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: water_simulation.num_particles_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: bounding_box.buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: delta_time_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: max_particles_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 5,
-                    resource: pressed_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 6,
-                    resource: mouse_delta_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 7,
-                    resource: cheat_depth_buffer.as_entire_binding(),
-                },
-            ],
-            label: Some("settings_bind_group"),
-        });
-        
-        let grid = Grid::new(&device, &queue, water_simulation.max_particles);
-
-        let compute_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Simulation Pipeline Layout"),
-            bind_group_layouts: &[
-                &particale_bind_layout,
-                &settings_bind_layout,
-                &grid.grid_bind_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-
-        let compute_mouse_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Simulation Pipeline Layout"),
-            bind_group_layouts: &[
-                &particale_bind_layout,
-                &settings_bind_layout,
-                &grid.grid_bind_layout,
-                &camera_bind_inverse_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-
-        let gpu_sort_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("GPU Sort Pipeline Layout"),
-            bind_group_layouts: &[
-                &particale_bind_layout,
-                &settings_bind_layout,
-                &grid.grid_bind_layout,
-            ],
-            push_constant_ranges: &[
-                wgpu::PushConstantRange {
-                    stages: wgpu::ShaderStages::COMPUTE,
-                    range: 0..12,
-                },
-            ],
-        });
-
-        let predict_position_pipeline = pipeline_manager.create_compute_pipeline(
-            "predict_position_compute_pipeline", 
-            &compute_mouse_layout, 
-            &compute_shader,
-            "predict_position",
-        );
-
-        let calculate_density_pipeline = pipeline_manager.create_compute_pipeline(
-            "calculate_density_compute_pipeline", 
-            &compute_layout, 
-            &compute_shader,
-            "calculate_density",
-        );
-
-        let update_position_pipeline = pipeline_manager.create_compute_pipeline(
-            "update_position_compute_pipeline", 
-            &compute_layout, 
-            &compute_shader,
-            "update_position",
-        );
-
-        let update_spatial_hash_pipeline = pipeline_manager.create_compute_pipeline(
-            "spatial_hash_compute_pipeline", 
-            &compute_layout, 
-            &compute_shader,
-            "update_spatial_hash",
-        );
-
-        let sort_pipeline = pipeline_manager.create_compute_pipeline(
-            "sort_compute_pipeline", 
-            &gpu_sort_layout, 
-            &compute_shader,
-            "bitonic_sort_kernel",
-        );
-
-        let indecies_pipeline = pipeline_manager.create_compute_pipeline(
-            "indecies_compute_pipeline", 
-            &compute_layout, 
-            &compute_shader,
-            "calculate_start_indices",
-        );
-
-        let reset_indecies_pipeline = pipeline_manager.create_compute_pipeline(
-            "reset_indecies_compute_pipeline", 
-            &compute_layout, 
-            &compute_shader,
-            "reset_indecies",
-        );
-
-        let viscosity_pipeline = pipeline_manager.create_compute_pipeline(
-            "viscosity_compute_pipeline", 
-            &compute_layout, 
-            &compute_shader,
-            "calculate_viscosity",
-        );
 
         let pressure_visualizer = pressure_visualizer::PressureVisualizer::new(
             &device,
             &pipeline_manager,
             &camera_bind_group_layout,
-            &particale_bind_layout,
+            &simulation_compute.particle_bind_layout,
             &water_simulation,
-            &settings_bind_layout,
+            &simulation_compute.settings_bind_layout,
         );
 
 
@@ -783,6 +449,16 @@ impl <'a> State <'a> {
 
 
         let num_indices = INDICES.len() as u32;
+        let cpu_solver = CpuSolver::new(water_simulation.max_particles);
+
+        // the scene as loaded, so the series starts at t = 0
+        let exporter = exporter.and_then(|mut exporter| match exporter.write_frame(&water_simulation, 0, 0.0) {
+            Ok(()) => Some(exporter),
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            }
+        });
 
         Self {
             window,
@@ -815,39 +491,30 @@ impl <'a> State <'a> {
             proj_buffer,
             proj_view_inv_buffer,
             camera_bind_group,
-            camera_bind_group_inverse,
             proj_view_inv,
 
             camera_controller,
 
+            backend,
             water_simulation,
-            particle_buffer,
-            particle_bind_group,
+            cpu_solver,
+            stepper,
+            exporter,
+            simulated_steps: 0,
+            solver_stats: None,
+            simulation_compute,
             particle_pipeline,
             radius_bind_group,
+            particle_images_bind_group,
 
             bounding_box,
+            container_grab: None,
+            obstacle_outline,
+            body_outline,
 
             smoothing_pipeline,
             density_pipeline,
-            predict_position_pipeline,
-            calculate_density_pipeline,
-            update_position_pipeline,
-            update_spatial_hash_pipeline,
-            sort_pipeline,
-            indecies_pipeline,
-            reset_indecies_pipeline,
-            viscosity_pipeline,
-
-            settings_bind_group,
             pressure_visualizer,
-            delta_time_buffer,
-            cheat_depth_buffer,
-
-            pressed_buffer,
-            mouse_delta_buffer,
-
-            grid,
         }
     }
 
@@ -877,6 +544,36 @@ impl <'a> State <'a> {
         }
     }
 
+    pub fn save_snapshot(&mut self) {
+        let time = match self.backend {
+            Backend::Gpu => {
+                self.water_simulation.sync_from_gpu(&self.device, &self.queue);
+                futures::executor::block_on(self.simulation_compute.read_time_step(&self.device, &self.queue)).time
+            }
+            Backend::Cpu => self.cpu_solver.time_step().time,
+        };
+        match self.water_simulation.save_snapshot(Path::new(SNAPSHOT_PATH), time, self.bounding_box.position()) {
+            Ok(()) => log::info!("Saved snapshot to {}", SNAPSHOT_PATH),
+            Err(e) => log::error!("{}", e),
+        }
+    }
+
+    pub fn load_snapshot(&mut self) {
+        match self.water_simulation.load_snapshot(Path::new(SNAPSHOT_PATH), &self.queue) {
+            Ok(container) => {
+                let size = cgmath::vec2(self.water_simulation.bound_size[0], self.water_simulation.bound_size[1]);
+                self.bounding_box.set_size(size, &self.queue);
+                if let Some(container) = container {
+                    self.bounding_box.set_position(container.center.into(), cgmath::vec2(0.0, 0.0), &self.queue);
+                    self.cpu_solver.set_time(container.time);
+                    self.simulation_compute.write_time(&self.queue, container.time);
+                }
+                log::info!("Loaded snapshot from {}", SNAPSHOT_PATH);
+            }
+            Err(e) => log::error!("{}", e),
+        }
+    }
+
     pub fn input(&mut self, event: &WindowEvent) -> bool {
         self.camera_controller.process_events(event, self.size)
     }
@@ -2,6 +2,7 @@ use std::time::Instant;
 use log::info;
 use crate::state::State;
 use crate::state::render::Render;
+use crate::simulation::simulation::Backend;
 use cgmath::{Matrix, SquareMatrix, Vector4, Vector2};
 use crate::state::camera::camera::{inverse, CameraMatrix, ViewMatrix, MatrixUniform};
 
@@ -21,6 +22,7 @@ pub trait OtherLogic {
     fn update(&mut self);
     fn print_adapters(wgpu_instance: &wgpu::Instance);
     fn update_particle_vertex_data(&mut self);
+    fn export_frame(&mut self);
 }
 
 impl<'a> OtherLogic for State<'a>{
@@ -84,35 +86,92 @@ impl<'a> OtherLogic for State<'a>{
         self.proj_view_inv = proj_view_inv;
         
         self.queue.write_buffer(&self.proj_view_inv_buffer, 0, bytemuck::cast_slice(&[proj_view_inv]));
-        self.queue.write_buffer(&self.cheat_depth_buffer, 0, bytemuck::cast_slice(&[ndc.z]));        
+        self.queue.write_buffer(&self.simulation_compute.cheat_depth_buffer, 0, bytemuck::cast_slice(&[ndc.z]));        
 
         self.queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[self.view.view_matrix]));
         self.queue.write_buffer(&self.proj_buffer, 0, bytemuck::cast_slice(&[self.proj.camera_matrix]));
 
-        self.queue.write_buffer(&self.smoothing_pipeline.smoothing_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.smoothing_radius]));
-        self.queue.write_buffer(&self.density_pipeline.smoothing_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.smoothing_radius]));
+        self.queue.write_buffer(&self.smoothing_pipeline.smoothing_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.params.smoothing_radius]));
+        self.queue.write_buffer(&self.density_pipeline.smoothing_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.params.smoothing_radius]));
         
         self.queue.write_buffer(&self.water_simulation.num_particles_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.num_particles as u32]));
-        self.queue.write_buffer(&self.delta_time_buffer, 0, bytemuck::cast_slice(&[delta_time.as_secs_f32()]));        
+        self.queue.write_buffer(&self.water_simulation.params_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.params]));
+        self.queue.write_buffer(&self.water_simulation.time_step_params_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.time_step_params]));
+        self.queue.write_buffer(&self.water_simulation.solver_params_buffer, 0, bytemuck::cast_slice(&[self.water_simulation.solver_params]));
         
-        self.queue.write_buffer(&self.pressed_buffer, 0, bytemuck::cast_slice(&[self.camera_controller.is_mouse_pressed as u32]));
-        self.queue.write_buffer(&self.mouse_delta_buffer, 0, bytemuck::cast_slice(&[self.camera_controller.mouse_delta]));
+        self.queue.write_buffer(&self.simulation_compute.pressed_buffer, 0, bytemuck::cast_slice(&[self.camera_controller.is_mouse_pressed as u32]));
+        self.queue.write_buffer(&self.simulation_compute.mouse_delta_buffer, 0, bytemuck::cast_slice(&[self.camera_controller.mouse_delta]));
         
-        if !self.paused {
-            // self.water_simulation.update(delta_time);
-            //self.water_simulation.update_in_parrallel(delta_time);
-            // self.updateeparticle_vertex_data();
-            match self.compute() {
-                Ok(_) => {}
-                Err(e) => {
-                    info!("Error in compute: {:?}", e);
+        // dragging with the right button moves where the container rests, its velocity goes
+        // into the walls so a shake sloshes the fluid instead of just teleporting the box
+        if self.camera_controller.is_container_grabbed {
+            let grab = *self.container_grab.get_or_insert(self.bounding_box.position() - world_pos);
+            let position = world_pos + grab;
+            let velocity = (position - self.bounding_box.position()) / delta_time.as_secs_f32().max(1e-3);
+            self.bounding_box.set_position(position, velocity, &self.queue);
+        } else if self.container_grab.take().is_some() {
+            self.bounding_box.set_position(self.bounding_box.position(), Vector2::new(0.0, 0.0), &self.queue);
+        }
+
+        // a new smoothing radius or a loaded snapshot moves the boundary particles
+        if self.water_simulation.update_boundary() {
+            self.simulation_compute.grid.write_boundary(&self.queue, &self.water_simulation.boundary);
+        }
+        self.water_simulation.update_body_samples(&self.queue);
+
+        if self.paused {
+            self.stepper.reset();
+            return;
+        }
+
+        for _ in 0..self.stepper.advance(delta_time.as_secs_f32()) {
+            match self.backend {
+                Backend::Gpu => match self.compute() {
+                    Ok(_) => {}
+                    Err(e) => {
+                        info!("Error in compute: {:?}", e);
+                    }
+                },
+                Backend::Cpu => {
+                    let mouse = self.camera_controller.is_mouse_pressed.then_some(world_pos);
+                    self.cpu_solver.step(&mut self.water_simulation, self.bounding_box.origin(), mouse);
                 }
             }
+            self.simulated_steps += 1;
+            self.export_frame();
+        }
+        if self.backend == Backend::Cpu {
+            self.water_simulation.upload_particles(&self.queue);
+            let bounds = self.water_simulation.container_motion.apply(self.bounding_box.origin(), self.cpu_solver.time_step().time);
+            self.bounding_box.update(bounds, &self.queue);
+        }
+
+        self.solver_stats = self.water_simulation.solver.solves_to_tolerance().then(|| match self.backend {
+            Backend::Gpu => futures::executor::block_on(self.simulation_compute.read_solver_stats(&self.device, &self.queue)),
+            Backend::Cpu => self.cpu_solver.solver_stats(),
+        });
+    }
+
+    fn export_frame(&mut self) {
+        let Some(exporter) = self.exporter.as_mut() else { return };
+        if !exporter.is_due(self.simulated_steps) {
+            return;
+        }
+        let time = match self.backend {
+            Backend::Gpu => {
+                self.water_simulation.sync_from_gpu(&self.device, &self.queue);
+                futures::executor::block_on(self.simulation_compute.read_time_step(&self.device, &self.queue)).time
+            }
+            Backend::Cpu => self.cpu_solver.time_step().time,
+        };
+        if let Err(e) = exporter.write_frame(&self.water_simulation, self.simulated_steps, time) {
+            log::error!("{}", e);
+            self.exporter = None;
         }
     }
 
     fn update_particle_vertex_data(&mut self) {
-        self.queue.write_buffer(&self.particle_buffer, 0, bytemuck::cast_slice(&self.water_simulation.particles));
+        self.queue.write_buffer(&self.water_simulation.particle_buffer, 0, bytemuck::cast_slice(&self.water_simulation.particles));
     }
 
     fn print_adapters(wgpu_instance: &wgpu::Instance) {
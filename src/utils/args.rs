@@ -0,0 +1,94 @@
+use std::path::PathBuf;
+use crate::simulation::simulation::Backend;
+
+// command line options, parsed by hand to keep the dependency list short
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub backend: Backend,
+    pub headless: bool,
+    pub steps: u32,
+    pub software: bool,
+    pub scene: Option<PathBuf>,
+    pub load_snapshot: Option<PathBuf>,
+    pub save_snapshot: Option<PathBuf>,
+    pub export: Option<PathBuf>,
+    pub export_every: u32,
+    pub substeps: u32,
+    pub time_scale: f32,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Gpu,
+            headless: false,
+            steps: 600,
+            software: false,
+            scene: None,
+            load_snapshot: None,
+            save_snapshot: None,
+            export: None,
+            export_every: 1,
+            substeps: 2,
+            time_scale: 1.0,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--backend" => {
+                    let value = args.next().ok_or("--backend expects 'gpu' or 'cpu'")?;
+                    parsed.backend = value.parse()?;
+                }
+                "--cpu" => parsed.backend = Backend::Cpu,
+                "--headless" => parsed.headless = true,
+                "--steps" => {
+                    let value = args.next().ok_or("--steps expects a number")?;
+                    parsed.steps = value.parse().map_err(|_| format!("invalid step count '{}'", value))?;
+                }
+                "--software" => parsed.software = true,
+                "--scene" => {
+                    let value = args.next().ok_or("--scene expects a path to a .toml file")?;
+                    parsed.scene = Some(PathBuf::from(value));
+                }
+                "--load-snapshot" => {
+                    let value = args.next().ok_or("--load-snapshot expects a path")?;
+                    parsed.load_snapshot = Some(PathBuf::from(value));
+                }
+                "--save-snapshot" => {
+                    let value = args.next().ok_or("--save-snapshot expects a path")?;
+                    parsed.save_snapshot = Some(PathBuf::from(value));
+                }
+                "--export" => {
+                    let value = args.next().ok_or("--export expects a directory")?;
+                    parsed.export = Some(PathBuf::from(value));
+                }
+                "--export-every" => {
+                    let value = args.next().ok_or("--export-every expects a number")?;
+                    parsed.export_every = value.parse().map_err(|_| format!("invalid export interval '{}'", value))?;
+                }
+                "--substeps" => {
+                    let value = args.next().ok_or("--substeps expects a number")?;
+                    parsed.substeps = value.parse().map_err(|_| format!("invalid substep count '{}'", value))?;
+                }
+                "--time-scale" => {
+                    let value = args.next().ok_or("--time-scale expects a number")?;
+                    parsed.time_scale = value.parse().map_err(|_| format!("invalid time scale '{}'", value))?;
+                }
+                other => return Err(format!("unknown argument '{}'", other)),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
@@ -1,5 +1,6 @@
 use std::io::{Write, stdout};
 use crossterm::{QueueableCommand, cursor, terminal, ExecutableCommand};
+use crate::simulation::solver::SolverStats;
 
 pub struct ConsoleLogger {
     stdout: std::io::Stdout,
@@ -15,9 +16,12 @@ impl ConsoleLogger {
         }
     }
 
-    pub fn fps(&mut self, fps: f32) {
+    pub fn status(&mut self, fps: f32, solver_stats: Option<SolverStats>) {
         self.stdout.queue(terminal::Clear(terminal::ClearType::FromCursorDown)).unwrap();
         self.stdout.write_all(format!("FPS: {:.2}", fps).as_bytes()).unwrap();
+        if let Some(stats) = solver_stats {
+            self.stdout.write_all(format!(", {}", stats).as_bytes()).unwrap();
+        }
         self.stdout.queue(cursor::RestorePosition).unwrap();
         self.stdout.flush().unwrap();
     }
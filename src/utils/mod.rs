@@ -1,2 +1,4 @@
 pub mod fps;
-pub mod console_logger;
\ No newline at end of file
+pub mod console_logger;
+pub mod args;
+pub mod readback;
@@ -0,0 +1,47 @@
+use std::ops::Range;
+use bytemuck::Pod;
+use futures::channel::oneshot;
+
+// copies `range` (in elements of T) of a COPY_SRC buffer into a staging buffer and maps it back
+pub async fn read_buffer<T: Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    range: Range<usize>,
+) -> Vec<T> {
+    let element_size = std::mem::size_of::<T>() as wgpu::BufferAddress;
+    let offset = range.start as wgpu::BufferAddress * element_size;
+    let size = range.len() as wgpu::BufferAddress * element_size;
+    if size == 0 {
+        return Vec::new();
+    }
+
+    // Create a temporary buffer for reading
+    let temp_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, offset, &temp_buffer, 0, size);
+    queue.submit(Some(encoder.finish()));
+
+    let buffer_slice = temp_buffer.slice(..);
+    let (sender, receiver) = oneshot::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).unwrap();
+    });
+
+    device.poll(wgpu::Maintain::Wait);
+
+    receiver.await
+        .expect("map callback dropped")
+        .expect("buffer mapping");
+    let data = bytemuck::cast_slice::<u8, T>(&buffer_slice.get_mapped_range()).to_vec();
+    temp_buffer.unmap();
+    data
+}